@@ -0,0 +1,24 @@
+-- The repositories store a generated filename alongside the original one, and
+-- dimensions are not known for every upload, so rebuild images to match.
+CREATE TABLE images_new (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    original_filename TEXT NOT NULL,
+    storage_path TEXT NOT NULL,
+    file_size INTEGER NOT NULL,
+    mime_type TEXT NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO images_new (id, user_id, filename, original_filename, storage_path, file_size, mime_type, width, height, created_at)
+SELECT id, user_id, original_filename, original_filename, storage_path, file_size, mime_type, width, height, created_at
+FROM images;
+
+DROP TABLE images;
+ALTER TABLE images_new RENAME TO images;
+
+CREATE INDEX idx_images_user_id ON images(user_id);
+CREATE INDEX idx_images_created_at ON images(created_at);
@@ -0,0 +1,10 @@
+-- storage_path held a filesystem path; images are now addressed by a backend-agnostic key
+ALTER TABLE images RENAME COLUMN storage_path TO storage_key;
+
+-- The key is the stored file's name, the last component of the old path. 002 filled filename
+-- from original_filename, so it gets the stored name too. rtrim strips everything after the
+-- last '/', leaving the length of the directory part.
+UPDATE images
+SET storage_key = substr(storage_key, length(rtrim(storage_key, replace(storage_key, '/', ''))) + 1);
+
+UPDATE images SET filename = storage_key WHERE filename <> storage_key;
@@ -0,0 +1,3 @@
+-- Colour information detected from the decoded image header
+ALTER TABLE images ADD COLUMN color_type TEXT;
+ALTER TABLE images ADD COLUMN bit_depth INTEGER;
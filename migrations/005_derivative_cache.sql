@@ -0,0 +1,16 @@
+-- Encoded transformation results, keyed by a hash of the source image and canonical transformation
+CREATE TABLE IF NOT EXISTS derivative_cache (
+    cache_key TEXT PRIMARY KEY,
+    image_id TEXT NOT NULL,
+    -- Other images the result depends on (watermark overlays), as ",id1,id2,"
+    dependencies TEXT NOT NULL DEFAULT ',',
+    storage_key TEXT NOT NULL,
+    format TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    -- Unix time in milliseconds, used for LRU eviction
+    last_accessed_at INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_derivative_cache_image_id ON derivative_cache(image_id);
+CREATE INDEX IF NOT EXISTS idx_derivative_cache_last_accessed ON derivative_cache(last_accessed_at);
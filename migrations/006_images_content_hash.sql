@@ -0,0 +1,2 @@
+-- SHA-256 of the stored bytes, used as the ETag; rows uploaded earlier are filled in on first access
+ALTER TABLE images ADD COLUMN content_hash TEXT;
@@ -0,0 +1,24 @@
+-- Asynchronous transformation jobs, picked up by the background workers
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    image_id TEXT NOT NULL,
+    -- Serialized ImageTransformation
+    transformation TEXT NOT NULL,
+    -- queued | running | succeeded | failed
+    status TEXT NOT NULL DEFAULT 'queued',
+    progress INTEGER NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    error TEXT,
+    result_key TEXT,
+    result_format TEXT,
+    -- Unix time in milliseconds before which the job must not run (retry backoff)
+    available_at INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    completed_at DATETIME
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_status_available ON jobs(status, available_at);
+CREATE INDEX IF NOT EXISTS idx_jobs_user_id ON jobs(user_id);
@@ -0,0 +1,7 @@
+-- Images saved from a transformation record the image they were derived from and the
+-- serialized ImageTransformation that produced them. No foreign key: what happens to
+-- derivatives when their parent is deleted is decided by PARENT_DELETE_POLICY.
+ALTER TABLE images ADD COLUMN parent_id TEXT;
+ALTER TABLE images ADD COLUMN transformation TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_images_parent_id ON images(parent_id);
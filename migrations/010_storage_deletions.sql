@@ -0,0 +1,7 @@
+-- Stored objects whose database rows are already gone. Keys are queued in the same transaction
+-- that deletes the rows and removed once the object is deleted, so a crash in between leaves
+-- at worst an object to clean up on the next start, never a row pointing at a missing file.
+CREATE TABLE IF NOT EXISTS storage_deletions (
+    storage_key TEXT PRIMARY KEY,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,4 @@
+-- Soft delete: images in the trash have deleted_at set and are purged after the retention period
+ALTER TABLE images ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS idx_images_deleted_at ON images(deleted_at);
@@ -0,0 +1,8 @@
+-- Images trashed by one delete share a batch id, so restoring the image brings back exactly the
+-- derived images its delete cascaded to. Images already in the trash were grouped by their
+-- deletion time, which stands in for the batch.
+ALTER TABLE images ADD COLUMN trash_batch_id TEXT;
+
+UPDATE images SET trash_batch_id = deleted_at WHERE deleted_at IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_images_trash_batch_id ON images(trash_batch_id);
@@ -0,0 +1,88 @@
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::Response;
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+// Cache-Control value shared by image and transformation responses, injected as an extension
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    pub cache_control: Option<HeaderValue>,
+}
+
+impl CachePolicy {
+    pub fn new(cache_control: &str) -> Self {
+        Self {
+            cache_control: HeaderValue::from_str(cache_control).ok().filter(|_| !cache_control.is_empty()),
+        }
+    }
+}
+
+// Validators for one representation: a strong ETag and, when known, a modification time
+#[derive(Debug, Clone)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl Validators {
+    // `created_at` is SQLite's CURRENT_TIMESTAMP format, always UTC
+    pub fn new(hash: &str, created_at: Option<&str>) -> Self {
+        Self {
+            etag: format!("\"{}\"", hash),
+            last_modified: created_at
+                .and_then(|value| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").ok())
+                .map(|naive| naive.and_utc()),
+        }
+    }
+
+    // If-None-Match wins over If-Modified-Since, as RFC 9110 requires
+    pub fn is_not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+            return if_none_match.split(',').map(str::trim).any(|tag| {
+                tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag
+            });
+        }
+
+        let if_modified_since = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok());
+        match (if_modified_since, self.last_modified) {
+            (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+            _ => false,
+        }
+    }
+
+    // If-Range holds either an ETag (strong comparison) or an HTTP date (exact match)
+    pub fn matches_if_range(&self, if_range: &str) -> bool {
+        if if_range.starts_with('"') || if_range.starts_with("W/") {
+            return if_range == self.etag;
+        }
+        match (DateTime::parse_from_rfc2822(if_range), self.last_modified) {
+            (Ok(date), Some(modified)) => date.timestamp() == modified.timestamp(),
+            _ => false,
+        }
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap, policy: &CachePolicy) {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Some(modified) = self.last_modified {
+            let date = modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            if let Ok(value) = HeaderValue::from_str(&date) {
+                headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+        if let Some(cache_control) = &policy.cache_control {
+            headers.insert(header::CACHE_CONTROL, cache_control.clone());
+        }
+    }
+
+    pub fn not_modified(&self, policy: &CachePolicy) -> Response {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        self.apply(response.headers_mut(), policy);
+        response
+    }
+}
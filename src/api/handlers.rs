@@ -1,15 +1,32 @@
 use axum::{
     extract::{State, Multipart, Path, Query},
-    response::Json,
-    http::StatusCode,
+    response::{IntoResponse, Json},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    Extension,
 };
+use bytes::Bytes;
+use futures_util::{future, stream, StreamExt};
 use serde::Deserialize;
+use std::sync::Arc;
 use crate::application::user_service::UserService;
-use crate::application::image_service::ImageService;
+use crate::application::derivative_cache::CacheStatus;
+use crate::application::image_service::{BatchItem, ImageService, ProcessedImage, TransformPlan};
+use crate::application::zip_stream::ZipStreamWriter;
+use crate::application::processing_pool::ProcessingMetrics;
+use crate::application::job_service::JobService;
+use crate::application::webhook_service::WebhookService;
 use crate::domain::user_repository::UserRepository;
-use crate::domain::image::{ImageRepository, ImageTransformation};
+use crate::domain::image::{Image, ImageFilter, ImageRepository, ImageTransformation};
+use crate::domain::filename::derived_filename;
+use crate::domain::job::Job;
+use crate::domain::webhook::{Webhook, WebhookDelivery};
 use crate::core::error::ServiceError;
 use crate::core::jwt::JwtService;
+use crate::core::signed_url::{self, RenderUrlSigner};
+use crate::api::middleware::{self, AuthUser};
+use crate::api::conditional::{CachePolicy, Validators};
+use crate::api::range::RangeRequest;
+use crate::api::negotiation::accepted_formats;
 
 // DTOs برای درخواست‌ها
 #[derive(Deserialize)]
@@ -31,6 +48,55 @@ pub struct PaginationParams {
 }
 
 // پاسخ‌ها
+#[derive(Deserialize)]
+pub struct TransformRequest {
+    // Store the result as a new image derived from the source
+    #[serde(default)]
+    pub save: bool,
+    #[serde(flatten)]
+    pub transformations: ImageTransformation,
+}
+
+#[derive(Deserialize, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchOutput {
+    #[default]
+    Manifest,
+    Zip,
+}
+
+#[derive(Deserialize)]
+pub struct BatchTransformRequest {
+    pub image_ids: Option<Vec<String>>,
+    pub filter: Option<ImageFilter>,
+    pub transformations: ImageTransformation,
+    #[serde(default)]
+    pub output: BatchOutput,
+    // Store every result as a new image derived from its source
+    #[serde(default)]
+    pub save: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RenderUrlRequest {
+    // Render query parameters, e.g. "w=200&fmt=webp"
+    #[serde(default)]
+    pub query: String,
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterWebhookRequest {
+    pub url: String,
+    pub secret: Option<String>,
+    pub events: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeliveryLogParams {
+    pub limit: Option<i64>,
+}
+
 #[derive(serde::Serialize)]
 pub struct AuthResponse {
     pub user: UserResponse,
@@ -51,7 +117,211 @@ pub struct ImageResponse {
     pub original_filename: String,
     pub file_size: i64,
     pub mime_type: String,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub color_type: Option<String>,
+    pub bit_depth: Option<i64>,
+    pub parent_id: Option<String>,
+    // The transformation that produced this image from its parent
+    pub transformation: Option<serde_json::Value>,
     pub created_at: Option<String>,
+    // Only set for images in the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+}
+
+impl From<Image> for ImageResponse {
+    fn from(image: Image) -> Self {
+        ImageResponse {
+            transformation: image.transformation.and_then(|json| serde_json::from_str(&json).ok()),
+            parent_id: image.parent_id,
+            id: image.id,
+            filename: image.filename,
+            original_filename: image.original_filename,
+            file_size: image.file_size,
+            mime_type: image.mime_type,
+            width: image.width,
+            height: image.height,
+            color_type: image.color_type,
+            bit_depth: image.bit_depth,
+            created_at: image.created_at,
+            deleted_at: image.deleted_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RenderUrlResponse {
+    pub url: String,
+    pub expires_at: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmptyTrashResponse {
+    pub purged: usize,
+}
+
+#[derive(serde::Serialize)]
+pub struct JobResponse {
+    pub id: String,
+    pub image_id: String,
+    pub status: String,
+    pub progress: i64,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub error: Option<String>,
+    pub result_url: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+impl From<Job> for JobResponse {
+    fn from(job: Job) -> Self {
+        JobResponse {
+            result_url: job.result_key.as_ref().map(|_| format!("/api/jobs/{}/result", job.id)),
+            id: job.id,
+            image_id: job.image_id,
+            status: job.status,
+            progress: job.progress,
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            error: job.error,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+            completed_at: job.completed_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct BatchItemResponse {
+    #[serde(skip)]
+    pub index: usize,
+    pub image_id: String,
+    pub status: &'static str,
+    // Derivative cache key of the result
+    pub derivative_id: Option<String>,
+    pub format: Option<&'static str>,
+    pub size: Option<usize>,
+    pub cache: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saved_image_id: Option<String>,
+    // Entry name inside the ZIP archive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub error: Option<String>,
+}
+
+impl BatchItemResponse {
+    fn new(item: &BatchItem, file: Option<String>) -> Self {
+        match &item.outcome {
+            Ok(result) => BatchItemResponse {
+                index: item.index,
+                image_id: item.image_id.clone(),
+                status: "succeeded",
+                derivative_id: Some(result.plan.cache_key.clone()),
+                format: Some(result.processed.format.name()),
+                size: Some(result.processed.data.len()),
+                cache: Some(result.cache.as_str()),
+                saved_image_id: result.saved.as_ref().map(|image| image.id.clone()),
+                file,
+                error: None,
+            },
+            Err(e) => BatchItemResponse {
+                index: item.index,
+                image_id: item.image_id.clone(),
+                status: "failed",
+                derivative_id: None,
+                format: None,
+                size: None,
+                cache: None,
+                saved_image_id: None,
+                file: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct BatchManifest {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<BatchItemResponse>,
+}
+
+impl BatchManifest {
+    fn new(mut items: Vec<BatchItemResponse>) -> Self {
+        items.sort_by_key(|item| item.index);
+        let succeeded = items.iter().filter(|item| item.error.is_none()).count();
+        BatchManifest { total: items.len(), succeeded, failed: items.len() - succeeded, items }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct WebhookResponse {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    // Only returned when the webhook is registered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: Option<String>,
+}
+
+impl From<Webhook> for WebhookResponse {
+    fn from(webhook: Webhook) -> Self {
+        WebhookResponse {
+            events: webhook.event_names(),
+            id: webhook.id,
+            url: webhook.url,
+            secret: None,
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct WebhookDeliveryResponse {
+    pub id: String,
+    pub event_id: String,
+    pub event: String,
+    pub status: String,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<String>,
+    pub payload: serde_json::Value,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub delivered_at: Option<String>,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        let pending = delivery.status == "pending";
+        WebhookDeliveryResponse {
+            payload: serde_json::from_str(&delivery.payload).unwrap_or(serde_json::Value::String(delivery.payload)),
+            next_attempt_at: pending
+                .then(|| chrono::DateTime::from_timestamp_millis(delivery.next_attempt_at))
+                .flatten()
+                .map(|at| at.format("%Y-%m-%d %H:%M:%S").to_string()),
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event: delivery.event,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            max_attempts: delivery.max_attempts,
+            response_status: delivery.response_status,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+            updated_at: delivery.updated_at,
+            delivered_at: delivery.delivered_at,
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -108,10 +378,12 @@ pub async fn login<UR: UserRepository>(
 // Simple test handlers
 pub async fn upload_image_simple<IR: ImageRepository>(
     State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
     mut multipart: Multipart,
 ) -> Result<Json<ImageResponse>, ServiceError> {
     let mut image_data = Vec::new();
     let mut filename = None;
+    let mut auto_orient = true;
 
     while let Some(field) = multipart.next_field().await.map_err(|e| {
         ServiceError::ValidationError(format!("Multipart error: {}", e))
@@ -121,9 +393,18 @@ pub async fn upload_image_simple<IR: ImageRepository>(
         if field_name == "image" {
             filename = field.file_name().map(|f| f.to_string());
             let data = field.bytes().await.map_err(|e| {
-                ServiceError::ValidationError(format!("Failed to read image data: {}", e))
+                if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+                    ServiceError::PayloadTooLarge("uploaded file exceeds the maximum upload size".to_string())
+                } else {
+                    ServiceError::ValidationError(format!("Failed to read image data: {}", e))
+                }
             })?;
             image_data = data.to_vec();
+        } else if field_name == "auto_orient" {
+            let value = field.text().await.map_err(|e| {
+                ServiceError::ValidationError(format!("Failed to read auto_orient: {}", e))
+            })?;
+            auto_orient = parse_bool_field("auto_orient", &value)?;
         }
     }
 
@@ -133,70 +414,303 @@ pub async fn upload_image_simple<IR: ImageRepository>(
 
     let filename = filename.unwrap_or_else(|| "unknown.jpg".to_string());
     
-    let user_id = "ad808fc5-a806-481a-ac15-3ea8fbdc66da";
-    
-    let image = image_service.upload_image(user_id, &filename, &image_data).await?;
-
-    let response = ImageResponse {
-        id: image.id,
-        filename: image.filename,
-        original_filename: image.original_filename,
-        file_size: image.file_size,
-        mime_type: "image/jpeg".to_string(),
-        created_at: image.created_at,
-    };
+    let image = image_service.upload_image(&auth_user.user_id, &filename, &image_data, auto_orient).await?;
+
+    Ok(Json(ImageResponse::from(image)))
+}
 
-    Ok(Json(response))
+fn parse_bool_field(name: &str, value: &str) -> Result<bool, ServiceError> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        other => Err(ServiceError::ValidationError(format!("Invalid {} value '{}'", name, other))),
+    }
 }
 
 pub async fn transform_image_simple<IR: ImageRepository>(
     State(image_service): State<ImageService<IR>>,
+    Extension(policy): Extension<CachePolicy>,
+    auth_user: AuthUser,
     Path(image_id): Path<String>,
-    Json(transformations): Json<ImageTransformation>,
+    headers: HeaderMap,
+    Json(payload): Json<TransformRequest>,
 ) -> Result<axum::response::Response, ServiceError> {
-    let user_id = "ad808fc5-a806-481a-ac15-3ea8fbdc66da";
-    let processed_image = image_service.transform_image(&image_id, user_id, transformations).await?;
+    let plan = image_service.plan_transform(&image_id, &auth_user.user_id, payload.transformations, &accepted_formats(&headers)).await?;
     
-    Ok(axum::response::Response::builder()
-        .status(StatusCode::OK)
-        .header("content-type", "image/jpeg")
-        .body(axum::body::Body::from(processed_image))
-        .unwrap())
+    let (processed, cache) = image_service.transform(&plan).await?;
+    transform_response(&image_service, plan, processed, cache, &policy, payload.save).await
+}
+
+// GET variant of transform driven by query parameters, usable from <img src> and CDNs
+// Authorized either by a Bearer token or by a signed URL from POST /images/:id/render-url,
+// which lets <img> tags and CDNs fetch renders without an authorization header
+pub async fn render_image<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    Extension(policy): Extension<CachePolicy>,
+    Extension(jwt_service): Extension<JwtService>,
+    Extension(signer): Extension<RenderUrlSigner>,
+    Path(image_id): Path<String>,
+    headers: HeaderMap,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<axum::response::Response, ServiceError> {
+    let (params, signature) = signed_url::split_signature(params)?;
+    let user_id = match signature {
+        Some(signature) => {
+            signer.verify(&image_id, &params, &signature)?;
+            image_service.image_owner(&image_id).await?
+        }
+        None => middleware::authenticate(&jwt_service, &headers)?.sub,
+    };
+
+    let transformations = ImageTransformation::from_query(&params)?;
+    let plan = image_service.plan_transform(&image_id, &user_id, transformations, &accepted_formats(&headers)).await?;
+
+    // Conditional requests are answered from the ETag alone, without touching the pipeline
+    let validators = Validators::new(&plan.etag, plan.image.created_at.as_deref());
+    if validators.is_not_modified(&headers) {
+        let mut response = validators.not_modified(&policy);
+        if plan.negotiated {
+            response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+        }
+        return Ok(response);
+    }
+
+    let (processed, cache) = image_service.execute_transform(&plan).await?;
+    transform_response(&image_service, plan, processed, cache, &policy, false).await
+}
+
+const DEFAULT_RENDER_URL_TTL_SECS: u64 = 3600;
+
+// Issues a render URL usable without a Bearer token until it expires
+pub async fn create_render_url<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    Extension(signer): Extension<RenderUrlSigner>,
+    auth_user: AuthUser,
+    Path(image_id): Path<String>,
+    Json(request): Json<RenderUrlRequest>,
+) -> Result<Json<RenderUrlResponse>, ServiceError> {
+    let expires_in = request.expires_in.unwrap_or(DEFAULT_RENDER_URL_TTL_SECS.min(signer.max_ttl_secs()));
+    if expires_in == 0 || expires_in > signer.max_ttl_secs() {
+        return Err(ServiceError::ValidationError(format!(
+            "expires_in must be between 1 and {} seconds",
+            signer.max_ttl_secs()
+        )));
+    }
+
+    let uri = format!("/?{}", request.query.trim_start_matches('?'))
+        .parse()
+        .map_err(|_| ServiceError::ValidationError("query is not a valid query string".to_string()))?;
+    let Query(params) = Query::<Vec<(String, String)>>::try_from_uri(&uri)
+        .map_err(|e| ServiceError::ValidationError(format!("query is not a valid query string: {}", e)))?;
+    ImageTransformation::from_query(&params)?.validate()?;
+    let image = image_service.find_owned_image(&image_id, &auth_user.user_id).await?;
+
+    let expires_at = chrono::Utc::now().timestamp() + expires_in as i64;
+    let signature = signer.sign(&image.id, &params, expires_at);
+    let mut query = params;
+    query.push((signed_url::EXPIRES_PARAM.to_string(), signature.expires.to_string()));
+    query.push((signed_url::SIGNATURE_PARAM.to_string(), signature.signature));
+
+    Ok(Json(RenderUrlResponse {
+        url: format!("/api/images/{}/render?{}", image.id, signed_url::encode_query(&query)),
+        expires_at,
+    }))
+}
+
+// Responds with a rendered plan; when saving, the response is 201 with the new image in Location.
+// Conditional headers are only evaluated by the GET route: for a POST a matching
+// If-None-Match would have to be 412 (RFC 9110 §13.1.2), and a transform is safe to repeat.
+async fn transform_response<IR: ImageRepository>(
+    image_service: &ImageService<IR>,
+    plan: TransformPlan,
+    processed: ProcessedImage,
+    cache: CacheStatus,
+    policy: &CachePolicy,
+    save: bool,
+) -> Result<axum::response::Response, ServiceError> {
+    let validators = Validators::new(&plan.etag, plan.image.created_at.as_deref());
+    let saved = match save {
+        true => Some(image_service.save_derivative(&plan, &processed).await?),
+        false => None,
+    };
+    let mut response = axum::response::Response::builder()
+        .status(if saved.is_some() { StatusCode::CREATED } else { StatusCode::OK })
+        .header("x-cache", cache.as_str())
+        .header("content-type", processed.format.mime_type())
+        .header("content-disposition", format!("inline; filename=\"{}\"", derived_filename(&plan.image.original_filename, processed.format.extension())))
+        .body(axum::body::Body::from(processed.data))
+        .unwrap();
+    validators.apply(response.headers_mut(), policy);
+    if plan.negotiated {
+        response.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+    }
+    if let Some(saved) = saved {
+        let location = HeaderValue::from_str(&format!("/api/images/{}", saved.id)).unwrap();
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    Ok(response)
 }
 
 pub async fn get_image_simple<IR: ImageRepository>(
     State(image_service): State<ImageService<IR>>,
+    Extension(policy): Extension<CachePolicy>,
+    auth_user: AuthUser,
     Path(image_id): Path<String>,
+    headers: HeaderMap,
 ) -> Result<axum::response::Response, ServiceError> {
-    let user_id = "ad808fc5-a806-481a-ac15-3ea8fbdc66da";
-    let (image, image_data) = image_service.get_image(&image_id, user_id).await?;
-    
-    Ok(axum::response::Response::builder()
-        .status(StatusCode::OK)
-        .header("content-type", &image.mime_type)
-        .header("content-disposition", format!("inline; filename=\"{}\"", image.original_filename))
-        .body(axum::body::Body::from(image_data))
-        .unwrap())
+    let mut image = image_service.find_owned_image(&image_id, &auth_user.user_id).await?;
+    image_service.ensure_content_hash(&mut image).await?;
+
+    let validators = Validators::new(image.content_hash.as_deref().unwrap_or_default(), image.created_at.as_deref());
+    if validators.is_not_modified(&headers) {
+        return Ok(validators.not_modified(&policy));
+    }
+
+    let len = image.file_size as u64;
+    let builder = axum::response::Response::builder()
+        .header("accept-ranges", "bytes")
+        .header("content-disposition", format!("inline; filename=\"{}\"", image.original_filename));
+
+    let mut response = match RangeRequest::from_headers(&headers, len, &validators) {
+        RangeRequest::Full => {
+            let stream = image_service.stream_image(&image, None).await?;
+            builder
+                .status(StatusCode::OK)
+                .header("content-type", &image.mime_type)
+                .header("content-length", len)
+                .body(axum::body::Body::from_stream(stream))
+                .unwrap()
+        }
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            let stream = image_service.stream_image(&image, Some(range.clone())).await?;
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header("content-type", &image.mime_type)
+                .header("content-range", format!("bytes {}-{}/{}", range.start, range.end - 1, len))
+                .header("content-length", range.end - range.start)
+                .body(axum::body::Body::from_stream(stream))
+                .unwrap()
+        }
+        RangeRequest::Partial(ranges) => {
+            // multipart/byteranges: each part carries its own Content-Type and Content-Range
+            let boundary = uuid::Uuid::new_v4().simple().to_string();
+            let mut parts = Vec::new();
+            let mut content_length = 0;
+            for range in ranges {
+                let part_header = format!(
+                    "\r\n--{}\r\ncontent-type: {}\r\ncontent-range: bytes {}-{}/{}\r\n\r\n",
+                    boundary, image.mime_type, range.start, range.end - 1, len
+                );
+                content_length += part_header.len() as u64 + (range.end - range.start);
+                parts.push(stream::once(future::ready(Ok(Bytes::from(part_header)))).boxed());
+                parts.push(image_service.stream_image(&image, Some(range)).await?);
+            }
+            let closing = format!("\r\n--{}--\r\n", boundary);
+            content_length += closing.len() as u64;
+            parts.push(stream::once(future::ready(Ok(Bytes::from(closing)))).boxed());
+
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header("content-type", format!("multipart/byteranges; boundary={}", boundary))
+                .header("content-length", content_length)
+                .body(axum::body::Body::from_stream(stream::iter(parts).flatten()))
+                .unwrap()
+        }
+        RangeRequest::Unsatisfiable => {
+            return Ok(axum::response::Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("accept-ranges", "bytes")
+                .header("content-range", format!("bytes */{}", len))
+                .body(axum::body::Body::empty())
+                .unwrap());
+        }
+    };
+    validators.apply(response.headers_mut(), &policy);
+    Ok(response)
+}
+
+// 404 when the image does not exist, 403 when it belongs to someone else
+pub async fn delete_image<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
+    Path(image_id): Path<String>,
+) -> Result<StatusCode, ServiceError> {
+    image_service.delete_image(&image_id, &auth_user.user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_trash<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
+    Query(params): Query<PaginationParams>,
+) -> Result<Json<ImageListResponse>, ServiceError> {
+    let page = params.page.unwrap_or(1);
+    let limit = params.limit.unwrap_or(10).min(100);
+
+    let images = image_service.list_trash(&auth_user.user_id, page, limit).await?;
+    let image_responses: Vec<ImageResponse> = images.into_iter().map(ImageResponse::from).collect();
+    let total = image_responses.len();
+
+    Ok(Json(ImageListResponse {
+        images: image_responses,
+        total,
+        page,
+        limit,
+    }))
+}
+
+pub async fn restore_image<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
+    Path(image_id): Path<String>,
+) -> Result<Json<ImageResponse>, ServiceError> {
+    let image = image_service.restore_image(&image_id, &auth_user.user_id).await?;
+
+    Ok(Json(ImageResponse::from(image)))
+}
+
+pub async fn empty_trash<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
+) -> Result<Json<EmptyTrashResponse>, ServiceError> {
+    let purged = image_service.empty_trash(&auth_user.user_id).await?;
+
+    Ok(Json(EmptyTrashResponse { purged }))
+}
+
+pub async fn list_derivatives<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
+    Path(image_id): Path<String>,
+) -> Result<Json<Vec<ImageResponse>>, ServiceError> {
+    let images = image_service.derivatives(&image_id, &auth_user.user_id).await?;
+
+    Ok(Json(images.into_iter().map(ImageResponse::from).collect()))
+}
+
+pub async fn get_lineage<IR: ImageRepository>(
+    State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
+    Path(image_id): Path<String>,
+) -> Result<Json<Vec<ImageResponse>>, ServiceError> {
+    let images = image_service.lineage(&image_id, &auth_user.user_id).await?;
+
+    Ok(Json(images.into_iter().map(ImageResponse::from).collect()))
 }
 
 pub async fn list_images_simple<IR: ImageRepository>(
     State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
     Query(params): Query<PaginationParams>,
 ) -> Result<Json<ImageListResponse>, ServiceError> {
     let page = params.page.unwrap_or(1);
     let limit = params.limit.unwrap_or(10).min(100);
     
-    let user_id = "ad808fc5-a806-481a-ac15-3ea8fbdc66da";
-    let images = image_service.list_images(user_id, page, limit).await?;
+    let images = image_service.list_images(&auth_user.user_id, page, limit).await?;
     
-    let image_responses: Vec<ImageResponse> = images.into_iter().map(|image| ImageResponse {
-        id: image.id,
-        filename: image.filename,
-        original_filename: image.original_filename,
-        file_size: image.file_size,
-        mime_type: image.mime_type,
-        created_at: image.created_at,
-    }).collect();
+    let image_responses: Vec<ImageResponse> = images.into_iter().map(ImageResponse::from).collect();
     
     let total = image_responses.len();
     
@@ -208,28 +722,149 @@ pub async fn list_images_simple<IR: ImageRepository>(
     }))
 }
 
-// Main handlers (for future use)
-#[allow(dead_code)]
-pub async fn upload_image() -> &'static str {
-    "Upload image endpoint (with auth) - Coming soon"
+// Applies one pipeline to many images. Responds with a JSON manifest, or with a ZIP of the
+// results streamed as they complete, ending with the manifest as manifest.json.
+pub async fn batch_transform<IR: ImageRepository + Clone + Send + Sync + 'static>(
+    State(image_service): State<ImageService<IR>>,
+    auth_user: AuthUser,
+    Json(payload): Json<BatchTransformRequest>,
+) -> Result<axum::response::Response, ServiceError> {
+    let image_ids = image_service
+        .plan_batch(&auth_user.user_id, payload.image_ids, payload.filter, &payload.transformations)
+        .await?;
+    let items = image_service.transform_batch(&auth_user.user_id, image_ids, payload.transformations, payload.save);
+
+    if payload.output == BatchOutput::Manifest {
+        let responses = items.map(|item| BatchItemResponse::new(&item, None)).collect::<Vec<_>>().await;
+        return Ok(Json(BatchManifest::new(responses)).into_response());
+    }
+
+    let archive = stream::unfold(Some((items, ZipStreamWriter::new(), Vec::new())), |state| async move {
+        let (mut items, mut zip, mut responses) = state?;
+        let chunk = match items.next().await {
+            Some(item) => {
+                let chunk = match &item.outcome {
+                    Ok(result) => {
+                        let name = format!("{}.{}", item.image_id, result.processed.format.extension());
+                        let chunk = zip.add_file(&name, &result.processed.data);
+                        responses.push(BatchItemResponse::new(&item, Some(name)));
+                        chunk
+                    }
+                    Err(_) => {
+                        responses.push(BatchItemResponse::new(&item, None));
+                        Ok(Vec::new())
+                    }
+                };
+                // A write error ends the archive; the client sees a truncated download
+                let next = chunk.is_ok().then_some((items, zip, responses));
+                return Some((chunk.map(Bytes::from), next));
+            }
+            None => serde_json::to_vec_pretty(&BatchManifest::new(responses))
+                .map_err(|e| ServiceError::ImageProcessingError(format!("Failed to write manifest: {}", e)))
+                .and_then(|manifest| zip.add_file("manifest.json", &manifest))
+                .and_then(|mut chunk| {
+                    chunk.extend(zip.finish()?);
+                    Ok(chunk)
+                }),
+        };
+        Some((chunk.map(Bytes::from), None))
+    });
+
+    Ok(axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/zip")
+        .header("content-disposition", "attachment; filename=\"batch.zip\"")
+        .body(axum::body::Body::from_stream(archive))
+        .unwrap())
+}
+
+// Accepts the same body as /transform and returns immediately; poll the job for the result
+pub async fn create_job<IR: ImageRepository + Clone + Send + Sync + 'static>(
+    State(job_service): State<JobService<IR>>,
+    auth_user: AuthUser,
+    Path(image_id): Path<String>,
+    Json(transformations): Json<ImageTransformation>,
+) -> Result<(StatusCode, [(header::HeaderName, String); 1], Json<JobResponse>), ServiceError> {
+    let job = job_service.enqueue(&image_id, &auth_user.user_id, transformations).await?;
+    let location = format!("/api/jobs/{}", job.id);
+
+    Ok((StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(job.into())))
+}
+
+pub async fn get_job<IR: ImageRepository + Clone + Send + Sync + 'static>(
+    State(job_service): State<JobService<IR>>,
+    auth_user: AuthUser,
+    Path(job_id): Path<String>,
+) -> Result<Json<JobResponse>, ServiceError> {
+    let job = job_service.get_job(&job_id, &auth_user.user_id).await?;
+
+    Ok(Json(job.into()))
+}
+
+pub async fn get_job_result<IR: ImageRepository + Clone + Send + Sync + 'static>(
+    State(job_service): State<JobService<IR>>,
+    auth_user: AuthUser,
+    Path(job_id): Path<String>,
+) -> Result<axum::response::Response, ServiceError> {
+    let (job, format, stream) = job_service.job_result(&job_id, &auth_user.user_id).await?;
+
+    Ok(axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", format.mime_type())
+        .header("content-disposition", format!("attachment; filename=\"{}.{}\"", job.id, format.extension()))
+        .body(axum::body::Body::from_stream(stream))
+        .unwrap())
+}
+
+pub async fn register_webhook(
+    State(webhook_service): State<WebhookService>,
+    auth_user: AuthUser,
+    Json(payload): Json<RegisterWebhookRequest>,
+) -> Result<(StatusCode, Json<WebhookResponse>), ServiceError> {
+    let webhook = webhook_service
+        .register(&auth_user.user_id, &payload.url, payload.secret, &payload.events)
+        .await?;
+    let secret = webhook.secret.clone();
+
+    let mut response = WebhookResponse::from(webhook);
+    response.secret = Some(secret);
+    Ok((StatusCode::CREATED, Json(response)))
 }
 
-#[allow(dead_code)]
-pub async fn transform_image() -> &'static str {
-    "Transform image endpoint (with auth) - Coming soon"
+pub async fn list_webhooks(
+    State(webhook_service): State<WebhookService>,
+    auth_user: AuthUser,
+) -> Result<Json<Vec<WebhookResponse>>, ServiceError> {
+    let webhooks = webhook_service.list(&auth_user.user_id).await?;
+
+    Ok(Json(webhooks.into_iter().map(WebhookResponse::from).collect()))
 }
 
-#[allow(dead_code)]
-pub async fn get_image() -> &'static str {
-    "Get image endpoint (with auth) - Coming soon"
+pub async fn delete_webhook(
+    State(webhook_service): State<WebhookService>,
+    auth_user: AuthUser,
+    Path(webhook_id): Path<String>,
+) -> Result<StatusCode, ServiceError> {
+    webhook_service.delete(&webhook_id, &auth_user.user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
-#[allow(dead_code)]
-pub async fn list_images() -> &'static str {
-    "List images endpoint (with auth) - Coming soon"
+// Delivery log, most recent first
+pub async fn list_webhook_deliveries(
+    State(webhook_service): State<WebhookService>,
+    auth_user: AuthUser,
+    Path(webhook_id): Path<String>,
+    Query(params): Query<DeliveryLogParams>,
+) -> Result<Json<Vec<WebhookDeliveryResponse>>, ServiceError> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let deliveries = webhook_service.deliveries(&webhook_id, &auth_user.user_id, limit).await?;
+
+    Ok(Json(deliveries.into_iter().map(WebhookDeliveryResponse::from).collect()))
 }
 
-#[allow(dead_code)]
-pub async fn delete_image() -> &'static str {
-    "Delete image endpoint (with auth) - Coming soon"
-}
\ No newline at end of file
+pub async fn metrics(
+    State(metrics): State<Arc<ProcessingMetrics>>,
+) -> impl axum::response::IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics.render())
+}
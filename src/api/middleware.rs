@@ -1,36 +1,87 @@
 use axum::{
-    extract::Request,
+    async_trait,
+    extract::{FromRequestParts, Request, State},
     middleware::Next,
     response::Response,
-    http::{StatusCode, HeaderMap},
+    http::{request::Parts, HeaderMap},
 };
-use crate::core::jwt::JwtService;
+use std::sync::Arc;
+use crate::core::error::ServiceError;
+use crate::core::jwt::{Claims, JwtService};
 
-#[allow(dead_code)]
 pub async fn auth_middleware(
+    State(jwt_service): State<JwtService>,
     headers: HeaderMap,
     mut request: Request,
     next: Next,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ServiceError> {
+    let claims = authenticate(&jwt_service, &headers)?;
+
+    // Add claims to request extensions
+    request.extensions_mut().insert(claims);
+
+    Ok(next.run(request).await)
+}
+
+// Verifies the Bearer token in the authorization header
+pub fn authenticate(jwt_service: &JwtService, headers: &HeaderMap) -> Result<Claims, ServiceError> {
     let auth_header = headers
         .get("authorization")
         .and_then(|value| value.to_str().ok())
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .ok_or_else(|| ServiceError::AuthenticationError("Missing authorization header".to_string()))?;
 
-    if !auth_header.starts_with("Bearer ") {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| ServiceError::AuthenticationError("Malformed authorization header".to_string()))?;
 
-    let token = &auth_header[7..]; // Remove "Bearer " prefix
-    
-    // Create JWT service
-    let jwt_service = JwtService::new("your-secret-key".to_string());
-    
-    let claims = jwt_service.verify_token(token)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    jwt_service.verify_token(token)
+        .map_err(|e| ServiceError::AuthenticationError(format!("Invalid token: {}", e)))
+}
 
-    // Add claims to request extensions
-    request.extensions_mut().insert(claims);
-    
+// Guards /metrics with the static scrape token from METRICS_TOKEN rather than a user JWT
+pub async fn metrics_auth_middleware(
+    State(token): State<Arc<str>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Result<Response, ServiceError> {
+    let given = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| ServiceError::AuthenticationError("Missing metrics token".to_string()))?;
+
+    if !constant_time_eq(given.as_bytes(), token.as_bytes()) {
+        return Err(ServiceError::AuthenticationError("Invalid metrics token".to_string()));
+    }
     Ok(next.run(request).await)
-}
\ No newline at end of file
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Authenticated user extracted from the claims inserted by `auth_middleware`
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: String,
+    #[allow(dead_code)]
+    pub username: String,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = ServiceError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let claims = parts
+            .extensions
+            .get::<Claims>()
+            .ok_or(ServiceError::Unauthorized)?;
+
+        Ok(AuthUser {
+            user_id: claims.sub.clone(),
+            username: claims.username.clone(),
+        })
+    }
+}
@@ -1,3 +1,6 @@
 pub mod handlers;
 pub mod routes;
-pub mod middleware;
\ No newline at end of file
+pub mod middleware;
+pub mod conditional;
+pub mod range;
+pub mod negotiation;
\ No newline at end of file
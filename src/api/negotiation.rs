@@ -0,0 +1,117 @@
+use axum::http::{header, HeaderMap};
+use crate::domain::formats::OutputFormat;
+
+// Image formats the Accept header explicitly allows. Wildcards are not taken as support for
+// newer formats such as AVIF, since browsers list those explicitly when they can decode them.
+pub fn accepted_formats(headers: &HeaderMap) -> Vec<OutputFormat> {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return Vec::new();
+    };
+
+    accept
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let media_type = parts.next()?.to_lowercase();
+
+            // q=0 means "not acceptable"
+            let refused = parts
+                .filter_map(|param| param.split_once('='))
+                .filter(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .any(|(_, q)| q.trim().parse::<f32>().is_ok_and(|q| q <= 0.0));
+            if refused {
+                return None;
+            }
+
+            OutputFormat::ALL.into_iter().find(|format| format.mime_type() == media_type)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use crate::domain::image::ImageTransformation;
+
+    fn accepted(accept: &str) -> Vec<OutputFormat> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+        accepted_formats(&headers)
+    }
+
+    fn auto(json: serde_json::Value, accept: &str, source_has_alpha: bool) -> OutputFormat {
+        let mut transformation: ImageTransformation = serde_json::from_value(json).unwrap();
+        assert!(transformation.resolve_auto_format(&accepted(accept), source_has_alpha));
+        transformation.output_format().unwrap()
+    }
+
+    #[test]
+    fn listed_image_types_are_accepted() {
+        assert!(accepted_formats(&HeaderMap::new()).is_empty());
+        assert_eq!(
+            accepted("image/avif,image/webp,image/apng,*/*;q=0.8"),
+            [OutputFormat::Avif, OutputFormat::WebP]
+        );
+        assert_eq!(accepted("image/jpeg, image/png;q=0.9"), [OutputFormat::Jpeg, OutputFormat::Png]);
+        assert_eq!(accepted("IMAGE/WebP"), [OutputFormat::WebP]);
+        assert!(accepted("text/html,application/json").is_empty());
+    }
+
+    #[test]
+    fn wildcards_do_not_count_as_support() {
+        assert!(accepted("*/*").is_empty());
+        assert!(accepted("image/*;q=1").is_empty());
+        assert_eq!(accepted("image/*, image/webp"), [OutputFormat::WebP]);
+    }
+
+    #[test]
+    fn zero_quality_excludes_a_type() {
+        for accept in ["image/webp;q=0", "image/webp; q=0.0", "image/webp;Q=0.000", "image/webp; q = 0"] {
+            assert!(accepted(accept).is_empty(), "{}", accept);
+        }
+        assert_eq!(accepted("image/avif;q=0,image/webp;q=0.1"), [OutputFormat::WebP]);
+        // Anything else, even malformed, leaves the type acceptable
+        assert_eq!(accepted("image/webp;q=0.5"), [OutputFormat::WebP]);
+        assert_eq!(accepted("image/webp;q=abc"), [OutputFormat::WebP]);
+    }
+
+    #[test]
+    fn avif_then_webp_are_preferred_over_jpeg() {
+        let resize = serde_json::json!({ "resize": { "width": 10 }, "format": "auto" });
+        assert_eq!(auto(resize.clone(), "image/avif,image/webp,image/jpeg", false), OutputFormat::Avif);
+        // The service's preference wins over the client's q-values
+        assert_eq!(auto(resize.clone(), "image/webp,image/avif;q=0.5", false), OutputFormat::Avif);
+        assert_eq!(auto(resize.clone(), "image/webp,image/jpeg", false), OutputFormat::WebP);
+        assert_eq!(auto(resize.clone(), "image/webp;q=0,image/jpeg", false), OutputFormat::Jpeg);
+        assert_eq!(auto(resize, "*/*", false), OutputFormat::Jpeg);
+    }
+
+    #[test]
+    fn transparent_output_falls_back_to_png() {
+        let resize = serde_json::json!({ "resize": { "width": 10 }, "format": "auto" });
+        assert_eq!(auto(resize.clone(), "image/jpeg", true), OutputFormat::Png);
+        assert_eq!(auto(resize, "image/webp", true), OutputFormat::WebP);
+
+        // Opaque sources gain transparency from uneven rotations and transparent padding
+        let rotate = serde_json::json!({ "rotate": 45.0, "format": "auto" });
+        assert_eq!(auto(rotate, "*/*", false), OutputFormat::Png);
+        let square = serde_json::json!({ "rotate": 90.0, "format": "auto" });
+        assert_eq!(auto(square, "*/*", false), OutputFormat::Jpeg);
+        let pad = serde_json::json!({ "resize": { "width": 10, "height": 10, "mode": "pad" }, "format": "auto" });
+        assert_eq!(auto(pad, "*/*", false), OutputFormat::Png);
+        let white_pad = serde_json::json!({
+            "resize": { "width": 10, "height": 10, "mode": "pad", "background": "#ffffff" },
+            "format": "auto"
+        });
+        assert_eq!(auto(white_pad, "*/*", false), OutputFormat::Jpeg);
+    }
+
+    #[test]
+    fn explicit_formats_are_not_negotiated() {
+        let mut transformation: ImageTransformation =
+            serde_json::from_value(serde_json::json!({ "resize": { "width": 10 }, "format": "png" })).unwrap();
+        assert!(!transformation.resolve_auto_format(&accepted("image/avif"), false));
+        assert_eq!(transformation.output_format().unwrap(), OutputFormat::Png);
+    }
+}
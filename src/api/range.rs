@@ -0,0 +1,167 @@
+use std::ops::Range;
+use axum::http::{header, HeaderMap};
+use crate::api::conditional::Validators;
+
+// More ranges than this in one request is treated as abuse and refused
+pub const MAX_RANGES: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRequest {
+    // No usable Range header: serve the whole representation
+    Full,
+    // Half-open byte ranges, in the order requested
+    Partial(Vec<Range<u64>>),
+    // Syntactically valid, but nothing overlaps the representation
+    Unsatisfiable,
+}
+
+impl RangeRequest {
+    // Ranges are only honoured when If-Range (if any) still matches the current representation
+    pub fn from_headers(headers: &HeaderMap, len: u64, validators: &Validators) -> Self {
+        let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+            return RangeRequest::Full;
+        };
+
+        if let Some(if_range) = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) {
+            if !validators.matches_if_range(if_range.trim()) {
+                return RangeRequest::Full;
+            }
+        }
+
+        parse(range, len)
+    }
+}
+
+// RFC 9110 section 14.1.2. Malformed headers are ignored rather than rejected, as the RFC allows.
+pub fn parse(header: &str, len: u64) -> RangeRequest {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let specs: Vec<&str> = specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()).collect();
+    // "bytes=" alone names no range at all, so it is as invalid as any other malformed header
+    if specs.is_empty() {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+
+        let range = match (start.trim(), end.trim()) {
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => None,
+                Ok(suffix) => Some(len.saturating_sub(suffix)..len),
+                Err(_) => return RangeRequest::Full,
+            },
+            (start, "") => match start.parse::<u64>() {
+                Ok(start) => Some(start..len),
+                Err(_) => return RangeRequest::Full,
+            },
+            (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+                (Ok(start), Ok(end)) if start <= end => Some(start..end.saturating_add(1).min(len)),
+                _ => return RangeRequest::Full,
+            },
+        };
+
+        // Ranges starting past the end are unsatisfiable on their own but do not spoil the rest
+        if let Some(range) = range.filter(|range| range.start < len) {
+            ranges.push(range);
+        }
+        if ranges.len() > MAX_RANGES {
+            return RangeRequest::Unsatisfiable;
+        }
+    }
+
+    if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const LEN: u64 = 1000;
+
+    fn single(range: Range<u64>) -> RangeRequest {
+        RangeRequest::Partial(vec![range])
+    }
+
+    fn partial(ranges: &[Range<u64>]) -> RangeRequest {
+        RangeRequest::Partial(ranges.to_vec())
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(parse("bytes=0-99", LEN), single(0..100));
+        assert_eq!(parse("bytes=500-500", LEN), single(500..501));
+        // The last byte position is clamped to the representation
+        assert_eq!(parse("bytes=900-5000", LEN), single(900..1000));
+        assert_eq!(parse(" bytes= 10 - 19 ", LEN), single(10..20));
+    }
+
+    #[test]
+    fn open_ended_and_suffix_ranges() {
+        assert_eq!(parse("bytes=990-", LEN), single(990..1000));
+        assert_eq!(parse("bytes=-10", LEN), single(990..1000));
+        // A suffix longer than the representation selects all of it
+        assert_eq!(parse("bytes=-5000", LEN), single(0..1000));
+    }
+
+    #[test]
+    fn multiple_ranges_keep_their_order() {
+        assert_eq!(parse("bytes=500-599,0-9,-5", LEN), partial(&[500..600, 0..10, 995..1000]));
+        // Ranges past the end are dropped while the others are served
+        assert_eq!(parse("bytes=0-9,2000-2100", LEN), single(0..10));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse("bytes=1000-", LEN), RangeRequest::Unsatisfiable);
+        assert_eq!(parse("bytes=1000-1999", LEN), RangeRequest::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", LEN), RangeRequest::Unsatisfiable);
+        assert_eq!(parse("bytes=0-0", 0), RangeRequest::Unsatisfiable);
+
+        let many = vec!["0-0"; MAX_RANGES + 1].join(",");
+        assert_eq!(parse(&format!("bytes={}", many), LEN), RangeRequest::Unsatisfiable);
+        let allowed = vec!["0-0"; MAX_RANGES].join(",");
+        assert!(matches!(parse(&format!("bytes={}", allowed), LEN), RangeRequest::Partial(_)));
+    }
+
+    #[test]
+    fn invalid_headers_are_ignored() {
+        for header in ["bytes=", "bytes= , ", "items=0-9", "0-9", "bytes=abc", "bytes=9-0", "bytes=0-9,x", "bytes=--1", "bytes=1-2-3"] {
+            assert_eq!(parse(header, LEN), RangeRequest::Full, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn if_range_must_match_the_current_representation() {
+        let validators = Validators::new("abc", Some("2024-01-02 03:04:05"));
+        let request = |if_range: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, HeaderValue::from_static("bytes=0-9"));
+            if let Some(if_range) = if_range {
+                headers.insert(header::IF_RANGE, HeaderValue::from_str(if_range).unwrap());
+            }
+            RangeRequest::from_headers(&headers, LEN, &validators)
+        };
+
+        assert_eq!(request(None), single(0..10));
+        assert_eq!(request(Some("\"abc\"")), single(0..10));
+        assert_eq!(request(Some("Tue, 02 Jan 2024 03:04:05 GMT")), single(0..10));
+
+        // Stale validators, and weak ETags which If-Range never accepts, fall back to the full response
+        assert_eq!(request(Some("\"old\"")), RangeRequest::Full);
+        assert_eq!(request(Some("W/\"abc\"")), RangeRequest::Full);
+        assert_eq!(request(Some("Mon, 01 Jan 2024 00:00:00 GMT")), RangeRequest::Full);
+
+        assert_eq!(RangeRequest::from_headers(&HeaderMap::new(), LEN, &validators), RangeRequest::Full);
+    }
+}
@@ -1,17 +1,28 @@
+use std::sync::Arc;
 use axum::{
-    routing::{get, post},
-    Router,
+    extract::DefaultBodyLimit,
+    middleware::from_fn_with_state,
+    routing::{delete, get, post},
+    Extension, Router,
 };
-use crate::api::handlers;
+use crate::api::{handlers, middleware};
+use crate::api::conditional::CachePolicy;
 use crate::application::user_service::UserService;
 use crate::application::image_service::ImageService;
+use crate::application::job_service::JobService;
+use crate::application::webhook_service::WebhookService;
 use crate::domain::user_repository::UserRepository;
 use crate::domain::image::ImageRepository;
 use crate::core::jwt::JwtService;
+use crate::core::signed_url::RenderUrlSigner;
+use crate::core::config::Settings;
 
 pub fn create_router<UR, IR>(
+    settings: &Settings,
     user_service: UserService<UR>,
     image_service: ImageService<IR>,
+    job_service: JobService<IR>,
+    webhook_service: WebhookService,
     jwt_service: JwtService,
 ) -> Router
 where
@@ -21,16 +32,58 @@ where
     let auth_router = Router::new()
         .route("/register", post(handlers::register))
         .route("/login", post(handlers::login))
-        .with_state((user_service, jwt_service));
+        .with_state((user_service, jwt_service.clone()));
 
-    // Routes بدون authentication برای تست
+    // Prometheus scrapes with the static METRICS_TOKEN; without one the route is not served
+    let metrics_router = match settings.metrics_token.as_str() {
+        "" => Router::new(),
+        token => Router::new()
+            .route("/metrics", get(handlers::metrics))
+            .route_layer(from_fn_with_state(Arc::<str>::from(token), middleware::metrics_auth_middleware))
+            .with_state(image_service.processing_metrics()),
+    };
+
+    // All image routes require a valid JWT
     let image_router = Router::new()
         .route("/images", post(handlers::upload_image_simple).get(handlers::list_images_simple))
-        .route("/images/:id", get(handlers::get_image_simple))
+        .route("/images/batch/transform", post(handlers::batch_transform))
+        .route("/images/:id", get(handlers::get_image_simple).delete(handlers::delete_image))
         .route("/images/:id/transform", post(handlers::transform_image_simple))
+        .route("/images/:id/render-url", post(handlers::create_render_url))
+        .route("/images/:id/derivatives", get(handlers::list_derivatives))
+        .route("/images/:id/lineage", get(handlers::get_lineage))
+        .route("/trash", get(handlers::list_trash).delete(handlers::empty_trash))
+        .route("/trash/:id/restore", post(handlers::restore_image))
+        .route_layer(from_fn_with_state(jwt_service.clone(), middleware::auth_middleware))
+        .layer(DefaultBodyLimit::max(settings.max_upload_size))
+        .layer(Extension(CachePolicy::new(&settings.cache_control)))
+        .layer(Extension(RenderUrlSigner::new(settings)))
+        .with_state(image_service.clone());
+
+    // Outside the Bearer-only layer: the handler also accepts signed URLs
+    let render_router = Router::new()
+        .route("/images/:id/render", get(handlers::render_image))
+        .layer(Extension(CachePolicy::new(&settings.cache_control)))
+        .layer(Extension(RenderUrlSigner::new(settings)))
+        .layer(Extension(jwt_service.clone()))
         .with_state(image_service);
 
+    let job_router = Router::new()
+        .route("/images/:id/jobs", post(handlers::create_job))
+        .route("/jobs/:id", get(handlers::get_job))
+        .route("/jobs/:id/result", get(handlers::get_job_result))
+        .route_layer(from_fn_with_state(jwt_service.clone(), middleware::auth_middleware))
+        .with_state(job_service);
+
+    let webhook_router = Router::new()
+        .route("/webhooks", post(handlers::register_webhook).get(handlers::list_webhooks))
+        .route("/webhooks/:id", delete(handlers::delete_webhook))
+        .route("/webhooks/:id/deliveries", get(handlers::list_webhook_deliveries))
+        .route_layer(from_fn_with_state(jwt_service, middleware::auth_middleware))
+        .with_state(webhook_service);
+
     Router::new()
         .nest("/auth", auth_router)
-        .nest("/api", image_router)
+        .nest("/api", image_router.merge(render_router).merge(job_router).merge(webhook_router))
+        .merge(metrics_router)
 }
\ No newline at end of file
@@ -0,0 +1,317 @@
+use std::sync::Arc;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::application::encoding::ENCODER_VERSION;
+use crate::application::image_service::ProcessedImage;
+use crate::core::error::ServiceError;
+use crate::domain::derivative_cache::{CachedDerivative, DerivativeCacheRepository};
+use crate::domain::formats::OutputFormat;
+use crate::domain::image::ImageTransformation;
+use crate::domain::transformations::Operation;
+use crate::infrastructure::storage::StorageBackend;
+
+// Entries are evicted in batches of this size until the cache fits its budget again
+const EVICTION_BATCH: i64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    // The cache is disabled
+    Bypass,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        }
+    }
+}
+
+// What the cache key is computed from. Legacy fields are expanded into their pipeline and
+// only the encoder settings of the chosen format are kept, so equivalent requests share a key.
+#[derive(Serialize)]
+struct CanonicalTransformation<'a> {
+    encoder_version: u32,
+    image_id: &'a str,
+    auto_orient: bool,
+    operations: Vec<Operation>,
+    format: OutputFormat,
+    encoder: serde_json::Value,
+}
+
+pub struct DerivativeCache {
+    repository: Arc<dyn DerivativeCacheRepository>,
+    storage: Arc<dyn StorageBackend>,
+    max_bytes: u64,
+}
+
+impl DerivativeCache {
+    pub fn new(
+        repository: Arc<dyn DerivativeCacheRepository>,
+        storage: Arc<dyn StorageBackend>,
+        max_bytes: u64,
+    ) -> Self {
+        Self { repository, storage, max_bytes }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    pub fn cache_key(image_id: &str, transformations: &ImageTransformation) -> Result<String, ServiceError> {
+        let format = transformations.output_format()?;
+        let options = &transformations.encoder;
+        let encoder = match format {
+            OutputFormat::Jpeg => serde_json::to_value(&options.jpeg),
+            OutputFormat::Png => serde_json::to_value(&options.png),
+            OutputFormat::WebP => serde_json::to_value(&options.webp),
+            OutputFormat::Avif => serde_json::to_value(&options.avif),
+            _ => Ok(serde_json::Value::Null),
+        };
+
+        let canonical = CanonicalTransformation {
+            encoder_version: ENCODER_VERSION,
+            image_id,
+            auto_orient: transformations.auto_orient,
+            operations: transformations.pipeline(),
+            format,
+            encoder: encoder.map_err(|e| ServiceError::ImageProcessingError(e.to_string()))?,
+        };
+        let json = serde_json::to_vec(&canonical)
+            .map_err(|e| ServiceError::ImageProcessingError(e.to_string()))?;
+
+        Ok(format!("{:x}", Sha256::digest(json)))
+    }
+
+    pub async fn get(&self, cache_key: &str) -> Result<Option<ProcessedImage>, ServiceError> {
+        let Some(entry) = self.repository.find(cache_key).await? else {
+            return Ok(None);
+        };
+        let Some(format) = OutputFormat::from_name(&entry.format) else {
+            self.repository.remove(cache_key).await?;
+            return Ok(None);
+        };
+
+        // The index can outlive the bytes (manual cleanup, bucket lifecycle rules)
+        let data = match self.storage.get(&entry.storage_key).await {
+            Ok(data) => data,
+            Err(_) => {
+                self.repository.remove(cache_key).await?;
+                return Ok(None);
+            }
+        };
+
+        self.repository.touch(cache_key, now_millis()).await?;
+        Ok(Some(ProcessedImage { data, format }))
+    }
+
+    pub async fn put(
+        &self,
+        cache_key: &str,
+        image_id: &str,
+        dependencies: &[String],
+        processed: &ProcessedImage,
+    ) -> Result<(), ServiceError> {
+        if processed.data.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+
+        let storage_key = format!("derivatives/{}/{}.{}", image_id, cache_key, processed.format.extension());
+        self.storage.put(&storage_key, &processed.data).await?;
+        self.repository
+            .insert(&CachedDerivative {
+                cache_key: cache_key.to_string(),
+                image_id: image_id.to_string(),
+                dependencies: format!(",{},", dependencies.join(",")),
+                storage_key,
+                format: processed.format.name().to_string(),
+                size: processed.data.len() as i64,
+                last_accessed_at: now_millis(),
+            })
+            .await?;
+
+        self.evict().await
+    }
+
+    async fn evict(&self) -> Result<(), ServiceError> {
+        while self.repository.total_size().await? as u64 > self.max_bytes {
+            let entries = self.repository.least_recently_used(EVICTION_BATCH).await?;
+            if entries.is_empty() {
+                break;
+            }
+
+            let mut total = self.repository.total_size().await? as u64;
+            for entry in entries {
+                if total <= self.max_bytes {
+                    break;
+                }
+                self.remove(&entry).await?;
+                total = total.saturating_sub(entry.size as u64);
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, entry: &CachedDerivative) -> Result<(), ServiceError> {
+        // A missing object is fine, the index entry is what matters
+        let _ = self.storage.delete(&entry.storage_key).await;
+        self.repository.remove(&entry.cache_key).await
+    }
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use object_store::memory::InMemory;
+    use crate::infrastructure::storage::S3Storage;
+
+    // Same semantics as the SQLite index
+    #[derive(Default)]
+    struct MemoryRepository {
+        entries: Mutex<HashMap<String, CachedDerivative>>,
+    }
+
+    #[async_trait::async_trait]
+    impl DerivativeCacheRepository for MemoryRepository {
+        async fn find(&self, cache_key: &str) -> Result<Option<CachedDerivative>, ServiceError> {
+            Ok(self.entries.lock().unwrap().get(cache_key).cloned())
+        }
+
+        async fn insert(&self, entry: &CachedDerivative) -> Result<(), ServiceError> {
+            self.entries.lock().unwrap().insert(entry.cache_key.clone(), entry.clone());
+            Ok(())
+        }
+
+        async fn touch(&self, cache_key: &str, accessed_at: i64) -> Result<(), ServiceError> {
+            if let Some(entry) = self.entries.lock().unwrap().get_mut(cache_key) {
+                entry.last_accessed_at = accessed_at;
+            }
+            Ok(())
+        }
+
+        async fn remove(&self, cache_key: &str) -> Result<(), ServiceError> {
+            self.entries.lock().unwrap().remove(cache_key);
+            Ok(())
+        }
+
+        async fn total_size(&self) -> Result<i64, ServiceError> {
+            Ok(self.entries.lock().unwrap().values().map(|entry| entry.size).sum())
+        }
+
+        async fn least_recently_used(&self, limit: i64) -> Result<Vec<CachedDerivative>, ServiceError> {
+            let mut entries: Vec<CachedDerivative> = self.entries.lock().unwrap().values().cloned().collect();
+            entries.sort_by_key(|entry| entry.last_accessed_at);
+            entries.truncate(limit as usize);
+            Ok(entries)
+        }
+    }
+
+    struct Fixture {
+        cache: DerivativeCache,
+        repository: Arc<MemoryRepository>,
+        storage: Arc<dyn StorageBackend>,
+    }
+
+    fn fixture(max_bytes: u64) -> Fixture {
+        let repository = Arc::new(MemoryRepository::default());
+        let storage: Arc<dyn StorageBackend> = Arc::new(S3Storage::with_store(Arc::new(InMemory::new())));
+        let cache = DerivativeCache::new(repository.clone(), storage.clone(), max_bytes);
+        Fixture { cache, repository, storage }
+    }
+
+    fn processed(size: usize) -> ProcessedImage {
+        ProcessedImage { data: vec![7; size], format: OutputFormat::Png }
+    }
+
+    fn key(image_id: &str, json: serde_json::Value) -> String {
+        let transformation: ImageTransformation = serde_json::from_value(json).unwrap();
+        DerivativeCache::cache_key(image_id, &transformation).unwrap()
+    }
+
+    // Access times are in milliseconds; keep successive accesses apart
+    async fn later() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    #[tokio::test]
+    async fn misses_then_hits() {
+        let Fixture { cache, .. } = fixture(1000);
+        assert!(cache.get("a").await.unwrap().is_none());
+
+        cache.put("a", "image", &[], &processed(100)).await.unwrap();
+        let hit = cache.get("a").await.unwrap().unwrap();
+        assert_eq!(hit.data, processed(100).data);
+        assert_eq!(hit.format, OutputFormat::Png);
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_entries_are_evicted_over_budget() {
+        let Fixture { cache, repository, storage } = fixture(250);
+        cache.put("a", "image", &[], &processed(100)).await.unwrap();
+        later().await;
+        cache.put("b", "image", &[], &processed(100)).await.unwrap();
+        later().await;
+        // Reading "a" makes "b" the least recently used
+        cache.get("a").await.unwrap().unwrap();
+        later().await;
+
+        let evicted = repository.find("b").await.unwrap().unwrap().storage_key;
+        cache.put("c", "image", &[], &processed(100)).await.unwrap();
+
+        assert!(cache.get("b").await.unwrap().is_none());
+        assert!(cache.get("a").await.unwrap().is_some());
+        assert!(cache.get("c").await.unwrap().is_some());
+        assert_eq!(repository.total_size().await.unwrap(), 200);
+        assert!(!storage.exists(&evicted).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn entries_larger_than_the_budget_are_not_stored() {
+        let Fixture { cache, repository, .. } = fixture(50);
+        cache.put("a", "image", &[], &processed(100)).await.unwrap();
+        assert!(cache.get("a").await.unwrap().is_none());
+        assert_eq!(repository.total_size().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn entries_whose_bytes_are_gone_are_dropped() {
+        let Fixture { cache, repository, storage } = fixture(1000);
+        cache.put("a", "image", &["overlay".to_string()], &processed(10)).await.unwrap();
+        let entry = repository.find("a").await.unwrap().unwrap();
+        assert_eq!(entry.dependencies, ",overlay,");
+
+        storage.delete(&entry.storage_key).await.unwrap();
+        assert!(cache.get("a").await.unwrap().is_none());
+        assert!(repository.find("a").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn equivalent_requests_share_a_key() {
+        let legacy = key("image", serde_json::json!({ "resize": { "width": 100 }, "format": "png" }));
+        let pipeline = key("image", serde_json::json!({ "operations": [{ "op": "resize", "width": 100 }], "format": "png" }));
+        assert_eq!(legacy, pipeline);
+
+        // Settings of other encoders do not matter
+        let with_jpeg = key("image", serde_json::json!({ "resize": { "width": 100 }, "format": "png", "jpeg": { "quality": 10 } }));
+        assert_eq!(legacy, with_jpeg);
+
+        assert_ne!(legacy, key("other", serde_json::json!({ "resize": { "width": 100 }, "format": "png" })));
+        assert_ne!(legacy, key("image", serde_json::json!({ "resize": { "width": 101 }, "format": "png" })));
+        assert_ne!(legacy, key("image", serde_json::json!({ "resize": { "width": 100 }, "format": "webp" })));
+        assert_ne!(
+            key("image", serde_json::json!({ "resize": { "width": 100 }, "format": "jpeg", "jpeg": { "quality": 50 } })),
+            key("image", serde_json::json!({ "resize": { "width": 100 }, "format": "jpeg", "jpeg": { "quality": 60 } }))
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::io::Cursor;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{self, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+use crate::core::error::ServiceError;
+use crate::domain::formats::{EncoderOptions, OutputFormat, PngCompression, PngFilter};
+
+// Part of every derivative cache key; bump whenever encoder output changes for the same input
+pub const ENCODER_VERSION: u32 = 1;
+
+const DEFAULT_QUALITY: u8 = 80;
+const DEFAULT_AVIF_SPEED: u8 = 8;
+const MAX_ICO_DIMENSION: u32 = 256;
+
+fn encode_error(err: image::ImageError) -> ServiceError {
+    ServiceError::ImageProcessingError(format!("Failed to encode image: {}", err))
+}
+
+// JPEG has no alpha channel, so transparent areas are composited onto white
+fn flatten(img: &DynamicImage) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return DynamicImage::ImageRgb8(img.to_rgb8());
+    }
+    let (width, height) = img.dimensions();
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    image::imageops::overlay(&mut canvas, &img.to_rgba8(), 0, 0);
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+}
+
+pub fn encode(img: &DynamicImage, format: OutputFormat, options: &EncoderOptions) -> Result<Vec<u8>, ServiceError> {
+    let mut buffer = Cursor::new(Vec::new());
+
+    match format {
+        OutputFormat::Jpeg => {
+            let quality = options.jpeg.quality.unwrap_or(DEFAULT_QUALITY);
+            flatten(img)
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, quality))
+                .map_err(encode_error)?;
+        }
+        OutputFormat::Png => {
+            let compression = match options.png.compression {
+                PngCompression::Fast => png::CompressionType::Fast,
+                PngCompression::Default => png::CompressionType::Default,
+                PngCompression::Best => png::CompressionType::Best,
+            };
+            let filter = match options.png.filter {
+                PngFilter::None => png::FilterType::NoFilter,
+                PngFilter::Sub => png::FilterType::Sub,
+                PngFilter::Up => png::FilterType::Up,
+                PngFilter::Avg => png::FilterType::Avg,
+                PngFilter::Paeth => png::FilterType::Paeth,
+                PngFilter::Adaptive => png::FilterType::Adaptive,
+            };
+            img.write_with_encoder(PngEncoder::new_with_quality(&mut buffer, compression, filter))
+                .map_err(encode_error)?;
+        }
+        OutputFormat::WebP => {
+            // The WebP encoder only accepts 8-bit RGB(A)
+            DynamicImage::ImageRgba8(img.to_rgba8())
+                .write_with_encoder(WebPEncoder::new_lossless(&mut buffer))
+                .map_err(encode_error)?;
+        }
+        OutputFormat::Avif => {
+            let quality = options.avif.quality.unwrap_or(DEFAULT_QUALITY);
+            let speed = options.avif.speed.unwrap_or(DEFAULT_AVIF_SPEED);
+            DynamicImage::ImageRgba8(img.to_rgba8())
+                .write_with_encoder(AvifEncoder::new_with_speed_quality(&mut buffer, speed, quality))
+                .map_err(encode_error)?;
+        }
+        OutputFormat::Ico => {
+            let (width, height) = img.dimensions();
+            if width > MAX_ICO_DIMENSION || height > MAX_ICO_DIMENSION {
+                return Err(ServiceError::ValidationError(format!(
+                    "ICO output is limited to {}x{}, got {}x{}",
+                    MAX_ICO_DIMENSION, MAX_ICO_DIMENSION, width, height
+                )));
+            }
+            DynamicImage::ImageRgba8(img.to_rgba8())
+                .write_to(&mut buffer, ImageFormat::Ico)
+                .map_err(encode_error)?;
+        }
+        OutputFormat::Gif => {
+            DynamicImage::ImageRgba8(img.to_rgba8())
+                .write_to(&mut buffer, ImageFormat::Gif)
+                .map_err(encode_error)?;
+        }
+        OutputFormat::Bmp => {
+            DynamicImage::ImageRgba8(img.to_rgba8())
+                .write_to(&mut buffer, ImageFormat::Bmp)
+                .map_err(encode_error)?;
+        }
+        OutputFormat::Tiff => {
+            img.write_to(&mut buffer, ImageFormat::Tiff).map_err(encode_error)?;
+        }
+    }
+
+    Ok(buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A gradient with a transparent left half
+    fn source() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(24, 16, |x, y| {
+            Rgba([(x * 10) as u8, (y * 15) as u8, 128, if x < 12 { 0 } else { 255 }])
+        }))
+    }
+
+    fn encoded(format: OutputFormat, options: serde_json::Value) -> Vec<u8> {
+        let options: EncoderOptions = serde_json::from_value(options).unwrap();
+        encode(&source(), format, &options).unwrap()
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        for format in OutputFormat::ALL {
+            let data = encoded(format, serde_json::json!({}));
+            let detected = image::guess_format(&data).unwrap();
+            assert_eq!(detected.to_mime_type(), format.mime_type(), "{:?}", format);
+
+            // AVIF can be encoded but not decoded by this build
+            if format == OutputFormat::Avif {
+                continue;
+            }
+            let decoded = image::load_from_memory(&data).unwrap();
+            assert_eq!(decoded.dimensions(), (24, 16), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn lossless_formats_keep_every_pixel() {
+        for format in [OutputFormat::Png, OutputFormat::WebP, OutputFormat::Bmp, OutputFormat::Tiff, OutputFormat::Ico] {
+            let decoded = image::load_from_memory(&encoded(format, serde_json::json!({}))).unwrap();
+            assert_eq!(decoded.to_rgba8(), source().to_rgba8(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn png_options_do_not_change_pixels() {
+        for compression in ["fast", "default", "best"] {
+            for filter in ["none", "sub", "up", "avg", "paeth", "adaptive"] {
+                let options = serde_json::json!({ "png": { "compression": compression, "filter": filter } });
+                let decoded = image::load_from_memory(&encoded(OutputFormat::Png, options)).unwrap();
+                assert_eq!(decoded.to_rgba8(), source().to_rgba8(), "{} {}", compression, filter);
+            }
+        }
+    }
+
+    #[test]
+    fn jpeg_is_flattened_onto_white() {
+        let decoded = image::load_from_memory(&encoded(OutputFormat::Jpeg, serde_json::json!({ "jpeg": { "quality": 100 } })))
+            .unwrap()
+            .to_rgb8();
+        let transparent = decoded.get_pixel(2, 8).0;
+        assert!(transparent.iter().all(|channel| *channel > 240), "{:?}", transparent);
+        let opaque = decoded.get_pixel(20, 8).0;
+        assert!(opaque[0].abs_diff(200) < 16 && opaque[1].abs_diff(120) < 16, "{:?}", opaque);
+    }
+
+    #[test]
+    fn quality_controls_lossy_size() {
+        let photo = DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x * y) % 256) as u8])
+        }));
+        let size = |format: OutputFormat, options: serde_json::Value| {
+            let options: EncoderOptions = serde_json::from_value(options).unwrap();
+            encode(&photo, format, &options).unwrap().len()
+        };
+        assert!(size(OutputFormat::Jpeg, serde_json::json!({ "jpeg": { "quality": 10 } }))
+            < size(OutputFormat::Jpeg, serde_json::json!({ "jpeg": { "quality": 95 } })));
+        assert!(size(OutputFormat::Avif, serde_json::json!({ "avif": { "quality": 10, "speed": 10 } }))
+            < size(OutputFormat::Avif, serde_json::json!({ "avif": { "quality": 95, "speed": 10 } })));
+    }
+
+    #[test]
+    fn ico_is_limited_to_256_pixels() {
+        let large = DynamicImage::ImageRgba8(RgbaImage::new(257, 16));
+        let err = encode(&large, OutputFormat::Ico, &EncoderOptions::default()).unwrap_err();
+        assert!(matches!(err, ServiceError::ValidationError(_)), "{:?}", err);
+
+        let largest = DynamicImage::ImageRgba8(RgbaImage::new(256, 256));
+        assert!(encode(&largest, OutputFormat::Ico, &EncoderOptions::default()).is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use crate::domain::transformations::Color;
+
+// Per-pixel colour adjustments; alpha is always preserved
+
+fn map_pixels<F>(img: &DynamicImage, f: F) -> DynamicImage
+where
+    F: Fn([f32; 3]) -> [f32; 3],
+{
+    let mut buffer: RgbaImage = img.to_rgba8();
+    for pixel in buffer.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let [r, g, b] = f([r as f32, g as f32, b as f32]);
+        *pixel = Rgba([clamp(r), clamp(g), clamp(b), a]);
+    }
+    DynamicImage::ImageRgba8(buffer)
+}
+
+fn clamp(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+pub fn sepia(img: &DynamicImage) -> DynamicImage {
+    map_pixels(img, |[r, g, b]| {
+        [
+            0.393 * r + 0.769 * g + 0.189 * b,
+            0.349 * r + 0.686 * g + 0.168 * b,
+            0.272 * r + 0.534 * g + 0.131 * b,
+        ]
+    })
+}
+
+// factor 0 is grayscale, 1 leaves the image unchanged
+pub fn saturate(img: &DynamicImage, factor: f32) -> DynamicImage {
+    map_pixels(img, |rgb| {
+        let l = luma(rgb);
+        rgb.map(|c| l + (c - l) * factor)
+    })
+}
+
+pub fn gamma(img: &DynamicImage, value: f32) -> DynamicImage {
+    let exponent = 1.0 / value;
+    map_pixels(img, |rgb| rgb.map(|c| 255.0 * (c / 255.0).powf(exponent)))
+}
+
+pub fn tint(img: &DynamicImage, color: Color, strength: f32) -> DynamicImage {
+    let target = [color.r as f32, color.g as f32, color.b as f32];
+    map_pixels(img, |[r, g, b]| {
+        [
+            r + (target[0] - r) * strength,
+            g + (target[1] - g) * strength,
+            b + (target[2] - b) * strength,
+        ]
+    })
+}
+
+pub fn posterize(img: &DynamicImage, levels: u8) -> DynamicImage {
+    let steps = (levels - 1) as f32;
+    map_pixels(img, |rgb| rgb.map(|c| (c / 255.0 * steps).round() * 255.0 / steps))
+}
+
+// Pixels at or above the luma level become white, the rest black
+pub fn threshold(img: &DynamicImage, level: u8) -> DynamicImage {
+    map_pixels(img, |rgb| {
+        let value = if luma(rgb).round() >= level as f32 { 255.0 } else { 0.0 };
+        [value; 3]
+    })
+}
+
+pub fn invert(img: DynamicImage) -> DynamicImage {
+    let mut img = img;
+    img.invert();
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2x2 fixture: an orange, half-transparent black, white and a fully transparent blue
+    fn fixture() -> DynamicImage {
+        let pixels = [[200, 100, 50, 255], [0, 0, 0, 128], [255, 255, 255, 255], [10, 150, 240, 0]];
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 2, |x, y| Rgba(pixels[(y * 2 + x) as usize])))
+    }
+
+    fn pixels(img: &DynamicImage) -> Vec<[u8; 4]> {
+        img.to_rgba8().pixels().map(|pixel| pixel.0).collect()
+    }
+
+    #[test]
+    fn sepia_golden() {
+        assert_eq!(
+            pixels(&sepia(&fixture())),
+            [[165, 147, 114, 255], [0, 0, 0, 128], [255, 255, 239, 255], [165, 147, 114, 0]]
+        );
+    }
+
+    #[test]
+    fn saturation_golden() {
+        assert_eq!(
+            pixels(&saturate(&fixture(), 0.0)),
+            [[124, 124, 124, 255], [0, 0, 0, 128], [255, 255, 255, 255], [118, 118, 118, 0]]
+        );
+        assert_eq!(
+            pixels(&saturate(&fixture(), 2.0)),
+            [[255, 76, 0, 255], [0, 0, 0, 128], [255, 255, 255, 255], [0, 182, 255, 0]]
+        );
+        assert_eq!(pixels(&saturate(&fixture(), 1.0)), pixels(&fixture()));
+    }
+
+    #[test]
+    fn gamma_golden() {
+        assert_eq!(
+            pixels(&gamma(&fixture(), 2.0)),
+            [[226, 160, 113, 255], [0, 0, 0, 128], [255, 255, 255, 255], [50, 196, 247, 0]]
+        );
+        assert_eq!(pixels(&gamma(&fixture(), 1.0)), pixels(&fixture()));
+    }
+
+    #[test]
+    fn tint_golden() {
+        let red = Color { r: 255, g: 0, b: 0, a: 255 };
+        assert_eq!(
+            pixels(&tint(&fixture(), red, 0.5)),
+            [[228, 50, 25, 255], [128, 0, 0, 128], [255, 128, 128, 255], [133, 75, 120, 0]]
+        );
+        assert_eq!(pixels(&tint(&fixture(), red, 0.0)), pixels(&fixture()));
+    }
+
+    #[test]
+    fn posterize_golden() {
+        assert_eq!(
+            pixels(&posterize(&fixture(), 2)),
+            [[255, 0, 0, 255], [0, 0, 0, 128], [255, 255, 255, 255], [0, 255, 255, 0]]
+        );
+        assert_eq!(
+            pixels(&posterize(&fixture(), 3)),
+            [[255, 128, 0, 255], [0, 0, 0, 128], [255, 255, 255, 255], [0, 128, 255, 0]]
+        );
+    }
+
+    #[test]
+    fn threshold_golden() {
+        // Luma is 124.2 for the orange and 118.4 for the blue
+        assert_eq!(
+            pixels(&threshold(&fixture(), 120)),
+            [[255, 255, 255, 255], [0, 0, 0, 128], [255, 255, 255, 255], [0, 0, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn invert_golden() {
+        assert_eq!(
+            pixels(&invert(fixture())),
+            [[55, 155, 205, 255], [255, 255, 255, 128], [0, 0, 0, 255], [245, 105, 15, 0]]
+        );
+    }
+}
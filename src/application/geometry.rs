@@ -0,0 +1,310 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use crate::domain::image::Resize;
+use crate::domain::transformations::{Color, FlipDirection, ResizeFilter, ResizeMode};
+
+fn filter_type(filter: ResizeFilter) -> FilterType {
+    match filter {
+        ResizeFilter::Nearest => FilterType::Nearest,
+        ResizeFilter::Triangle => FilterType::Triangle,
+        ResizeFilter::CatmullRom => FilterType::CatmullRom,
+        ResizeFilter::Gaussian => FilterType::Gaussian,
+        ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+    }
+}
+
+fn scaled(dimension: u32, scale: f64) -> u32 {
+    ((dimension as f64 * scale).round() as u32).max(1)
+}
+
+// A missing dimension follows the source aspect ratio
+fn target_size(width: u32, height: u32, resize: &Resize) -> Option<(u32, u32)> {
+    match (resize.width, resize.height) {
+        (Some(w), Some(h)) => Some((w, h)),
+        (Some(w), None) => Some((w, scaled(height, w as f64 / width as f64))),
+        (None, Some(h)) => Some((scaled(width, h as f64 / height as f64), h)),
+        (None, None) => None,
+    }
+}
+
+// Largest buffer `resize` allocates, including the intermediate scaled image
+pub fn resize_extent(width: u32, height: u32, resize: &Resize) -> (u32, u32) {
+    let Some((target_width, target_height)) = target_size(width, height, resize) else {
+        return (width, height);
+    };
+    let scale_x = target_width as f64 / width as f64;
+    let scale_y = target_height as f64 / height as f64;
+    let scale = match resize.mode {
+        ResizeMode::Exact => return (target_width, target_height),
+        ResizeMode::Fit | ResizeMode::Pad => scale_x.min(scale_y),
+        ResizeMode::Fill | ResizeMode::Cover => scale_x.max(scale_y),
+    };
+    let scale = if resize.no_upscale { scale.min(1.0) } else { scale };
+    let (scaled_width, scaled_height) = (scaled(width, scale), scaled(height, scale));
+
+    match resize.mode {
+        ResizeMode::Pad => (scaled_width.max(target_width), scaled_height.max(target_height)),
+        _ => (scaled_width, scaled_height),
+    }
+}
+
+pub fn resize(img: &DynamicImage, resize: &Resize) -> DynamicImage {
+    let (width, height) = img.dimensions();
+
+    let Some((target_width, target_height)) = target_size(width, height, resize) else {
+        return img.clone();
+    };
+
+    let filter = filter_type(resize.filter);
+    let scale_x = target_width as f64 / width as f64;
+    let scale_y = target_height as f64 / height as f64;
+    let cap = |scale: f64| if resize.no_upscale { scale.min(1.0) } else { scale };
+
+    match resize.mode {
+        ResizeMode::Exact => {
+            let (w, h) = if resize.no_upscale {
+                (target_width.min(width), target_height.min(height))
+            } else {
+                (target_width, target_height)
+            };
+            img.resize_exact(w, h, filter)
+        }
+        ResizeMode::Fit => {
+            let scale = cap(scale_x.min(scale_y));
+            img.resize_exact(scaled(width, scale), scaled(height, scale), filter)
+        }
+        ResizeMode::Fill => {
+            let scale = cap(scale_x.max(scale_y));
+            img.resize_exact(scaled(width, scale), scaled(height, scale), filter)
+        }
+        ResizeMode::Cover => {
+            let scale = cap(scale_x.max(scale_y));
+            let resized = img.resize_exact(scaled(width, scale), scaled(height, scale), filter);
+            let (resized_width, resized_height) = resized.dimensions();
+            let crop_width = target_width.min(resized_width);
+            let crop_height = target_height.min(resized_height);
+            let (x, y) = resize.gravity.offset(resized_width - crop_width, resized_height - crop_height);
+            resized.crop_imm(x, y, crop_width, crop_height)
+        }
+        ResizeMode::Pad => {
+            let scale = cap(scale_x.min(scale_y));
+            let resized = img.resize_exact(scaled(width, scale), scaled(height, scale), filter);
+            let (resized_width, resized_height) = resized.dimensions();
+            let background = Rgba(resize.background.unwrap_or(Color::TRANSPARENT).to_rgba());
+            let mut canvas = RgbaImage::from_pixel(target_width, target_height, background);
+            let (x, y) = resize.gravity.offset(
+                target_width.saturating_sub(resized_width),
+                target_height.saturating_sub(resized_height),
+            );
+            imageops::overlay(&mut canvas, &resized.to_rgba8(), x as i64, y as i64);
+            DynamicImage::ImageRgba8(canvas)
+        }
+    }
+}
+
+pub fn flip(img: &DynamicImage, direction: FlipDirection) -> DynamicImage {
+    match direction {
+        FlipDirection::Horizontal => img.fliph(),
+        FlipDirection::Vertical => img.flipv(),
+    }
+}
+
+// Right angles always swap or keep the dimensions; other angles only grow the canvas with `expand`
+pub fn rotated_size(width: u32, height: u32, degrees: f32, expand: bool) -> (u32, u32) {
+    let normalized = degrees.rem_euclid(360.0);
+    if normalized == 90.0 || normalized == 270.0 {
+        return (height, width);
+    }
+    if !expand || normalized == 0.0 || normalized == 180.0 {
+        return (width, height);
+    }
+    let (sin, cos) = (normalized as f64).to_radians().sin_cos();
+    (
+        (width as f64 * cos.abs() + height as f64 * sin.abs()).round().max(1.0) as u32,
+        (width as f64 * sin.abs() + height as f64 * cos.abs()).round().max(1.0) as u32,
+    )
+}
+
+// Clockwise rotation by any angle; right angles are lossless
+pub fn rotate(img: &DynamicImage, degrees: f32, expand: bool, background: Color) -> DynamicImage {
+    let normalized = degrees.rem_euclid(360.0);
+    if normalized == 0.0 {
+        return img.clone();
+    } else if normalized == 90.0 {
+        return img.rotate90();
+    } else if normalized == 180.0 {
+        return img.rotate180();
+    } else if normalized == 270.0 {
+        return img.rotate270();
+    }
+
+    let source = img.to_rgba8();
+    let (width, height) = source.dimensions();
+    let (sin, cos) = (normalized as f64).to_radians().sin_cos();
+    let (out_width, out_height) = rotated_size(width, height, degrees, expand);
+
+    let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+    let (out_cx, out_cy) = (out_width as f64 / 2.0, out_height as f64 / 2.0);
+    let background = Rgba(background.to_rgba());
+
+    // Map every output pixel back into the source and sample bilinearly
+    let output = RgbaImage::from_fn(out_width, out_height, |x, y| {
+        let dx = x as f64 + 0.5 - out_cx;
+        let dy = y as f64 + 0.5 - out_cy;
+        let sx = dx * cos + dy * sin + cx - 0.5;
+        let sy = -dx * sin + dy * cos + cy - 0.5;
+        sample_bilinear(&source, sx, sy, background)
+    });
+
+    DynamicImage::ImageRgba8(output)
+}
+
+fn sample_bilinear(source: &RgbaImage, x: f64, y: f64, background: Rgba<u8>) -> Rgba<u8> {
+    let (width, height) = source.dimensions();
+    if x <= -1.0 || y <= -1.0 || x >= width as f64 || y >= height as f64 {
+        return background;
+    }
+
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let (fx, fy) = (x - x0, y - y0);
+
+    let pixel = |px: f64, py: f64| -> [f64; 4] {
+        let inside = px >= 0.0 && py >= 0.0 && px < width as f64 && py < height as f64;
+        let Rgba(channels) = if inside {
+            *source.get_pixel(px as u32, py as u32)
+        } else {
+            background
+        };
+        channels.map(f64::from)
+    };
+
+    let top_left = pixel(x0, y0);
+    let top_right = pixel(x0 + 1.0, y0);
+    let bottom_left = pixel(x0, y0 + 1.0);
+    let bottom_right = pixel(x0 + 1.0, y0 + 1.0);
+
+    let mut result = [0u8; 4];
+    for i in 0..4 {
+        let top = top_left[i] + (top_right[i] - top_left[i]) * fx;
+        let bottom = bottom_left[i] + (bottom_right[i] - bottom_left[i]) * fx;
+        result[i] = (top + (bottom - top) * fy).round().clamp(0.0, 255.0) as u8;
+    }
+    Rgba(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 400x200, left half red and right half blue
+    fn source() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(400, 200, |x, _| {
+            if x < 200 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 255, 255]) }
+        }))
+    }
+
+    fn spec(json: serde_json::Value) -> Resize {
+        serde_json::from_value(json).unwrap()
+    }
+
+    // Checks the result and that resize_extent predicted a buffer at least as large
+    fn resized(json: serde_json::Value) -> (u32, u32) {
+        let spec = spec(json);
+        let output = resize(&source(), &spec).dimensions();
+        let (extent_width, extent_height) = resize_extent(400, 200, &spec);
+        assert!(extent_width >= output.0 && extent_height >= output.1);
+        output
+    }
+
+    #[test]
+    fn fit_stays_inside_the_box() {
+        assert_eq!(resized(serde_json::json!({ "width": 100, "height": 100 })), (100, 50));
+        assert_eq!(resized(serde_json::json!({ "width": 300, "height": 50, "mode": "fit" })), (100, 50));
+        assert_eq!(resized(serde_json::json!({ "width": 800, "height": 800, "mode": "fit" })), (800, 400));
+    }
+
+    #[test]
+    fn fill_covers_the_box() {
+        assert_eq!(resized(serde_json::json!({ "width": 100, "height": 100, "mode": "fill" })), (200, 100));
+        assert_eq!(resized(serde_json::json!({ "width": 300, "height": 50, "mode": "fill" })), (300, 150));
+    }
+
+    #[test]
+    fn cover_crops_to_the_box() {
+        assert_eq!(resized(serde_json::json!({ "width": 100, "height": 100, "mode": "cover" })), (100, 100));
+        assert_eq!(resized(serde_json::json!({ "width": 300, "height": 50, "mode": "cover" })), (300, 50));
+    }
+
+    #[test]
+    fn exact_stretches() {
+        assert_eq!(resized(serde_json::json!({ "width": 100, "height": 100, "mode": "exact" })), (100, 100));
+        assert_eq!(resized(serde_json::json!({ "width": 37, "height": 999, "mode": "exact" })), (37, 999));
+    }
+
+    #[test]
+    fn pad_fills_the_box() {
+        assert_eq!(resized(serde_json::json!({ "width": 100, "height": 100, "mode": "pad" })), (100, 100));
+
+        let spec = spec(serde_json::json!({
+            "width": 100, "height": 100, "mode": "pad", "gravity": "north", "background": "#00ff00"
+        }));
+        let padded = resize(&source(), &spec).to_rgba8();
+        // The 100x50 image sits at the top, the background below it
+        assert_eq!(padded.get_pixel(50, 99).0, [0, 255, 0, 255]);
+        assert_eq!(padded.get_pixel(10, 10).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn single_dimension_keeps_the_aspect_ratio() {
+        assert_eq!(resized(serde_json::json!({ "width": 100 })), (100, 50));
+        assert_eq!(resized(serde_json::json!({ "height": 50 })), (100, 50));
+        assert_eq!(resized(serde_json::json!({ "width": 3 })), (3, 2));
+        assert_eq!(resized(serde_json::json!({ "height": 1 })), (2, 1));
+        assert_eq!(resized(serde_json::json!({ "width": 1 })), (1, 1));
+        assert_eq!(resized(serde_json::json!({})), (400, 200));
+    }
+
+    #[test]
+    fn no_upscale_caps_at_the_source_size() {
+        assert_eq!(resized(serde_json::json!({ "width": 800, "height": 800, "no_upscale": true })), (400, 200));
+        assert_eq!(resized(serde_json::json!({ "width": 800, "no_upscale": true, "mode": "fill" })), (400, 200));
+        assert_eq!(
+            resized(serde_json::json!({ "width": 800, "height": 100, "mode": "exact", "no_upscale": true })),
+            (400, 100)
+        );
+        // Pad still produces the full canvas, with the source at its own size
+        assert_eq!(
+            resized(serde_json::json!({ "width": 800, "height": 800, "mode": "pad", "no_upscale": true })),
+            (800, 800)
+        );
+    }
+
+    #[test]
+    fn cover_crops_around_the_gravity() {
+        let crop = |gravity: &str| {
+            let spec = spec(serde_json::json!({ "width": 100, "height": 100, "mode": "cover", "gravity": gravity }));
+            let output = resize(&source(), &spec).to_rgba8();
+            assert_eq!(output.dimensions(), (100, 100));
+            (output.get_pixel(10, 50).0, output.get_pixel(90, 50).0)
+        };
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+
+        assert_eq!(crop("west"), (red, red));
+        assert_eq!(crop("north_west"), (red, red));
+        assert_eq!(crop("east"), (blue, blue));
+        assert_eq!(crop("south_east"), (blue, blue));
+        assert_eq!(crop("center"), (red, blue));
+    }
+
+    #[test]
+    fn rotation_sizes() {
+        assert_eq!(rotated_size(400, 200, 90.0, true), (200, 400));
+        assert_eq!(rotated_size(400, 200, -90.0, false), (200, 400));
+        assert_eq!(rotated_size(400, 200, 180.0, true), (400, 200));
+        assert_eq!(rotated_size(400, 200, 45.0, false), (400, 200));
+        assert_eq!(rotated_size(400, 200, 45.0, true), (424, 424));
+        assert_eq!(rotate(&source(), 30.0, true, Color::TRANSPARENT).dimensions(), rotated_size(400, 200, 30.0, true));
+    }
+}
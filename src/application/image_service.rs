@@ -1,148 +1,1083 @@
-use image::imageops;
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageFormat, ImageReader};
+use std::collections::HashSet;
 use std::io::Cursor;
-use crate::domain::image::{Image, ImageTransformation, ImageRepository};
+use std::ops::Range;
+use sha2::{Digest, Sha256};
+use crate::domain::image::{Image, ImageFilter, ImageTransformation, ImageRepository};
+use crate::domain::transformations::{Color, Operation};
+use crate::application::{encoding, filters, geometry};
+use crate::application::watermark::{self, FontLibrary, Overlays};
+use crate::application::derivative_cache::{CacheStatus, DerivativeCache};
+use crate::application::processing_pool::{ProcessingMetrics, ProcessingPool};
+use crate::application::limits::ImageLimits;
+use crate::application::webhook_service::WebhookService;
+use crate::domain::webhook::WebhookEvent;
+use crate::domain::formats::OutputFormat;
+use crate::domain::filename::{derived_filename, sanitize_filename, storage_key};
+use crate::core::config::{ParentDeletePolicy, Settings};
 use crate::core::error::ServiceError;
-use tokio::fs;
+use crate::infrastructure::storage::{ByteStream, StorageBackend};
+use std::sync::Arc;
+use futures_util::stream::{self, BoxStream, StreamExt};
 
-pub struct ImageProcessor;
+// Format and header information sniffed from uploaded bytes
+#[derive(Debug, Clone)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub orientation: Orientation,
+    pub mime_type: String,
+    pub extension: String,
+    pub width: u32,
+    pub height: u32,
+    pub color_type: String,
+    pub bit_depth: u16,
+}
+
+// Encoded transformation result together with the format it was encoded as
+#[derive(Debug, Clone)]
+pub struct ProcessedImage {
+    pub data: Vec<u8>,
+    pub format: OutputFormat,
+}
+
+// A validated transformation of an image the user owns, ready to be rendered
+#[derive(Debug, Clone)]
+pub struct TransformPlan {
+    pub image: Image,
+    pub transformations: ImageTransformation,
+    pub overlay_ids: Vec<String>,
+    pub cache_key: String,
+    // Derived from the source content hashes and the cache key
+    pub etag: String,
+    // The format was chosen from the Accept header, so responses vary on it
+    pub negotiated: bool,
+}
+
+// Guards lineage walks against malformed parent chains
+const MAX_LINEAGE_DEPTH: usize = 64;
+
+const STORAGE_DELETION_BATCH: i64 = 100;
+
+// A rendered batch item, with the plan it was rendered from
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub plan: TransformPlan,
+    pub processed: ProcessedImage,
+    pub cache: CacheStatus,
+    // The new image, when the batch asked for results to be saved
+    pub saved: Option<Image>,
+}
+
+// Items of a batch complete out of order; `index` is the position in the request
+#[derive(Debug)]
+pub struct BatchItem {
+    pub index: usize,
+    pub image_id: String,
+    pub outcome: Result<BatchResult, ServiceError>,
+}
+
+fn decode_error(err: image::ImageError) -> ServiceError {
+    match err {
+        image::ImageError::Limits(e) => ServiceError::ImageTooLarge(e.to_string()),
+        e => ServiceError::ValidationError(format!("Invalid image: {}", e)),
+    }
+}
+
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+pub struct ImageProcessor {
+    fonts: FontLibrary,
+    limits: ImageLimits,
+}
 
 impl ImageProcessor {
-    pub async fn process_image(
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            fonts: FontLibrary::new(&settings.fonts_path, &settings.default_font),
+            limits: ImageLimits::new(settings),
+        }
+    }
+
+    // Reads only the header, so this is cheap even for large images
+    pub fn inspect(&self, image_data: &[u8]) -> Result<ImageInfo, ServiceError> {
+        self.limits.check_input_size(image_data.len())?;
+        let format = image::guess_format(image_data)
+            .map_err(|_| ServiceError::UnsupportedMediaType("Uploaded file is not a recognised image".to_string()))?;
+
+        if !format.reading_enabled() {
+            return Err(ServiceError::UnsupportedMediaType(format!("{:?} images are not supported", format)));
+        }
+
+        let mut decoder = ImageReader::with_format(Cursor::new(image_data), format)
+            .into_decoder()
+            .map_err(|e| ServiceError::ValidationError(format!("Invalid image: {}", e)))?;
+
+        let (width, height) = decoder.dimensions();
+        self.limits.check_dimensions(width, height)?;
+        let color_type = decoder.color_type();
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+
+        Ok(ImageInfo {
+            format,
+            orientation,
+            mime_type: format.to_mime_type().to_string(),
+            extension: format.extensions_str().first().copied().unwrap_or("bin").to_string(),
+            width,
+            height,
+            color_type: format!("{:?}", color_type).to_lowercase(),
+            bit_depth: color_type.bits_per_pixel() / color_type.channel_count() as u16,
+        })
+    }
+
+    // CPU-bound; callers run it through the processing pool
+    pub fn process_image(
         &self,
         image_data: &[u8],
         transformations: &ImageTransformation,
-    ) -> Result<Vec<u8>, ServiceError> {
-        let mut img = image::load_from_memory(image_data)
-            .map_err(|e| ServiceError::ValidationError(format!("Invalid image: {}", e)))?;
+        overlays: &Overlays,
+    ) -> Result<ProcessedImage, ServiceError> {
+        let mut img = self.decode(image_data, transformations.auto_orient)?;
         
-        // Apply transformations
-        if let Some(resize) = &transformations.resize {
-            img = img.resize(resize.width, resize.height, imageops::FilterType::Lanczos3);
+        for operation in transformations.pipeline() {
+            if let Some((width, height)) = Self::grown_size(&img, &operation) {
+                self.limits.check_output(width, height)?;
+            }
+            img = self.apply_operation(img, &operation, overlays)?;
         }
         
-        if let Some(crop) = &transformations.crop {
-            img = img.crop(crop.x, crop.y, crop.width, crop.height);
-        }
+        let format = transformations.output_format()?;
+        let data = encoding::encode(&img, format, &transformations.encoder)?;
         
-        if let Some(rotate) = &transformations.rotate {
-            if *rotate == 90.0 {
-                img = img.rotate90();
-            } else if *rotate == 180.0 {
-                img = img.rotate180();
-            } else if *rotate == 270.0 {
-                img = img.rotate270();
-            }
+        Ok(ProcessedImage { data, format })
+    }
+
+    // Enforces the size limits from the header first, then caps allocations while decoding
+    pub fn decode(&self, image_data: &[u8], auto_orient: bool) -> Result<DynamicImage, ServiceError> {
+        self.limits.check_input_size(image_data.len())?;
+
+        let mut reader = ImageReader::new(Cursor::new(image_data)).with_guessed_format()?;
+        reader.limits(self.limits.decoder_limits());
+        let mut decoder = reader.into_decoder().map_err(decode_error)?;
+
+        let (width, height) = decoder.dimensions();
+        self.limits.check_dimensions(width, height)?;
+
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let mut img = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+
+        if auto_orient {
+            img.apply_orientation(orientation);
         }
-        
-        if let Some(filters) = &transformations.filters {
-            if filters.grayscale {
-                img = img.grayscale();
-            }
-            if let Some(blur) = &filters.blur {
-                img = img.blur(*blur);
-            }
+        Ok(img)
+    }
+
+    // Bakes the EXIF orientation into the pixels, re-encoding in the original format.
+    // Returns None when nothing needs to change or the format cannot be written back.
+    pub fn normalize_orientation(&self, image_data: &[u8], info: &ImageInfo) -> Result<Option<Vec<u8>>, ServiceError> {
+        if info.orientation == Orientation::NoTransforms || !info.format.writing_enabled() {
+            return Ok(None);
         }
-        
-        // Change format - use the new API
-        let format = transformations.format.as_deref().unwrap_or("jpeg");
+
+        let img = self.decode(image_data, true)?;
         let mut buffer = Cursor::new(Vec::new());
-        
-        match format.to_lowercase().as_str() {
-            "jpeg" | "jpg" => {
-                // For newer versions of image crate
-                img.write_to(&mut buffer, image::ImageFormat::Jpeg)
-                    .map_err(|e| ServiceError::ValidationError(format!("Failed to encode image: {}", e)))?;
+        img.write_to(&mut buffer, info.format)
+            .map_err(|e| ServiceError::ImageProcessingError(format!("Failed to encode image: {}", e)))?;
+        Ok(Some(buffer.into_inner()))
+    }
+
+    // Size of the largest buffer a step that can grow the image will allocate. Other steps keep
+    // or shrink the size, so an upload already accepted by the input limits may pass through them
+    // even when it is larger than the output limits.
+    fn grown_size(img: &DynamicImage, operation: &Operation) -> Option<(u32, u32)> {
+        let (width, height) = img.dimensions();
+        match operation {
+            Operation::Resize(resize) => Some(geometry::resize_extent(width, height, resize)),
+            Operation::Rotate { degrees, expand, .. } => Some(geometry::rotated_size(width, height, *degrees, *expand)),
+            _ => None,
+        }
+    }
+
+    fn apply_operation(
+        &self,
+        img: DynamicImage,
+        operation: &Operation,
+        overlays: &Overlays,
+    ) -> Result<DynamicImage, ServiceError> {
+        let img = match operation {
+            Operation::Resize(resize) => geometry::resize(&img, resize),
+            Operation::Crop(crop) => {
+                // Only checkable here, since earlier steps decide the size. A crop reaching past an
+                // edge is clipped to the image; one starting outside it would leave nothing.
+                let (width, height) = img.dimensions();
+                if crop.x >= width || crop.y >= height {
+                    return Err(ServiceError::ValidationError(format!(
+                        "crop at {},{} lies outside the {}x{} image",
+                        crop.x, crop.y, width, height
+                    )));
+                }
+                img.crop_imm(crop.x, crop.y, crop.width, crop.height)
             }
-            "png" => {
-                img.write_to(&mut buffer, image::ImageFormat::Png)
-                    .map_err(|e| ServiceError::ValidationError(format!("Failed to encode image: {}", e)))?;
+            Operation::Rotate { degrees, expand, background } => {
+                geometry::rotate(&img, *degrees, *expand, background.unwrap_or(Color::TRANSPARENT))
             }
-            _ => return Err(ServiceError::ValidationError("Unsupported format".to_string())),
-        }
-        
-        Ok(buffer.into_inner())
+            Operation::Flip { direction } => geometry::flip(&img, *direction),
+            Operation::Grayscale => img.grayscale(),
+            Operation::Sepia => filters::sepia(&img),
+            Operation::Brightness { value } => img.brighten(*value),
+            Operation::Contrast { value } => img.adjust_contrast(*value),
+            Operation::Saturation { factor } => filters::saturate(&img, *factor),
+            Operation::HueRotate { degrees } => img.huerotate(*degrees),
+            Operation::Gamma { value } => filters::gamma(&img, *value),
+            Operation::Tint { color, strength } => filters::tint(&img, *color, *strength),
+            Operation::Invert => filters::invert(img),
+            Operation::Posterize { levels } => filters::posterize(&img, *levels),
+            Operation::Threshold { level } => filters::threshold(&img, *level),
+            Operation::Blur { sigma } => img.blur(*sigma),
+            Operation::Watermark(watermark) => watermark::apply(&img, watermark, overlays, &self.fonts, &self.limits)?,
+        };
+        Ok(img)
     }
 }
 
 #[derive(Clone)]
 pub struct ImageService<R: ImageRepository> {
     image_repository: R,
-    storage_path: String,
+    storage: Arc<dyn StorageBackend>,
+    processor: Arc<ImageProcessor>,
+    pool: Arc<ProcessingPool>,
+    cache: Arc<DerivativeCache>,
+    webhooks: WebhookService,
+    batch_max_items: usize,
+    parent_delete_policy: ParentDeletePolicy,
+    trash_retention_secs: u64,
+    trash_purge_interval_secs: u64,
 }
 
 impl<R: ImageRepository> ImageService<R> {
-    pub fn new(image_repository: R, storage_path: String) -> Self {
-        let _ = std::fs::create_dir_all(&storage_path);
+    pub fn new(
+        image_repository: R,
+        storage: Arc<dyn StorageBackend>,
+        cache: DerivativeCache,
+        webhooks: WebhookService,
+        settings: &Settings,
+    ) -> Self {
         Self {
             image_repository,
-            storage_path,
+            storage,
+            processor: Arc::new(ImageProcessor::new(settings)),
+            pool: Arc::new(ProcessingPool::new(settings)),
+            cache: Arc::new(cache),
+            webhooks,
+            batch_max_items: settings.batch_max_items,
+            parent_delete_policy: settings.parent_delete_policy,
+            trash_retention_secs: settings.trash_retention_secs,
+            trash_purge_interval_secs: settings.trash_purge_interval_secs,
         }
     }
+
+    pub fn processing_metrics(&self) -> Arc<ProcessingMetrics> {
+        self.pool.metrics()
+    }
     
     pub async fn upload_image(
         &self,
         user_id: &str,
         filename: &str,
         image_data: &[u8],
+        auto_orient: bool,
     ) -> Result<Image, ServiceError> {
         let image_id = uuid::Uuid::new_v4().to_string();
-        let storage_filename = format!("{}_{}", image_id, filename);
-        let storage_path = format!("{}/{}", self.storage_path, storage_filename);
+        let original_filename = sanitize_filename(filename);
+        let mut info = self.processor.inspect(image_data)?;
+
+        let oriented = if auto_orient && info.orientation != Orientation::NoTransforms {
+            let data = image_data.to_vec();
+            let original = info.clone();
+            let processor = self.processor.clone();
+            self.pool.run(move || processor.normalize_orientation(&data, &original)).await?
+        } else {
+            None
+        };
+        let image_data = match &oriented {
+            Some(data) => {
+                info = self.processor.inspect(data)?;
+                data.as_slice()
+            }
+            None => image_data,
+        };
+        let storage_filename = storage_key(&image_id, &info.extension);
         
         // Save file
-        fs::write(&storage_path, image_data).await
-            .map_err(|e| ServiceError::DatabaseError(format!("Failed to save image file: {}", e)))?;
+        self.storage.put(&storage_filename, image_data).await?;
         
         // Create database record
         let image = Image {
             id: image_id,
             user_id: user_id.to_string(),
-            filename: storage_filename,
-            original_filename: filename.to_string(),
+            filename: storage_filename.clone(),
+            original_filename,
             file_size: image_data.len() as i64,
-            mime_type: "image/jpeg".to_string(),
-            storage_path: storage_path.clone(),
+            mime_type: info.mime_type,
+            storage_key: storage_filename,
+            width: Some(info.width as i64),
+            height: Some(info.height as i64),
+            color_type: Some(info.color_type),
+            bit_depth: Some(info.bit_depth as i64),
+            content_hash: Some(content_hash(image_data)),
+            parent_id: None,
+            transformation: None,
             created_at: None,
+            deleted_at: None,
+            trash_batch_id: None,
         };
         
-        self.image_repository.create_image(&image).await
+        let image = self.insert_stored_image(&image).await?;
+        self.notify_created(&image).await;
+        Ok(image)
+    }
+
+    // Inserts the row for an object stored just before. When that fails the object would be
+    // orphaned, so it is deleted, or queued for the purger if the delete fails too.
+    async fn insert_stored_image(&self, image: &Image) -> Result<Image, ServiceError> {
+        let err = match self.image_repository.create_image(image).await {
+            Ok(image) => return Ok(image),
+            Err(e) => e,
+        };
+        if let Err(delete_error) = self.storage.delete(&image.storage_key).await {
+            if let Err(queue_error) = self.image_repository.queue_storage_deletion(&image.storage_key).await {
+                eprintln!(
+                    "⚠️  Failed to clean up {} after a failed insert: {}; {}",
+                    image.storage_key, delete_error, queue_error
+                );
+            }
+        }
+        Err(err)
+    }
+
+    // Stores a transformation result as a new image of the same owner, linked to its source
+    pub async fn save_derivative(&self, plan: &TransformPlan, processed: &ProcessedImage) -> Result<Image, ServiceError> {
+        let image_id = uuid::Uuid::new_v4().to_string();
+        let storage_filename = storage_key(&image_id, processed.format.extension());
+        let transformation = serde_json::to_string(&plan.transformations)
+            .map_err(|e| ServiceError::ImageProcessingError(format!("Failed to serialize transformation: {}", e)))?;
+        // Formats that cannot be decoded (AVIF) are stored without dimensions
+        let info = self.processor.inspect(&processed.data).ok();
+
+        self.storage.put(&storage_filename, &processed.data).await?;
+
+        let image = Image {
+            id: image_id,
+            user_id: plan.image.user_id.clone(),
+            filename: storage_filename.clone(),
+            original_filename: derived_filename(&plan.image.original_filename, processed.format.extension()),
+            file_size: processed.data.len() as i64,
+            mime_type: processed.format.mime_type().to_string(),
+            storage_key: storage_filename,
+            width: info.as_ref().map(|info| info.width as i64),
+            height: info.as_ref().map(|info| info.height as i64),
+            color_type: info.as_ref().map(|info| info.color_type.clone()),
+            bit_depth: info.as_ref().map(|info| info.bit_depth as i64),
+            content_hash: Some(content_hash(&processed.data)),
+            parent_id: Some(plan.image.id.clone()),
+            transformation: Some(transformation),
+            created_at: None,
+            deleted_at: None,
+            trash_batch_id: None,
+        };
+
+        let image = self.insert_stored_image(&image).await?;
+        self.notify_created(&image).await;
+        Ok(image)
+    }
+
+    async fn notify_created(&self, image: &Image) {
+        self.webhooks
+            .emit(&image.user_id, WebhookEvent::ImageUploaded, serde_json::json!({
+                "image_id": image.id,
+                "original_filename": image.original_filename,
+                "file_size": image.file_size,
+                "mime_type": image.mime_type,
+                "width": image.width,
+                "height": image.height,
+                "parent_id": image.parent_id,
+            }))
+            .await;
+    }
+
+    // Images saved directly from transformations of `image_id`
+    pub async fn derivatives(&self, image_id: &str, user_id: &str) -> Result<Vec<Image>, ServiceError> {
+        let image = self.find_owned_image(image_id, user_id).await?;
+        let children = self.image_repository.find_children(&image.id).await?;
+        Ok(children.into_iter().filter(|child| child.deleted_at.is_none()).collect())
+    }
+
+    // The chain of images `image_id` was derived from, root first and ending with the image itself.
+    // The chain stops early where a parent is in the trash or was purged.
+    pub async fn lineage(&self, image_id: &str, user_id: &str) -> Result<Vec<Image>, ServiceError> {
+        let mut chain = vec![self.find_owned_image(image_id, user_id).await?];
+
+        while let Some(parent_id) = chain.last().and_then(|image| image.parent_id.clone()) {
+            if chain.len() >= MAX_LINEAGE_DEPTH {
+                break;
+            }
+            match self.image_repository.find_by_id(&parent_id).await? {
+                Some(parent) if parent.user_id == user_id && parent.deleted_at.is_none() => chain.push(parent),
+                _ => break,
+            }
+        }
+
+        chain.reverse();
+        Ok(chain)
+    }
+
+    // Every image derived from `image`, directly or not, parents before their children.
+    // Includes images in the trash.
+    async fn descendants(&self, image: &Image) -> Result<Vec<Image>, ServiceError> {
+        let mut found: Vec<Image> = Vec::new();
+        let mut next = 0;
+        let mut children = self.image_repository.find_children(&image.id).await?;
+        loop {
+            for child in children {
+                if child.id != image.id && !found.iter().any(|image| image.id == child.id) {
+                    found.push(child);
+                }
+            }
+            let Some(parent) = found.get(next) else {
+                return Ok(found);
+            };
+            children = self.image_repository.find_children(&parent.id).await?;
+            next += 1;
+        }
     }
     
     pub async fn get_image(&self, image_id: &str, user_id: &str) -> Result<(Image, Vec<u8>), ServiceError> {
+        let mut image = self.find_owned_image(image_id, user_id).await?;
+        let image_data = self.load_image_data(&mut image).await?;
+        
+        Ok((image, image_data))
+    }
+
+    pub async fn load_image_data(&self, image: &mut Image) -> Result<Vec<u8>, ServiceError> {
+        let image_data = self.storage.get(&image.storage_key).await?;
+        if image.content_hash.is_none() {
+            self.backfill_content_hash(image, &image_data).await?;
+        }
+        Ok(image_data)
+    }
+
+    pub async fn ensure_content_hash(&self, image: &mut Image) -> Result<(), ServiceError> {
+        if image.content_hash.is_none() {
+            let image_data = self.storage.get(&image.storage_key).await?;
+            self.backfill_content_hash(image, &image_data).await?;
+        }
+        Ok(())
+    }
+
+    // Streams the stored original, or just `range` of it, without buffering
+    pub async fn stream_image(&self, image: &Image, range: Option<Range<u64>>) -> Result<ByteStream, ServiceError> {
+        match range {
+            Some(range) => self.storage.stream_range(&image.storage_key, range).await,
+            None => self.storage.stream(&image.storage_key).await,
+        }
+    }
+
+    // Images uploaded before hashes were recorded get theirs on first access
+    async fn backfill_content_hash(&self, image: &mut Image, image_data: &[u8]) -> Result<String, ServiceError> {
+        let hash = content_hash(image_data);
+        self.image_repository.update_content_hash(&image.id, &hash).await?;
+        image.content_hash = Some(hash.clone());
+        Ok(hash)
+    }
+    
+    // Metadata only; the stored bytes are not read. Images in the trash are not found.
+    pub async fn find_owned_image(&self, image_id: &str, user_id: &str) -> Result<Image, ServiceError> {
+        let image = self.find_owned_image_any(image_id, user_id).await?;
+        if image.deleted_at.is_some() {
+            return Err(ServiceError::NotFound("Image not found".to_string()));
+        }
+        Ok(image)
+    }
+
+    // Owner of an image outside the trash, for requests authorized by a signed URL rather than a user
+    pub async fn image_owner(&self, image_id: &str) -> Result<String, ServiceError> {
+        match self.image_repository.find_by_id(image_id).await? {
+            Some(image) if image.deleted_at.is_none() => Ok(image.user_id),
+            _ => Err(ServiceError::NotFound("Image not found".to_string())),
+        }
+    }
+
+    async fn find_owned_image_any(&self, image_id: &str, user_id: &str) -> Result<Image, ServiceError> {
         let image = self.image_repository.find_by_id(image_id).await?
-            .ok_or(ServiceError::ValidationError("Image not found".to_string()))?;
+            .ok_or(ServiceError::NotFound("Image not found".to_string()))?;
         
         if image.user_id != user_id {
-            return Err(ServiceError::ValidationError("Access denied".to_string()));
+            return Err(ServiceError::Forbidden("Access denied".to_string()));
         }
-        
-        let image_data = fs::read(&image.storage_path).await
-            .map_err(|e| ServiceError::DatabaseError(format!("Failed to read image file: {}", e)))?;
-        
-        Ok((image, image_data))
+        Ok(image)
     }
     
     pub async fn list_images(&self, user_id: &str, page: i64, limit: i64) -> Result<Vec<Image>, ServiceError> {
         self.image_repository.find_by_user_id(user_id, page, limit).await
     }
     
-    pub async fn transform_image(
+    // Validates the pipeline once and resolves the images a batch addresses: either explicit ids,
+    // whose ownership is checked per item when rendering, or a filter over the user's own images
+    pub async fn plan_batch(
+        &self,
+        user_id: &str,
+        image_ids: Option<Vec<String>>,
+        filter: Option<ImageFilter>,
+        transformations: &ImageTransformation,
+    ) -> Result<Vec<String>, ServiceError> {
+        transformations.validate()?;
+
+        let too_many = || ServiceError::ValidationError(format!(
+            "A batch may address at most {} images",
+            self.batch_max_items
+        ));
+        let image_ids: Vec<String> = match (image_ids, filter) {
+            // The limit applies before de-duplication, so the request's size is bounded up front
+            (Some(image_ids), None) => {
+                if image_ids.len() > self.batch_max_items {
+                    return Err(too_many());
+                }
+                let mut seen = HashSet::with_capacity(image_ids.len());
+                image_ids.into_iter().filter(|image_id| seen.insert(image_id.clone())).collect()
+            }
+            (None, Some(filter)) => self
+                .image_repository
+                .find_matching(user_id, &filter, self.batch_max_items as i64 + 1)
+                .await?
+                .into_iter()
+                .map(|image| image.id)
+                .collect(),
+            _ => {
+                return Err(ServiceError::ValidationError(
+                    "Exactly one of image_ids or filter is required".to_string(),
+                ));
+            }
+        };
+
+        if image_ids.is_empty() {
+            return Err(ServiceError::ValidationError("The batch addresses no images".to_string()));
+        }
+        if image_ids.len() > self.batch_max_items {
+            return Err(too_many());
+        }
+        Ok(image_ids)
+    }
+
+    // Validates and checks ownership without rendering, so callers can answer
+    // conditional requests from the ETag alone. `accepted` is used to resolve "auto" formats.
+    pub async fn plan_transform(
         &self,
         image_id: &str,
         user_id: &str,
-        transformations: ImageTransformation,
-    ) -> Result<Vec<u8>, ServiceError> {
-        let (_image, original_data) = self.get_image(image_id, user_id).await?;
+        mut transformations: ImageTransformation,
+        accepted: &[OutputFormat],
+    ) -> Result<TransformPlan, ServiceError> {
+        transformations.validate()?;
         
-        let processor = ImageProcessor;
-        processor.process_image(&original_data, &transformations).await
+        let mut image = self.find_owned_image(image_id, user_id).await?;
+        let negotiated = transformations.resolve_auto_format(accepted, image.has_alpha());
+
+        // Watermark overlays go through the same ownership checks as the source image
+        let overlay_ids = transformations.watermark_image_ids();
+        let mut source_hashes = Vec::new();
+        for overlay_id in &overlay_ids {
+            let overlay = self.find_owned_image(overlay_id, user_id).await?;
+            source_hashes.push(overlay.content_hash.unwrap_or_default());
+        }
+
+        let source_hash = match image.content_hash.clone() {
+            Some(hash) => hash,
+            None => {
+                let image_data = self.storage.get(&image.storage_key).await?;
+                self.backfill_content_hash(&mut image, &image_data).await?
+            }
+        };
+        source_hashes.insert(0, source_hash);
+
+        let cache_key = DerivativeCache::cache_key(&image.id, &transformations)?;
+        let etag = content_hash(format!("{}:{}", source_hashes.join(":"), cache_key).as_bytes());
+
+        Ok(TransformPlan { image, transformations, overlay_ids, cache_key, etag, negotiated })
+    }
+
+    // Renders without announcing it; GET renders, which CDNs and <img> tags repeat, use this
+    pub async fn execute_transform(&self, plan: &TransformPlan) -> Result<(ProcessedImage, CacheStatus), ServiceError> {
+        self.cached_render(plan).await
+    }
+
+    // Renders for an explicit transform request and sends image.transformed
+    pub async fn transform(&self, plan: &TransformPlan) -> Result<(ProcessedImage, CacheStatus), ServiceError> {
+        let (processed, cache) = self.execute_transform(plan).await?;
+        self.emit_transformed(&plan.image, &processed, cache).await;
+        Ok((processed, cache))
+    }
+
+    pub async fn emit_transformed(&self, image: &Image, processed: &ProcessedImage, cache: CacheStatus) {
+        self.webhooks
+            .emit(&image.user_id, WebhookEvent::ImageTransformed, serde_json::json!({
+                "image_id": image.id,
+                "format": processed.format.name(),
+                "size": processed.data.len(),
+                "cache": cache.as_str(),
+            }))
+            .await;
+    }
+
+    async fn cached_render(&self, plan: &TransformPlan) -> Result<(ProcessedImage, CacheStatus), ServiceError> {
+        if !self.cache.is_enabled() {
+            let processed = self.render(plan).await?;
+            return Ok((processed, CacheStatus::Bypass));
+        }
+
+        // Cache failures only cost a re-render, they never fail the request
+        match self.cache.get(&plan.cache_key).await {
+            Ok(Some(processed)) => return Ok((processed, CacheStatus::Hit)),
+            Ok(None) => {}
+            Err(e) => eprintln!("⚠️  Derivative cache lookup failed: {}", e),
+        }
+
+        let processed = self.render(plan).await?;
+        if let Err(e) = self.cache.put(&plan.cache_key, &plan.image.id, &plan.overlay_ids, &processed).await {
+            eprintln!("⚠️  Failed to cache derivative: {}", e);
+        }
+        Ok((processed, CacheStatus::Miss))
+    }
+
+    async fn render(&self, plan: &TransformPlan) -> Result<ProcessedImage, ServiceError> {
+        let original_data = self.storage.get(&plan.image.storage_key).await?;
+
+        let mut overlay_data = Vec::new();
+        for overlay_id in &plan.overlay_ids {
+            let (_, data) = self.get_image(overlay_id, &plan.image.user_id).await?;
+            overlay_data.push((overlay_id.clone(), data));
+        }
+
+        // Decoding, every step and encoding all happen on the processing pool
+        let processor = self.processor.clone();
+        let transformations = plan.transformations.clone();
+        self.pool
+            .run(move || {
+                let mut overlays = Overlays::new();
+                for (overlay_id, data) in overlay_data {
+                    overlays.insert(overlay_id, processor.decode(&data, true)?);
+                }
+                processor.process_image(&original_data, &transformations, &overlays)
+            })
+            .await
+    }
+    // Moves the image to the trash, where it stays restorable until the retention period ends.
+    // Images saved from it are handled according to the parent delete policy: cascade trashes
+    // them along with it, block refuses while any are outside the trash and orphan leaves them
+    // alone until the parent is purged.
+    pub async fn delete_image(&self, image_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        let image = self.find_owned_image(image_id, user_id).await?;
+        let deleted_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+        let live: Vec<Image> = self.descendants(&image).await?
+            .into_iter()
+            .filter(|descendant| descendant.deleted_at.is_none())
+            .collect();
+        let mut ids = vec![image.id.clone()];
+        if !live.is_empty() {
+            match self.parent_delete_policy {
+                ParentDeletePolicy::Block => {
+                    return Err(ServiceError::Conflict(format!(
+                        "Image has {} derived images; delete them first",
+                        live.len()
+                    )));
+                }
+                ParentDeletePolicy::Orphan => {}
+                // One batch with the parent, so restoring the parent brings exactly these back
+                ParentDeletePolicy::Cascade => ids.extend(live.into_iter().map(|descendant| descendant.id)),
+            }
+        }
+
+        let batch_id = uuid::Uuid::new_v4().to_string();
+        let trashed = self.image_repository.trash_images(&ids, user_id, &deleted_at, &batch_id).await?;
+        if trashed.is_empty() {
+            // Deleted by a concurrent request since the ownership check
+            return Err(ServiceError::NotFound("Image not found".to_string()));
+        }
+        for id in trashed {
+            self.webhooks
+                .emit(user_id, WebhookEvent::ImageDeleted, serde_json::json!({
+                    "image_id": id,
+                    "permanent": false,
+                }))
+                .await;
+        }
+        Ok(())
+    }
+
+    pub async fn list_trash(&self, user_id: &str, page: i64, limit: i64) -> Result<Vec<Image>, ServiceError> {
+        self.image_repository.find_trashed(user_id, page, limit).await
+    }
+
+    // Takes the image out of the trash, along with derived images trashed by the same delete
+    pub async fn restore_image(&self, image_id: &str, user_id: &str) -> Result<Image, ServiceError> {
+        let mut image = self.find_owned_image_any(image_id, user_id).await?;
+        if image.deleted_at.take().is_none() {
+            return Err(ServiceError::Conflict("Image is not in the trash".to_string()));
+        }
+
+        let mut ids = vec![image.id.clone()];
+        if let Some(batch_id) = image.trash_batch_id.take() {
+            ids.extend(
+                self.descendants(&image).await?
+                    .into_iter()
+                    .filter(|descendant| descendant.trash_batch_id.as_deref() == Some(batch_id.as_str()))
+                    .map(|descendant| descendant.id),
+            );
+        }
+
+        if !self.image_repository.restore_images(&ids, user_id).await? {
+            // Restored or purged by a concurrent request
+            return Err(ServiceError::NotFound("Image not found in the trash".to_string()));
+        }
+        Ok(image)
+    }
+
+    // Permanently deletes everything in the user's trash. Returns how many images were purged.
+    pub async fn empty_trash(&self, user_id: &str) -> Result<usize, ServiceError> {
+        let mut purged = 0;
+        loop {
+            let images = self.image_repository.find_trashed(user_id, 1, STORAGE_DELETION_BATCH).await?;
+            if images.is_empty() {
+                return Ok(purged);
+            }
+            for image in &images {
+                self.purge_image(image).await?;
+                purged += 1;
+            }
+        }
+    }
+
+    // Permanently deletes images whose retention period has ended
+    pub async fn purge_expired_trash(&self) -> Result<usize, ServiceError> {
+        // A retention too long to represent never expires anything
+        let cutoff = i64::try_from(self.trash_retention_secs)
+            .ok()
+            .and_then(chrono::TimeDelta::try_seconds)
+            .and_then(|retention| chrono::Utc::now().checked_sub_signed(retention))
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+
+        let mut purged = 0;
+        loop {
+            let images = self.image_repository.find_expired_trash(&cutoff, STORAGE_DELETION_BATCH).await?;
+            if images.is_empty() {
+                return Ok(purged);
+            }
+            for image in &images {
+                self.purge_image(image).await?;
+                purged += 1;
+            }
+        }
+    }
+
+    // Deletes the image, its stored file and its cached derivatives. Derived images still around
+    // lose their parent; under cascade they were trashed with it and are purged alongside.
+    async fn purge_image(&self, image: &Image) -> Result<(), ServiceError> {
+        self.image_repository.clear_parent(&image.id).await?;
+        self.remove_image(image).await?;
+        Ok(())
+    }
+
+    async fn remove_image(&self, image: &Image) -> Result<bool, ServiceError> {
+        // Rows go first, in one transaction that queues the files; the files follow
+        let Some(keys) = self.image_repository.delete_image(&image.id, &image.user_id).await? else {
+            return Ok(false);
+        };
+        // Only this image's files: anything else queued is left to the background purger
+        for key in keys {
+            if let Err(e) = self.delete_stored(&key).await {
+                eprintln!("⚠️  Failed to delete stored file {}, will retry in the background: {}", key, e);
+            }
+        }
+        self.webhooks
+            .emit(&image.user_id, WebhookEvent::ImageDeleted, serde_json::json!({
+                "image_id": image.id,
+                "permanent": true,
+            }))
+            .await;
+        Ok(true)
+    }
+
+    async fn delete_stored(&self, key: &str) -> Result<(), ServiceError> {
+        self.storage.delete(key).await?;
+        self.image_repository.complete_storage_deletion(key).await
+    }
+
+    // Deletes stored objects still queued by row deletions, e.g. because storage was unreachable
+    // or the process stopped in between. Returns how many objects were deleted.
+    pub async fn purge_deleted_files(&self) -> Result<usize, ServiceError> {
+        let mut purged = 0;
+        loop {
+            let keys = self.image_repository.pending_storage_deletions(STORAGE_DELETION_BATCH).await?;
+            if keys.is_empty() {
+                return Ok(purged);
+            }
+            for key in keys {
+                self.delete_stored(&key).await?;
+                purged += 1;
+            }
+        }
+    }
+}
+
+impl<R: ImageRepository + Clone + Send + Sync + 'static> ImageService<R> {
+    // Purges expired trash and finishes queued file deletions now and then every purge interval.
+    // Failures are logged and retried on the next tick, so unreachable storage never stops startup.
+    pub fn start_trash_purger(&self) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(service.trash_purge_interval_secs));
+            loop {
+                interval.tick().await;
+                match service.purge_deleted_files().await {
+                    Ok(0) => {}
+                    Ok(purged) => println!("🧹 Deleted {} files left over from earlier deletions", purged),
+                    Err(e) => eprintln!("⚠️  Failed to delete queued files, will retry: {}", e),
+                }
+                match service.purge_expired_trash().await {
+                    Ok(0) => {}
+                    Ok(purged) => println!("🗑️  Purged {} images from the trash", purged),
+                    Err(e) => eprintln!("⚠️  Failed to purge expired trash: {}", e),
+                }
+            }
+        });
+    }
+
+    // Renders each image with the same pipeline, as many at a time as the processing pool runs,
+    // yielding items as they complete. Failures are reported per item and do not stop the batch.
+    pub fn transform_batch(
+        &self,
+        user_id: &str,
+        image_ids: Vec<String>,
+        transformations: ImageTransformation,
+        save: bool,
+    ) -> BoxStream<'static, BatchItem> {
+        let service = self.clone();
+        let user_id = user_id.to_string();
+
+        stream::iter(image_ids.into_iter().enumerate())
+            .map(move |(index, image_id)| {
+                let service = service.clone();
+                let user_id = user_id.clone();
+                let transformations = transformations.clone();
+                async move {
+                    let outcome = async {
+                        let plan = service.plan_transform(&image_id, &user_id, transformations, &[]).await?;
+                        let (processed, cache) = service.transform(&plan).await?;
+                        let saved = match save {
+                            true => Some(service.save_derivative(&plan, &processed).await?),
+                            false => None,
+                        };
+                        Ok(BatchResult { plan, processed, cache, saved })
+                    }
+                    .await;
+                    BatchItem { index, image_id, outcome }
+                }
+            })
+            .buffer_unordered(self.pool.concurrency())
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn processor() -> ImageProcessor {
+        ImageProcessor::new(&Settings::default())
+    }
+
+    fn png(img: &DynamicImage) -> Vec<u8> {
+        let mut buffer = Cursor::new(Vec::new());
+        img.write_to(&mut buffer, ImageFormat::Png).unwrap();
+        buffer.into_inner()
+    }
+
+    // Same 2x2 fixture as the filter tests
+    fn fixture() -> Vec<u8> {
+        let pixels = [[200, 100, 50, 255], [0, 0, 0, 128], [255, 255, 255, 255], [10, 150, 240, 0]];
+        png(&DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 2, |x, y| Rgba(pixels[(y * 2 + x) as usize]))))
+    }
+
+    fn transformation(json: serde_json::Value) -> ImageTransformation {
+        let transformation: ImageTransformation = serde_json::from_value(json).unwrap();
+        transformation.validate().unwrap();
+        transformation
+    }
+
+    fn render(source: &[u8], json: serde_json::Value) -> Result<DynamicImage, ServiceError> {
+        let processed = processor().process_image(source, &transformation(json), &Overlays::new())?;
+        Ok(image::load_from_memory(&processed.data).unwrap())
+    }
+
+    // A valid PNG whose IHDR claims other dimensions, with the chunk CRC fixed up to match
+    fn png_claiming(width: u32, height: u32) -> Vec<u8> {
+        let mut data = png(&DynamicImage::ImageRgb8(image::RgbImage::new(1, 1)));
+        data[16..20].copy_from_slice(&width.to_be_bytes());
+        data[20..24].copy_from_slice(&height.to_be_bytes());
+        let crc = crc32(&data[12..29]);
+        data[29..33].copy_from_slice(&crc.to_be_bytes());
+        data
+    }
+
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for byte in bytes {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            }
+        }
+        !crc
+    }
+
+    fn pixels(json: serde_json::Value) -> Vec<[u8; 4]> {
+        render(&fixture(), json).unwrap().to_rgba8().pixels().map(|pixel| pixel.0).collect()
+    }
+
+    #[test]
+    fn crops_must_start_inside_the_image() {
+        let source = png(&DynamicImage::ImageRgb8(image::RgbImage::new(10, 8)));
+        let crop = |x: u32, y: u32| {
+            render(&source, serde_json::json!({ "crop": { "x": x, "y": y, "width": 4, "height": 4 }, "format": "png" }))
+        };
+
+        assert_eq!(crop(0, 0).unwrap().dimensions(), (4, 4));
+        // Reaching past the edge is clipped
+        assert_eq!(crop(8, 6).unwrap().dimensions(), (2, 2));
+        for (x, y) in [(10, 0), (0, 8), (50, 50)] {
+            assert!(matches!(crop(x, y), Err(ServiceError::ValidationError(_))), "crop at {},{}", x, y);
+        }
+
+        // Checked against the size left by earlier steps
+        let after_resize = render(&source, serde_json::json!({
+            "operations": [
+                { "op": "resize", "width": 5, "mode": "exact", "height": 4 },
+                { "op": "crop", "x": 6, "y": 0, "width": 2, "height": 2 }
+            ],
+            "format": "png"
+        }));
+        assert!(matches!(after_resize, Err(ServiceError::ValidationError(_))));
+    }
+
+    #[test]
+    fn brightness_golden() {
+        assert_eq!(
+            pixels(serde_json::json!({ "operations": [{ "op": "brightness", "value": 20 }], "format": "png" })),
+            [[220, 120, 70, 255], [20, 20, 20, 128], [255, 255, 255, 255], [30, 170, 255, 0]]
+        );
+    }
+
+    #[test]
+    fn contrast_golden() {
+        assert_eq!(
+            pixels(serde_json::json!({ "operations": [{ "op": "contrast", "value": 50.0 }], "format": "png" })),
+            [[255, 65, 0, 255], [0, 0, 0, 128], [255, 255, 255, 255], [0, 178, 255, 0]]
+        );
+    }
+
+    #[test]
+    fn hue_rotate_golden() {
+        assert_eq!(
+            pixels(serde_json::json!({ "operations": [{ "op": "hue_rotate", "degrees": 120 }], "format": "png" })),
+            [[17, 151, 80, 255], [0, 0, 0, 128], [255, 254, 254, 255], [255, 75, 171, 0]]
+        );
+    }
+
+    #[test]
+    fn filters_apply_in_the_order_given() {
+        let brighten_first = serde_json::json!({
+            "operations": [{ "op": "brightness", "value": 10 }, { "op": "threshold", "level": 120 }],
+            "format": "png"
+        });
+        let threshold_first = serde_json::json!({
+            "operations": [{ "op": "threshold", "level": 120 }, { "op": "brightness", "value": 10 }],
+            "format": "png"
+        });
+        // Brightening lifts the blue (luma 118.4) over the threshold
+        assert_eq!(
+            pixels(brighten_first.clone()),
+            [[255, 255, 255, 255], [0, 0, 0, 128], [255, 255, 255, 255], [255, 255, 255, 0]]
+        );
+        assert_eq!(
+            pixels(threshold_first),
+            [[255, 255, 255, 255], [10, 10, 10, 128], [255, 255, 255, 255], [10, 10, 10, 0]]
+        );
+        assert_eq!(pixels(brighten_first.clone()), pixels(brighten_first));
+    }
+
+    #[test]
+    fn rotation_corners_follow_the_background_in_jpeg() {
+        let source = png(&DynamicImage::ImageRgb8(image::RgbImage::from_pixel(64, 64, image::Rgb([0, 0, 255]))));
+        let corner = |json: serde_json::Value| render(&source, json).unwrap().to_rgb8().get_pixel(0, 0).0;
+
+        let [r, g, b] = corner(serde_json::json!({
+            "operations": [{ "op": "rotate", "degrees": 45 }], "format": "jpeg"
+        }));
+        assert!(r > 245 && g > 245 && b > 245, "transparent corner should flatten to white, got {:?}", [r, g, b]);
+
+        let [r, g, b] = corner(serde_json::json!({
+            "operations": [{ "op": "rotate", "degrees": 45, "background": "#ff0000" }], "format": "jpeg"
+        }));
+        assert!(r > 240 && g < 15 && b < 15, "corner should use the background, got {:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn oversized_headers_are_refused_before_decoding() {
+        let too_wide = png_claiming(100_000, 1);
+        assert!(matches!(processor().inspect(&too_wide), Err(ServiceError::ImageTooLarge(_))));
+        assert!(matches!(processor().decode(&too_wide, false), Err(ServiceError::ImageTooLarge(_))));
+
+        // Within the width and height limits, over MAX_IMAGE_PIXELS
+        let too_many_pixels = png_claiming(16_000, 16_000);
+        assert!(matches!(processor().inspect(&too_many_pixels), Err(ServiceError::ImageTooLarge(_))));
+        assert!(matches!(
+            render(&too_many_pixels, serde_json::json!({ "operations": [{ "op": "sepia" }] })),
+            Err(ServiceError::ImageTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn uploads_wider_than_the_output_limit_take_size_preserving_steps() {
+        // Accepted by the input limit (16384) but wider than the output limit (8192)
+        let wide = png(&DynamicImage::ImageRgb8(image::RgbImage::new(9000, 2)));
+        assert_eq!(processor().inspect(&wide).unwrap().width, 9000);
+
+        for op in [serde_json::json!({ "op": "sepia" }), serde_json::json!({ "op": "blur", "sigma": 0.5 })] {
+            let img = render(&wide, serde_json::json!({ "operations": [op], "format": "png" })).unwrap();
+            assert_eq!(img.dimensions(), (9000, 2));
+        }
+
+        let img = render(&wide, serde_json::json!({ "operations": [{ "op": "resize", "width": 900 }], "format": "png" })).unwrap();
+        assert_eq!(img.width(), 900);
+    }
+
+    #[test]
+    fn steps_that_grow_past_the_output_limit_are_refused() {
+        let source = png(&DynamicImage::ImageRgb8(image::RgbImage::new(100, 100)));
+        assert!(matches!(
+            render(&source, serde_json::json!({ "operations": [{ "op": "resize", "width": 9000 }] })),
+            Err(ServiceError::ImageTooLarge(_))
+        ));
+
+        let wide = png(&DynamicImage::ImageRgb8(image::RgbImage::new(9000, 2)));
+        assert!(matches!(
+            render(&wide, serde_json::json!({ "operations": [{ "op": "rotate", "degrees": 90 }] })),
+            Err(ServiceError::ImageTooLarge(_))
+        ));
     }
-    #[allow(dead_code)]
-    pub async fn delete_image(&self, image_id: &str, user_id: &str) -> Result<bool, ServiceError> {
-    // First verify the image exists and belongs to the user
-    let _ = self.get_image(image_id, user_id).await?;
-    
-    // Delete from database
-    self.image_repository.delete_image(image_id, user_id).await
 }
-}
\ No newline at end of file
@@ -0,0 +1,297 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use crate::application::derivative_cache::CacheStatus;
+use crate::application::image_service::{ImageService, ProcessedImage};
+use crate::application::webhook_service::WebhookService;
+use crate::domain::formats::OutputFormat;
+use crate::domain::image::{Image, ImageRepository, ImageTransformation};
+use crate::domain::job::{Job, JobRepository, JobStatus};
+use crate::domain::webhook::WebhookEvent;
+use crate::core::config::Settings;
+use crate::core::error::ServiceError;
+use crate::infrastructure::storage::{ByteStream, StorageBackend};
+
+// Retry delays double from this base and never exceed the cap
+const RETRY_BASE_SECS: u64 = 2;
+const RETRY_MAX_SECS: u64 = 300;
+// How often results past their retention are looked for
+const RESULT_PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+// Errors caused by the request itself fail the job straight away; anything else may be transient
+fn is_retryable(err: &ServiceError) -> bool {
+    !matches!(
+        err,
+        ServiceError::ValidationError(_)
+            | ServiceError::NotFound(_)
+            | ServiceError::Forbidden(_)
+            | ServiceError::UnsupportedMediaType(_)
+            | ServiceError::PayloadTooLarge(_)
+            | ServiceError::ImageTooLarge(_)
+    )
+}
+
+fn retry_delay(attempts: i64) -> Duration {
+    let exponent = attempts.clamp(1, 16) as u32 - 1;
+    Duration::from_secs((RETRY_BASE_SECS << exponent).min(RETRY_MAX_SECS))
+}
+
+fn result_key(job_id: &str, format: OutputFormat) -> String {
+    format!("jobs/{}.{}", job_id, format.extension())
+}
+
+// Runs transformations in the background. Jobs live in the database, so queued work
+// survives a restart and any worker may pick it up.
+#[derive(Clone)]
+pub struct JobService<R: ImageRepository> {
+    image_service: ImageService<R>,
+    job_repository: Arc<dyn JobRepository>,
+    storage: Arc<dyn StorageBackend>,
+    webhooks: WebhookService,
+    wake: Arc<Notify>,
+    workers: usize,
+    max_attempts: i64,
+    poll_interval: Duration,
+    result_retention_secs: u64,
+}
+
+impl<R: ImageRepository + Clone + Send + Sync + 'static> JobService<R> {
+    pub fn new(
+        image_service: ImageService<R>,
+        job_repository: Arc<dyn JobRepository>,
+        storage: Arc<dyn StorageBackend>,
+        webhooks: WebhookService,
+        settings: &Settings,
+    ) -> Self {
+        Self {
+            image_service,
+            job_repository,
+            storage,
+            webhooks,
+            wake: Arc::new(Notify::new()),
+            workers: settings.job_workers,
+            max_attempts: settings.job_max_attempts as i64,
+            poll_interval: Duration::from_millis(settings.job_poll_interval_ms),
+            result_retention_secs: settings.job_result_retention_secs,
+        }
+    }
+
+    // Everything that can be checked up front is, so a bad request fails here rather than in a worker
+    pub async fn enqueue(
+        &self,
+        image_id: &str,
+        user_id: &str,
+        transformations: ImageTransformation,
+    ) -> Result<Job, ServiceError> {
+        self.image_service.plan_transform(image_id, user_id, transformations.clone(), &[]).await?;
+
+        let transformation = serde_json::to_string(&transformations)
+            .map_err(|e| ServiceError::ValidationError(format!("Invalid transformation: {}", e)))?;
+        let job = Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            image_id: image_id.to_string(),
+            transformation,
+            status: JobStatus::Queued.as_str().to_string(),
+            progress: 0,
+            attempts: 0,
+            max_attempts: self.max_attempts,
+            error: None,
+            result_key: None,
+            result_format: None,
+            available_at: now_millis(),
+            created_at: None,
+            updated_at: None,
+            completed_at: None,
+        };
+
+        let job = self.job_repository.create_job(&job).await?;
+        self.wake.notify_one();
+        Ok(job)
+    }
+
+    pub async fn get_job(&self, job_id: &str, user_id: &str) -> Result<Job, ServiceError> {
+        let job = self.job_repository.find_by_id(job_id).await?
+            .ok_or(ServiceError::NotFound("Job not found".to_string()))?;
+
+        if job.user_id != user_id {
+            return Err(ServiceError::Forbidden("Access denied".to_string()));
+        }
+        Ok(job)
+    }
+
+    // The stored result of a succeeded job, with its format. Results of images in the trash are
+    // withheld; purging the image deletes them.
+    pub async fn job_result(&self, job_id: &str, user_id: &str) -> Result<(Job, OutputFormat, ByteStream), ServiceError> {
+        let job = self.get_job(job_id, user_id).await?;
+        let (Some(key), Some(format)) = (&job.result_key, &job.result_format) else {
+            if job.status == JobStatus::Succeeded.as_str() {
+                return Err(ServiceError::NotFound("Job result is no longer available".to_string()));
+            }
+            return Err(ServiceError::Conflict(format!("Job is {}, no result is available", job.status)));
+        };
+        let format = OutputFormat::from_name(format)
+            .ok_or_else(|| ServiceError::ImageProcessingError(format!("Unknown result format '{}'", format)))?;
+        self.image_service.image_owner(&job.image_id).await?;
+
+        let stream = self.storage.stream(key).await?;
+        Ok((job, format, stream))
+    }
+
+    // Jobs interrupted by a shutdown are requeued before the workers start, unless the
+    // interrupted run was their last attempt: a job that keeps crashing the process must not
+    // be retried forever.
+    pub async fn start_workers(&self) -> Result<(), ServiceError> {
+        let failed = self.job_repository
+            .fail_exhausted_running("Interrupted during its final attempt")
+            .await?;
+        if !failed.is_empty() {
+            println!("🛑 Failed {} interrupted jobs with no attempts left", failed.len());
+        }
+        for job_id in &failed {
+            self.notify_completed(job_id).await;
+        }
+
+        let requeued = self.job_repository.requeue_running().await?;
+        if requeued > 0 {
+            println!("🔁 Requeued {} interrupted jobs", requeued);
+        }
+
+        for _ in 0..self.workers {
+            let service = self.clone();
+            tokio::spawn(async move { service.worker_loop().await });
+        }
+        self.start_result_purger();
+        Ok(())
+    }
+
+    // Expires results past their retention now and then every purge interval
+    fn start_result_purger(&self) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RESULT_PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match service.expire_results().await {
+                    Ok(0) => {}
+                    Ok(expired) => println!("🗑️  Expired {} job results", expired),
+                    Err(e) => eprintln!("⚠️  Failed to expire job results: {}", e),
+                }
+            }
+        });
+    }
+
+    // Result objects are queued for deletion with the rows updated, then deleted
+    pub async fn expire_results(&self) -> Result<u64, ServiceError> {
+        // A retention too long to represent never expires anything
+        let cutoff = i64::try_from(self.result_retention_secs)
+            .ok()
+            .and_then(chrono::TimeDelta::try_seconds)
+            .and_then(|retention| chrono::Utc::now().checked_sub_signed(retention))
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+
+        let expired = self.job_repository.expire_results(&cutoff).await?;
+        if expired > 0 {
+            self.image_service.purge_deleted_files().await?;
+        }
+        Ok(expired)
+    }
+
+    async fn worker_loop(&self) {
+        loop {
+            match self.job_repository.claim_next(now_millis()).await {
+                Ok(Some(job)) => self.run_job(job).await,
+                Ok(None) => {
+                    // Woken early by new jobs; the interval picks up retries that became due
+                    let _ = tokio::time::timeout(self.poll_interval, self.wake.notified()).await;
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Failed to claim job: {}", e);
+                    tokio::time::sleep(self.poll_interval).await;
+                }
+            }
+        }
+    }
+
+    async fn run_job(&self, job: Job) {
+        let result = match self.execute(&job).await {
+            Ok((image, processed, cache)) => {
+                let key = result_key(&job.id, processed.format);
+                self.storage.put(&key, &processed.data).await.map(|()| (key, image, processed, cache))
+            }
+            Err(e) => Err(e),
+        };
+
+        let outcome = match result {
+            Ok((key, image, processed, cache)) => {
+                let succeeded = self.job_repository.mark_succeeded(&job.id, &key, processed.format.name()).await;
+                if succeeded.is_ok() {
+                    self.image_service.emit_transformed(&image, &processed, cache).await;
+                }
+                succeeded.map(|()| true)
+            }
+            Err(e) => self.record_failure(&job, e).await,
+        };
+
+        match outcome {
+            Ok(true) => self.notify_completed(&job.id).await,
+            Ok(false) => {}
+            Err(e) => eprintln!("⚠️  Failed to record outcome of job {}: {}", job.id, e),
+        }
+    }
+
+    // Sent once a job has reached its final state, whether it succeeded or failed
+    async fn notify_completed(&self, job_id: &str) {
+        let job = match self.job_repository.find_by_id(job_id).await {
+            Ok(Some(job)) => job,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("⚠️  Failed to load job {} for webhooks: {}", job_id, e);
+                return;
+            }
+        };
+
+        self.webhooks
+            .emit(&job.user_id, WebhookEvent::JobCompleted, serde_json::json!({
+                "job_id": job.id,
+                "image_id": job.image_id,
+                "status": job.status,
+                "error": job.error,
+                "result_url": job.result_key.as_ref().map(|_| format!("/api/jobs/{}/result", job.id)),
+            }))
+            .await;
+    }
+
+    async fn execute(&self, job: &Job) -> Result<(Image, ProcessedImage, CacheStatus), ServiceError> {
+        let transformations: ImageTransformation = serde_json::from_str(&job.transformation)
+            .map_err(|e| ServiceError::ValidationError(format!("Invalid stored transformation: {}", e)))?;
+
+        // Ownership is checked again: the image may have been deleted since the job was queued
+        let plan = self.image_service.plan_transform(&job.image_id, &job.user_id, transformations, &[]).await?;
+        self.job_repository.update_progress(&job.id, 10).await?;
+
+        let (processed, cache) = self.image_service.execute_transform(&plan).await?;
+        self.job_repository.update_progress(&job.id, 90).await?;
+
+        Ok((plan.image, processed, cache))
+    }
+
+    // Returns true when the job has failed for good rather than being retried
+    async fn record_failure(&self, job: &Job, err: ServiceError) -> Result<bool, ServiceError> {
+        let message = err.to_string();
+        if is_retryable(&err) && job.attempts < job.max_attempts {
+            let available_at = now_millis() + retry_delay(job.attempts).as_millis() as i64;
+            self.job_repository.retry_later(&job.id, &message, available_at).await?;
+            Ok(false)
+        } else {
+            self.job_repository.mark_failed(&job.id, &message).await?;
+            Ok(true)
+        }
+    }
+}
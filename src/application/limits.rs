@@ -0,0 +1,75 @@
+use crate::core::config::Settings;
+use crate::core::error::ServiceError;
+
+// Resource limits guarding against decompression bombs and oversized transformation results
+#[derive(Debug, Clone, Copy)]
+pub struct ImageLimits {
+    pub max_input_bytes: usize,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+    pub max_alloc_bytes: u64,
+    pub max_output_width: u32,
+    pub max_output_height: u32,
+}
+
+impl ImageLimits {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            max_input_bytes: settings.max_upload_size,
+            max_width: settings.max_image_width,
+            max_height: settings.max_image_height,
+            max_pixels: settings.max_image_pixels,
+            max_alloc_bytes: settings.max_decode_alloc_bytes,
+            max_output_width: settings.max_output_width,
+            max_output_height: settings.max_output_height,
+        }
+    }
+
+    pub fn check_input_size(&self, len: usize) -> Result<(), ServiceError> {
+        if len > self.max_input_bytes {
+            return Err(ServiceError::PayloadTooLarge(format!(
+                "image is {} bytes, the maximum is {}",
+                len, self.max_input_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    // Checked against the header before any pixels are decoded
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<(), ServiceError> {
+        if width > self.max_width || height > self.max_height {
+            return Err(ServiceError::ImageTooLarge(format!(
+                "image is {}x{}, the maximum is {}x{}",
+                width, height, self.max_width, self.max_height
+            )));
+        }
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_pixels {
+            return Err(ServiceError::ImageTooLarge(format!(
+                "image has {} pixels, the maximum is {}",
+                pixels, self.max_pixels
+            )));
+        }
+        Ok(())
+    }
+
+    // Checked before each step allocates its result
+    pub fn check_output(&self, width: u32, height: u32) -> Result<(), ServiceError> {
+        if width > self.max_output_width || height > self.max_output_height {
+            return Err(ServiceError::ImageTooLarge(format!(
+                "result would be {}x{}, the maximum is {}x{}",
+                width, height, self.max_output_width, self.max_output_height
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn decoder_limits(&self) -> image::Limits {
+        let mut limits = image::Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_alloc_bytes);
+        limits
+    }
+}
@@ -1,2 +1,12 @@
 pub mod user_service;
-pub mod image_service;
\ No newline at end of file
+pub mod image_service;
+pub mod filters;
+pub mod geometry;
+pub mod encoding;
+pub mod watermark;
+pub mod derivative_cache;
+pub mod processing_pool;
+pub mod limits;
+pub mod job_service;
+pub mod webhook_service;
+pub mod zip_stream;
\ No newline at end of file
@@ -0,0 +1,181 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use crate::core::config::Settings;
+use crate::core::error::ServiceError;
+
+// Upper bounds, in seconds, of the histogram buckets for queue wait and processing time
+const BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+// Suggested client back-off when the queue is full
+const RETRY_AFTER_SECS: u64 = 2;
+
+// Cumulative histogram in the Prometheus style; sums are kept in microseconds
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, help: &str, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, bucket.load(Ordering::Relaxed));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ProcessingMetrics {
+    pub queue_wait: Histogram,
+    pub processing: Histogram,
+    pub queued: AtomicUsize,
+    pub running: AtomicUsize,
+    pub rejected: AtomicU64,
+    pub timed_out: AtomicU64,
+}
+
+impl ProcessingMetrics {
+    // Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.queue_wait.render(
+            "image_processing_queue_wait_seconds",
+            "Time spent waiting for a processing slot",
+            &mut out,
+        );
+        self.processing.render(
+            "image_processing_duration_seconds",
+            "Time spent decoding, transforming and encoding",
+            &mut out,
+        );
+
+        let gauges = [
+            ("image_processing_queued", "Requests waiting for a processing slot", self.queued.load(Ordering::Relaxed) as u64),
+            ("image_processing_running", "Requests currently being processed", self.running.load(Ordering::Relaxed) as u64),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value);
+        }
+
+        let counters = [
+            ("image_processing_rejected_total", "Requests refused because the queue was full", self.rejected.load(Ordering::Relaxed)),
+            ("image_processing_timeouts_total", "Requests that exceeded the processing timeout", self.timed_out.load(Ordering::Relaxed)),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+        }
+
+        out
+    }
+}
+
+// Runs CPU-heavy work on Tokio's blocking pool, at most `max_concurrency` jobs at a time
+pub struct ProcessingPool {
+    permits: Arc<Semaphore>,
+    concurrency: usize,
+    max_queue_depth: usize,
+    timeout: Duration,
+    metrics: Arc<ProcessingMetrics>,
+}
+
+impl ProcessingPool {
+    pub fn new(settings: &Settings) -> Self {
+        let concurrency = match settings.processing_concurrency {
+            0 => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            n => n,
+        };
+
+        Self {
+            permits: Arc::new(Semaphore::new(concurrency)),
+            concurrency,
+            max_queue_depth: settings.processing_queue_depth,
+            timeout: Duration::from_secs(settings.processing_timeout_secs),
+            metrics: Arc::new(ProcessingMetrics::default()),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<ProcessingMetrics> {
+        self.metrics.clone()
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    // The timeout covers both queueing and processing. A job that times out while running
+    // keeps its slot until the blocking thread finishes, so concurrency stays bounded.
+    pub async fn run<T, F>(&self, job: F) -> Result<T, ServiceError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, ServiceError> + Send + 'static,
+    {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        let queued_at = Instant::now();
+
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                // Reserve a queue slot, refusing the request if the queue is already full
+                let reserved = self.metrics.queued.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                    (queued < self.max_queue_depth).then_some(queued + 1)
+                });
+                if reserved.is_err() {
+                    self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+                    return Err(ServiceError::Overloaded { retry_after_secs: RETRY_AFTER_SECS });
+                }
+
+                let acquired = tokio::time::timeout_at(deadline, self.permits.clone().acquire_owned()).await;
+                self.metrics.queued.fetch_sub(1, Ordering::SeqCst);
+                match acquired {
+                    Ok(Ok(permit)) => permit,
+                    Ok(Err(_)) => return Err(ServiceError::ImageProcessingError("Processing pool closed".to_string())),
+                    Err(_) => return Err(self.timed_out()),
+                }
+            }
+        };
+        self.metrics.queue_wait.observe(queued_at.elapsed());
+
+        let metrics = self.metrics.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            metrics.running.fetch_add(1, Ordering::SeqCst);
+            let started = Instant::now();
+            let result = job();
+            metrics.processing.observe(started.elapsed());
+            metrics.running.fetch_sub(1, Ordering::SeqCst);
+            result
+        });
+
+        match tokio::time::timeout_at(deadline, task).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => Err(ServiceError::ImageProcessingError(format!("Processing task failed: {}", e))),
+            Err(_) => Err(self.timed_out()),
+        }
+    }
+
+    fn timed_out(&self) -> ServiceError {
+        self.metrics.timed_out.fetch_add(1, Ordering::Relaxed);
+        ServiceError::Timeout(format!("Image processing exceeded {}s", self.timeout.as_secs()))
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use ab_glyph::{point, Font, FontArc, FontVec, PxScale, ScaleFont};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use crate::core::config::BUNDLED_FONT;
+use crate::core::error::ServiceError;
+use crate::domain::image::{TextWatermark, Watermark};
+use crate::domain::transformations::{Color, Gravity};
+use crate::application::geometry;
+use crate::application::limits::ImageLimits;
+
+// Decoded overlay images keyed by image id, resolved by the service with ownership checks
+pub type Overlays = HashMap<String, DynamicImage>;
+
+static BUNDLED_FONT_DATA: &[u8] = include_bytes!("../../fonts/DejaVuSans.ttf");
+
+#[derive(Clone)]
+pub struct FontLibrary {
+    root: PathBuf,
+    default_font: String,
+    // Fonts are parsed once and shared; missing fonts are not remembered, so files added later are found
+    loaded: Arc<RwLock<HashMap<String, FontArc>>>,
+}
+
+impl FontLibrary {
+    pub fn new(root: &str, default_font: &str) -> Self {
+        Self {
+            root: PathBuf::from(root),
+            default_font: default_font.to_string(),
+            loaded: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    // Names are validated to [A-Za-z0-9_-] before they get here. A file in the fonts directory
+    // takes precedence over the bundled font of the same name.
+    fn load(&self, name: Option<&str>) -> Result<FontArc, ServiceError> {
+        let name = name.unwrap_or(&self.default_font);
+        if let Some(font) = self.loaded.read().unwrap_or_else(|e| e.into_inner()).get(name) {
+            return Ok(font.clone());
+        }
+
+        let path = ["ttf", "otf"]
+            .iter()
+            .map(|extension| self.root.join(format!("{}.{}", name, extension)))
+            .find(|path| path.is_file());
+        let font = match path {
+            Some(path) => FontVec::try_from_vec(std::fs::read(&path)?).map(FontArc::new),
+            None if name == BUNDLED_FONT => FontArc::try_from_slice(BUNDLED_FONT_DATA),
+            None => return Err(ServiceError::ValidationError(format!("Font '{}' is not available", name))),
+        }
+        .map_err(|_| ServiceError::ImageProcessingError(format!("Font '{}' could not be loaded", name)))?;
+
+        self.loaded
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), font.clone());
+        Ok(font)
+    }
+}
+
+pub fn apply(
+    img: &DynamicImage,
+    watermark: &Watermark,
+    overlays: &Overlays,
+    fonts: &FontLibrary,
+    limits: &ImageLimits,
+) -> Result<DynamicImage, ServiceError> {
+    let mut layer = match (&watermark.text, &watermark.image) {
+        (Some(text), _) => render_text(text, fonts, limits)?,
+        (None, Some(image)) => {
+            let overlay = overlays.get(&image.image_id).ok_or_else(|| {
+                ServiceError::ValidationError(format!("Watermark image {} not found", image.image_id))
+            })?;
+            match image.scale {
+                Some(scale) => {
+                    let width = ((img.width() as f32 * scale).round() as u32).max(1);
+                    let height = ((overlay.height() as f32 * width as f32 / overlay.width() as f32).round() as u32).max(1);
+                    limits.check_output(width, height)?;
+                    overlay.resize_exact(width, height, FilterType::Lanczos3).to_rgba8()
+                }
+                None => overlay.to_rgba8(),
+            }
+        }
+        (None, None) => return Ok(img.clone()),
+    };
+
+    for pixel in layer.pixels_mut() {
+        pixel.0[3] = (pixel.0[3] as f32 * watermark.opacity).round() as u8;
+    }
+
+    if watermark.rotation != 0.0 {
+        let (width, height) = geometry::rotated_size(layer.width(), layer.height(), watermark.rotation, true);
+        limits.check_output(width, height)?;
+        let rotated = geometry::rotate(&DynamicImage::ImageRgba8(layer), watermark.rotation, true, Color::TRANSPARENT);
+        layer = rotated.to_rgba8();
+    }
+
+    let mut base = img.to_rgba8();
+    let (base_width, base_height) = base.dimensions();
+    let (layer_width, layer_height) = layer.dimensions();
+
+    if watermark.tile {
+        let step_x = layer_width as i64 + watermark.offset_x as i64;
+        let step_y = layer_height as i64 + watermark.offset_y as i64;
+        let mut y = 0;
+        while y < base_height as i64 {
+            let mut x = 0;
+            while x < base_width as i64 {
+                imageops::overlay(&mut base, &layer, x, y);
+                x += step_x;
+            }
+            y += step_y;
+        }
+    } else {
+        let (x, y) = position(
+            watermark.gravity,
+            base_width as i64 - layer_width as i64,
+            base_height as i64 - layer_height as i64,
+            watermark.offset_x as i64,
+            watermark.offset_y as i64,
+        );
+        imageops::overlay(&mut base, &layer, x, y);
+    }
+
+    Ok(DynamicImage::ImageRgba8(base))
+}
+
+// Offsets push the layer inward from the edges named by the gravity
+fn position(gravity: Gravity, free_x: i64, free_y: i64, offset_x: i64, offset_y: i64) -> (i64, i64) {
+    let x = match gravity {
+        Gravity::West | Gravity::NorthWest | Gravity::SouthWest => offset_x,
+        Gravity::East | Gravity::NorthEast | Gravity::SouthEast => free_x - offset_x,
+        Gravity::Center | Gravity::North | Gravity::South => free_x / 2 + offset_x,
+    };
+    let y = match gravity {
+        Gravity::North | Gravity::NorthEast | Gravity::NorthWest => offset_y,
+        Gravity::South | Gravity::SouthEast | Gravity::SouthWest => free_y - offset_y,
+        Gravity::Center | Gravity::East | Gravity::West => free_y / 2 + offset_y,
+    };
+    (x, y)
+}
+
+fn render_text(text: &TextWatermark, fonts: &FontLibrary, limits: &ImageLimits) -> Result<RgbaImage, ServiceError> {
+    let font = fonts.load(text.font.as_deref())?;
+    let scaled = font.as_scaled(PxScale::from(text.size));
+    let line_height = scaled.height() + scaled.line_gap();
+    let lines: Vec<&str> = text.content.lines().collect();
+
+    // Lay out every glyph first to find the size of the layer
+    let mut glyphs = Vec::new();
+    let mut width: f32 = 0.0;
+    for (row, line) in lines.iter().enumerate() {
+        let baseline = scaled.ascent() + row as f32 * line_height;
+        let mut caret = 0.0;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id);
+            }
+            glyphs.push(id.with_scale_and_position(text.size, point(caret, baseline)));
+            caret += scaled.h_advance(id);
+            previous = Some(id);
+        }
+        width = width.max(caret);
+    }
+
+    let layer_width = width.ceil().max(1.0) as u32;
+    let layer_height = (lines.len() as f32 * line_height).ceil().max(1.0) as u32;
+    limits.check_output(layer_width, layer_height)?;
+    let mut layer = RgbaImage::new(layer_width, layer_height);
+    let [r, g, b, a] = text.color.to_rgba();
+
+    for glyph in glyphs {
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|x, y, coverage| {
+                let px = bounds.min.x as i64 + x as i64;
+                let py = bounds.min.y as i64 + y as i64;
+                if px < 0 || py < 0 || px >= layer_width as i64 || py >= layer_height as i64 {
+                    return;
+                }
+                let alpha = (coverage.clamp(0.0, 1.0) * a as f32).round() as u8;
+                let pixel = layer.get_pixel_mut(px as u32, py as u32);
+                if alpha > pixel.0[3] {
+                    *pixel = Rgba([r, g, b, alpha]);
+                }
+            });
+        }
+    }
+
+    Ok(layer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::Settings;
+
+    fn text_watermark(font: Option<&str>) -> Watermark {
+        serde_json::from_value(serde_json::json!({
+            "text": { "content": "Hello", "font": font, "size": 24, "color": "#ffffff" },
+            "gravity": "center",
+            "opacity": 1.0
+        }))
+        .unwrap()
+    }
+
+    fn render(fonts: &FontLibrary, font: Option<&str>) -> Result<DynamicImage, ServiceError> {
+        let base = DynamicImage::ImageRgba8(RgbaImage::from_pixel(120, 60, Rgba([0, 0, 0, 255])));
+        apply(&base, &text_watermark(font), &Overlays::new(), fonts, &ImageLimits::new(&Settings::default()))
+    }
+
+    #[test]
+    fn bundled_font_works_without_a_fonts_directory() {
+        let fonts = FontLibrary::new("./no-such-fonts-dir", BUNDLED_FONT);
+        let output = render(&fonts, None).unwrap().to_rgba8();
+        assert!(output.pixels().any(|pixel| pixel.0[0] > 200), "no text was drawn");
+
+        let named = render(&fonts, Some(BUNDLED_FONT)).unwrap();
+        assert_eq!(named.to_rgba8(), output);
+    }
+
+    #[test]
+    fn fonts_are_parsed_once() {
+        let fonts = FontLibrary::new("./no-such-fonts-dir", BUNDLED_FONT);
+        render(&fonts, None).unwrap();
+        render(&fonts.clone(), None).unwrap();
+        assert_eq!(fonts.loaded.read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn unknown_fonts_are_rejected_and_not_cached() {
+        let fonts = FontLibrary::new("./no-such-fonts-dir", BUNDLED_FONT);
+        assert!(matches!(render(&fonts, Some("Missing")), Err(ServiceError::ValidationError(_))));
+        assert!(fonts.loaded.read().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use crate::domain::webhook::{Webhook, WebhookDelivery, WebhookEvent, WebhookRepository, ALL_EVENTS};
+use crate::core::config::Settings;
+use crate::core::error::ServiceError;
+
+// Deliveries sent concurrently per dispatcher pass
+const DELIVERY_BATCH: i64 = 16;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_MAX_SECS: u64 = 3600;
+const MIN_SECRET_LEN: usize = 16;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+// Hex HMAC-SHA256 of "{timestamp}.{body}"; including the timestamp lets receivers reject replays
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+// Whether a webhook may be delivered to the address without WEBHOOK_ALLOW_PRIVATE_HOSTS.
+// Refuses loopback, private, link-local (cloud metadata endpoints live there), shared, multicast
+// and reserved ranges, including IPv4 addresses wrapped in IPv6.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+// Drops non-public addresses from every lookup the client makes, so a hostname that passed the
+// check at registration cannot be re-pointed at an internal service before a delivery connects
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_address(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+// Stores events as pending deliveries and sends them from a background dispatcher, so a slow
+// or failing receiver never holds up the request that caused the event
+#[derive(Clone)]
+pub struct WebhookService {
+    repository: Arc<dyn WebhookRepository>,
+    client: reqwest::Client,
+    wake: Arc<Notify>,
+    max_attempts: i64,
+    retry_base: Duration,
+    allow_private_hosts: bool,
+}
+
+impl WebhookService {
+    pub fn new(repository: Arc<dyn WebhookRepository>, settings: &Settings) -> Result<Self, ServiceError> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.webhook_timeout_secs))
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("image-processing-service/", env!("CARGO_PKG_VERSION")));
+        if !settings.webhook_allow_private_hosts {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = builder
+            .build()
+            .map_err(|e| ServiceError::ImageProcessingError(format!("Failed to create webhook client: {}", e)))?;
+
+        Ok(Self {
+            repository,
+            client,
+            wake: Arc::new(Notify::new()),
+            max_attempts: settings.webhook_max_attempts as i64,
+            retry_base: Duration::from_secs(settings.webhook_retry_base_secs),
+            allow_private_hosts: settings.webhook_allow_private_hosts,
+        })
+    }
+
+    // Every address the host resolves to must be public. IP literals never reach the client's
+    // resolver, so this also runs before each delivery.
+    async fn check_destination(&self, url: &reqwest::Url) -> Result<(), ServiceError> {
+        if self.allow_private_hosts {
+            return Ok(());
+        }
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or(0);
+
+        let addrs: Vec<IpAddr> = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| ServiceError::ValidationError(format!("Webhook host '{}' could not be resolved: {}", host, e)))?
+                .map(|addr| addr.ip())
+                .collect(),
+        };
+        if addrs.is_empty() || !addrs.into_iter().all(is_public_address) {
+            return Err(ServiceError::ValidationError(format!(
+                "Webhook host '{}' is not a public address; set WEBHOOK_ALLOW_PRIVATE_HOSTS to allow it",
+                host
+            )));
+        }
+        Ok(())
+    }
+
+    // A secret is generated when none is given; it is only returned from this call
+    pub async fn register(
+        &self,
+        user_id: &str,
+        url: &str,
+        secret: Option<String>,
+        events: &[String],
+    ) -> Result<Webhook, ServiceError> {
+        let parsed = reqwest::Url::parse(url)
+            .map_err(|e| ServiceError::ValidationError(format!("Invalid webhook URL: {}", e)))?;
+        if !matches!(parsed.scheme(), "http" | "https") || parsed.host().is_none() {
+            return Err(ServiceError::ValidationError("Webhook URL must be an http(s) URL".to_string()));
+        }
+        self.check_destination(&parsed).await?;
+
+        if events.is_empty() {
+            return Err(ServiceError::ValidationError("At least one event is required".to_string()));
+        }
+        let mut names = Vec::new();
+        for event in events {
+            if event != ALL_EVENTS && WebhookEvent::from_name(event).is_none() {
+                let known: Vec<&str> = WebhookEvent::ALL.iter().map(|e| e.as_str()).collect();
+                return Err(ServiceError::ValidationError(format!(
+                    "Unknown event '{}', expected '{}' or one of {}",
+                    event, ALL_EVENTS, known.join(", ")
+                )));
+            }
+            if !names.contains(event) {
+                names.push(event.clone());
+            }
+        }
+        let events = if names.iter().any(|name| name == ALL_EVENTS) { ALL_EVENTS.to_string() } else { names.join(",") };
+
+        let secret = match secret {
+            Some(secret) if secret.len() < MIN_SECRET_LEN => {
+                return Err(ServiceError::ValidationError(format!(
+                    "Webhook secret must be at least {} characters",
+                    MIN_SECRET_LEN
+                )));
+            }
+            Some(secret) => secret,
+            None => format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple()),
+        };
+
+        let webhook = Webhook {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            url: parsed.to_string(),
+            secret,
+            events,
+            created_at: None,
+        };
+        self.repository.create_webhook(&webhook).await
+    }
+
+    pub async fn list(&self, user_id: &str) -> Result<Vec<Webhook>, ServiceError> {
+        self.repository.find_by_user_id(user_id).await
+    }
+
+    pub async fn find_owned(&self, webhook_id: &str, user_id: &str) -> Result<Webhook, ServiceError> {
+        let webhook = self.repository.find_by_id(webhook_id).await?
+            .ok_or(ServiceError::NotFound("Webhook not found".to_string()))?;
+
+        if webhook.user_id != user_id {
+            return Err(ServiceError::Forbidden("Access denied".to_string()));
+        }
+        Ok(webhook)
+    }
+
+    // Pending deliveries are removed along with the webhook
+    pub async fn delete(&self, webhook_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        self.find_owned(webhook_id, user_id).await?;
+        self.repository.delete_webhook(webhook_id, user_id).await?;
+        Ok(())
+    }
+
+    pub async fn deliveries(&self, webhook_id: &str, user_id: &str, limit: i64) -> Result<Vec<WebhookDelivery>, ServiceError> {
+        self.find_owned(webhook_id, user_id).await?;
+        self.repository.find_deliveries(webhook_id, limit).await
+    }
+
+    // Failing to queue an event is logged, never surfaced: the action that caused it has already happened
+    pub async fn emit(&self, user_id: &str, event: WebhookEvent, data: serde_json::Value) {
+        if let Err(e) = self.queue_deliveries(user_id, event, data).await {
+            eprintln!("⚠️  Failed to queue {} webhooks: {}", event.as_str(), e);
+        }
+    }
+
+    async fn queue_deliveries(&self, user_id: &str, event: WebhookEvent, data: serde_json::Value) -> Result<(), ServiceError> {
+        let webhooks = self.repository.find_subscribed(user_id, event.as_str()).await?;
+        if webhooks.is_empty() {
+            return Ok(());
+        }
+
+        let event_id = uuid::Uuid::new_v4().to_string();
+        let payload = serde_json::json!({
+            "id": event_id,
+            "event": event.as_str(),
+            "created_at": chrono::Utc::now().to_rfc3339(),
+            "data": data,
+        })
+        .to_string();
+
+        for webhook in webhooks {
+            let delivery = WebhookDelivery {
+                id: uuid::Uuid::new_v4().to_string(),
+                webhook_id: webhook.id,
+                event_id: event_id.clone(),
+                event: event.as_str().to_string(),
+                payload: payload.clone(),
+                status: "pending".to_string(),
+                attempts: 0,
+                max_attempts: self.max_attempts,
+                response_status: None,
+                last_error: None,
+                next_attempt_at: now_millis(),
+                created_at: None,
+                updated_at: None,
+                delivered_at: None,
+            };
+            self.repository.create_delivery(&delivery).await?;
+        }
+
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    pub async fn start_dispatcher(&self) -> Result<(), ServiceError> {
+        let requeued = self.repository.requeue_delivering().await?;
+        if requeued > 0 {
+            println!("🔁 Requeued {} interrupted webhook deliveries", requeued);
+        }
+
+        let service = self.clone();
+        tokio::spawn(async move { service.dispatch_loop().await });
+        Ok(())
+    }
+
+    async fn dispatch_loop(&self) {
+        loop {
+            match self.repository.claim_due_deliveries(now_millis(), DELIVERY_BATCH).await {
+                Ok(deliveries) if !deliveries.is_empty() => {
+                    futures_util::future::join_all(deliveries.into_iter().map(|delivery| self.deliver(delivery))).await;
+                }
+                Ok(_) => {
+                    let _ = tokio::time::timeout(POLL_INTERVAL, self.wake.notified()).await;
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Failed to claim webhook deliveries: {}", e);
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, delivery: WebhookDelivery) {
+        let outcome = match self.repository.find_by_id(&delivery.webhook_id).await {
+            Ok(Some(webhook)) => match self.send(&webhook, &delivery).await {
+                Ok(status) if (200..300).contains(&status) => self.repository.mark_delivered(&delivery.id, status).await,
+                Ok(status) => self.record_failure(&delivery, Some(status), &format!("Receiver responded with HTTP {}", status)).await,
+                Err(e) => self.record_failure(&delivery, None, &e).await,
+            },
+            // Deleted in the meantime; its deliveries went with it
+            Ok(None) => Ok(()),
+            Err(e) => self.record_failure(&delivery, None, &e.to_string()).await,
+        };
+
+        if let Err(e) = outcome {
+            eprintln!("⚠️  Failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+    }
+
+    async fn send(&self, webhook: &Webhook, delivery: &WebhookDelivery) -> Result<i64, String> {
+        let url = reqwest::Url::parse(&webhook.url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+        self.check_destination(&url).await.map_err(|e| e.to_string())?;
+
+        let timestamp = chrono::Utc::now().timestamp();
+        let response = self
+            .client
+            .post(url)
+            .header("content-type", "application/json")
+            .header("x-webhook-id", &webhook.id)
+            .header("x-webhook-event", &delivery.event)
+            .header("x-webhook-delivery", &delivery.id)
+            .header("x-webhook-timestamp", timestamp.to_string())
+            .header("x-webhook-signature", format!("sha256={}", signature(&webhook.secret, timestamp, &delivery.payload)))
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+
+        Ok(response.status().as_u16() as i64)
+    }
+
+    async fn record_failure(&self, delivery: &WebhookDelivery, status: Option<i64>, error: &str) -> Result<(), ServiceError> {
+        if delivery.attempts < delivery.max_attempts {
+            let exponent = delivery.attempts.clamp(1, 16) as u32 - 1;
+            let delay = (self.retry_base.as_secs() << exponent).min(RETRY_MAX_SECS);
+            let next_attempt_at = now_millis() + delay as i64 * 1000;
+            self.repository.schedule_retry(&delivery.id, status, error, next_attempt_at).await
+        } else {
+            self.repository.mark_failed(&delivery.id, status, error).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1",
+            "0.0.0.0", "255.255.255.255", "224.0.0.1", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{} should not be public", ip);
+        }
+        for ip in ["93.184.216.34", "8.8.8.8", "100.128.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::{Datelike, Timelike};
+use crate::core::error::ServiceError;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+// Version 2.0 of the format is all stored entries need
+const VERSION: u16 = 20;
+// Bit 11: file names are UTF-8
+const FLAGS: u16 = 1 << 11;
+const METHOD_STORED: u16 = 0;
+
+struct CentralEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+// Writes a ZIP archive one entry at a time, so entries can be sent as soon as they are ready.
+// Entries are stored uncompressed: image formats are already compressed. Sizes and offsets
+// are 32-bit, which limits an archive to 4 GiB and 65535 entries.
+pub struct ZipStreamWriter {
+    offset: u64,
+    entries: Vec<CentralEntry>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+impl ZipStreamWriter {
+    pub fn new() -> Self {
+        let now = chrono::Local::now();
+        Self {
+            offset: 0,
+            entries: Vec::new(),
+            dos_time: ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16,
+            dos_date: (((now.year().clamp(1980, 2107) - 1980) as u32) << 9 | (now.month() << 5) | now.day()) as u16,
+        }
+    }
+
+    // Returns the bytes of the entry: local file header followed by the data
+    pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<Vec<u8>, ServiceError> {
+        let too_large = || ServiceError::ImageProcessingError("Batch result exceeds the ZIP size limit".to_string());
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let offset = u32::try_from(self.offset).map_err(|_| too_large())?;
+        if self.entries.len() >= u16::MAX as usize {
+            return Err(too_large());
+        }
+        let crc = crc32fast::hash(data);
+
+        let mut out = Vec::with_capacity(30 + name.len() + data.len());
+        put_u32(&mut out, LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut out, VERSION);
+        put_u16(&mut out, FLAGS);
+        put_u16(&mut out, METHOD_STORED);
+        put_u16(&mut out, self.dos_time);
+        put_u16(&mut out, self.dos_date);
+        put_u32(&mut out, crc);
+        put_u32(&mut out, size);
+        put_u32(&mut out, size);
+        put_u16(&mut out, name.len() as u16);
+        put_u16(&mut out, 0);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+
+        self.offset += out.len() as u64;
+        self.entries.push(CentralEntry { name: name.to_string(), crc, size, offset });
+        Ok(out)
+    }
+
+    // Central directory and end record; must come after every entry
+    pub fn finish(self) -> Result<Vec<u8>, ServiceError> {
+        let too_large = || ServiceError::ImageProcessingError("Batch result exceeds the ZIP size limit".to_string());
+        let directory_offset = u32::try_from(self.offset).map_err(|_| too_large())?;
+
+        let mut out = Vec::new();
+        for entry in &self.entries {
+            put_u32(&mut out, CENTRAL_HEADER_SIGNATURE);
+            put_u16(&mut out, VERSION);
+            put_u16(&mut out, VERSION);
+            put_u16(&mut out, FLAGS);
+            put_u16(&mut out, METHOD_STORED);
+            put_u16(&mut out, self.dos_time);
+            put_u16(&mut out, self.dos_date);
+            put_u32(&mut out, entry.crc);
+            put_u32(&mut out, entry.size);
+            put_u32(&mut out, entry.size);
+            put_u16(&mut out, entry.name.len() as u16);
+            // Extra field, comment, disk number, internal and external attributes
+            put_u16(&mut out, 0);
+            put_u16(&mut out, 0);
+            put_u16(&mut out, 0);
+            put_u16(&mut out, 0);
+            put_u32(&mut out, 0);
+            put_u32(&mut out, entry.offset);
+            out.extend_from_slice(entry.name.as_bytes());
+        }
+        let directory_size = u32::try_from(out.len()).map_err(|_| too_large())?;
+
+        put_u32(&mut out, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, 0);
+        put_u16(&mut out, self.entries.len() as u16);
+        put_u16(&mut out, self.entries.len() as u16);
+        put_u32(&mut out, directory_size);
+        put_u32(&mut out, directory_offset);
+        put_u16(&mut out, 0);
+        Ok(out)
+    }
+}
+
+impl Default for ZipStreamWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
@@ -0,0 +1,520 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Placeholder secret used when nothing is configured; only accepted in development
+pub const DEFAULT_JWT_SECRET: &str = "change-me-in-production";
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// Compiled into the binary, so text watermarks work without any font files installed
+pub const BUNDLED_FONT: &str = "DejaVuSans";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Io(String, std::io::Error),
+
+    #[error("Failed to parse config file {0}: {1}")]
+    Parse(String, toml::de::Error),
+
+    #[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Development,
+    Production,
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "development" | "dev" => Ok(Environment::Development),
+            "production" | "prod" => Ok(Environment::Production),
+            other => Err(format!("unknown environment '{}'", other)),
+        }
+    }
+}
+
+// What deleting an image does to the images saved from its transformations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParentDeletePolicy {
+    // Delete every derived image along with it
+    Cascade,
+    // Keep derived images, clearing their parent
+    Orphan,
+    // Refuse the delete while derived images exist
+    Block,
+}
+
+impl FromStr for ParentDeletePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cascade" => Ok(ParentDeletePolicy::Cascade),
+            "orphan" => Ok(ParentDeletePolicy::Orphan),
+            "block" => Ok(ParentDeletePolicy::Block),
+            other => Err(format!("unknown parent delete policy '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    Local,
+    S3,
+}
+
+impl FromStr for StorageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => Ok(StorageKind::Local),
+            "s3" => Ok(StorageKind::S3),
+            other => Err(format!("unknown storage backend '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub environment: Environment,
+    pub database_url: String,
+    pub jwt_secret: String,
+    pub jwt_expiry_hours: i64,
+    // Key for signed render URLs; the JWT secret is used when empty
+    pub render_url_secret: String,
+    // Longest lifetime a signed render URL may be issued with
+    pub render_url_max_ttl_secs: u64,
+    pub storage_backend: StorageKind,
+    pub storage_path: String,
+    pub s3_bucket: String,
+    pub s3_region: String,
+    pub s3_endpoint: Option<String>,
+    pub s3_access_key_id: String,
+    pub s3_secret_access_key: String,
+    pub s3_path_style: bool,
+    pub fonts_path: String,
+    pub default_font: String,
+    // Size budget for cached transformation results; 0 disables the cache
+    pub derivative_cache_max_bytes: u64,
+    // Sent with image and transformation responses; empty to omit the header
+    pub cache_control: String,
+    // Images processed at once; 0 uses the number of CPUs
+    pub processing_concurrency: usize,
+    // Requests allowed to wait for a processing slot before new ones get 503
+    pub processing_queue_depth: usize,
+    pub processing_timeout_secs: u64,
+    // Bearer token Prometheus must send to scrape /metrics; the route is not served when empty
+    pub metrics_token: String,
+    pub parent_delete_policy: ParentDeletePolicy,
+    // How long deleted images stay in the trash before they are purged for good
+    pub trash_retention_secs: u64,
+    // How often expired trash is looked for
+    pub trash_purge_interval_secs: u64,
+    // Most images a single batch request may address
+    pub batch_max_items: usize,
+    // Background workers running asynchronous jobs
+    pub job_workers: usize,
+    // Attempts per job, including the first, before it is marked failed
+    pub job_max_attempts: u32,
+    // How often idle workers look for due retries
+    pub job_poll_interval_ms: u64,
+    // How long job results stay downloadable after the job completes
+    pub job_result_retention_secs: u64,
+    // Attempts per webhook delivery before it is marked failed
+    pub webhook_max_attempts: u32,
+    // First retry delay; each further retry waits twice as long
+    pub webhook_retry_base_secs: u64,
+    pub webhook_timeout_secs: u64,
+    // Lets webhooks target loopback, private and link-local addresses, e.g. receivers on the
+    // same network; off by default so webhooks cannot be used to reach internal services
+    pub webhook_allow_private_hosts: bool,
+    pub server_host: String,
+    pub server_port: u16,
+    pub max_upload_size: usize,
+    // Decoding limits, checked against the header before pixels are decoded
+    pub max_image_width: u32,
+    pub max_image_height: u32,
+    pub max_image_pixels: u64,
+    pub max_decode_alloc_bytes: u64,
+    // Largest image any transformation step may produce
+    pub max_output_width: u32,
+    pub max_output_height: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            environment: Environment::Development,
+            database_url: "sqlite:image_service.db".to_string(),
+            jwt_secret: DEFAULT_JWT_SECRET.to_string(),
+            render_url_secret: String::new(),
+            render_url_max_ttl_secs: 7 * 24 * 60 * 60,
+            jwt_expiry_hours: 24,
+            storage_backend: StorageKind::Local,
+            storage_path: "./uploads".to_string(),
+            s3_bucket: String::new(),
+            s3_region: "us-east-1".to_string(),
+            s3_endpoint: None,
+            s3_access_key_id: String::new(),
+            s3_secret_access_key: String::new(),
+            s3_path_style: false,
+            fonts_path: "./fonts".to_string(),
+            default_font: BUNDLED_FONT.to_string(),
+            derivative_cache_max_bytes: 256 * 1024 * 1024,
+            cache_control: "private, max-age=86400".to_string(),
+            processing_concurrency: 0,
+            processing_queue_depth: 64,
+            processing_timeout_secs: 30,
+            metrics_token: String::new(),
+            parent_delete_policy: ParentDeletePolicy::Orphan,
+            trash_retention_secs: 30 * 24 * 60 * 60,
+            trash_purge_interval_secs: 3600,
+            batch_max_items: 100,
+            job_workers: 2,
+            job_max_attempts: 3,
+            job_poll_interval_ms: 1000,
+            job_result_retention_secs: 7 * 24 * 60 * 60,
+            webhook_max_attempts: 6,
+            webhook_retry_base_secs: 10,
+            webhook_timeout_secs: 10,
+            webhook_allow_private_hosts: false,
+            server_host: "0.0.0.0".to_string(),
+            server_port: 8080,
+            max_upload_size: 10 * 1024 * 1024,
+            max_image_width: 16384,
+            max_image_height: 16384,
+            max_image_pixels: 100_000_000,
+            max_decode_alloc_bytes: 1024 * 1024 * 1024,
+            max_output_width: 8192,
+            max_output_height: 8192,
+        }
+    }
+}
+
+impl Settings {
+    // Layers: defaults < TOML file (CONFIG_FILE or ./config.toml) < .env < environment
+    pub fn load() -> Result<Self, ConfigError> {
+        // .env never overrides variables that are already set
+        let _ = dotenvy::dotenv();
+
+        let mut settings = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(DEFAULT_CONFIG_FILE)?,
+            Err(_) => Self::default(),
+        };
+
+        let mut problems = settings.apply_env(|key| std::env::var(key).ok());
+        problems.extend(settings.validate());
+
+        if problems.is_empty() {
+            Ok(settings)
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_string(), e))?;
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+
+    // Returns one problem per variable that fails to parse
+    pub fn apply_env<F>(&mut self, get: F) -> Vec<String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut problems = Vec::new();
+
+        fn parse<T: FromStr>(key: &str, value: String, target: &mut T, problems: &mut Vec<String>)
+        where
+            T::Err: std::fmt::Display,
+        {
+            match value.trim().parse() {
+                Ok(parsed) => *target = parsed,
+                Err(e) => problems.push(format!("{} has invalid value '{}': {}", key, value, e)),
+            }
+        }
+
+        if let Some(value) = get("APP_ENV") {
+            parse("APP_ENV", value, &mut self.environment, &mut problems);
+        }
+        if let Some(value) = get("DATABASE_URL") {
+            self.database_url = value;
+        }
+        if let Some(value) = get("JWT_SECRET") {
+            self.jwt_secret = value;
+        }
+        if let Some(value) = get("JWT_EXPIRY_HOURS") {
+            parse("JWT_EXPIRY_HOURS", value, &mut self.jwt_expiry_hours, &mut problems);
+        }
+        if let Some(value) = get("RENDER_URL_SECRET") {
+            self.render_url_secret = value;
+        }
+        if let Some(value) = get("RENDER_URL_MAX_TTL_SECS") {
+            parse("RENDER_URL_MAX_TTL_SECS", value, &mut self.render_url_max_ttl_secs, &mut problems);
+        }
+        if let Some(value) = get("STORAGE_PATH") {
+            self.storage_path = value;
+        }
+        if let Some(value) = get("STORAGE_BACKEND") {
+            parse("STORAGE_BACKEND", value, &mut self.storage_backend, &mut problems);
+        }
+        if let Some(value) = get("S3_BUCKET") {
+            self.s3_bucket = value;
+        }
+        if let Some(value) = get("S3_REGION") {
+            self.s3_region = value;
+        }
+        if let Some(value) = get("S3_ENDPOINT") {
+            self.s3_endpoint = Some(value).filter(|v| !v.is_empty());
+        }
+        if let Some(value) = get("S3_ACCESS_KEY_ID") {
+            self.s3_access_key_id = value;
+        }
+        if let Some(value) = get("S3_SECRET_ACCESS_KEY") {
+            self.s3_secret_access_key = value;
+        }
+        if let Some(value) = get("S3_PATH_STYLE") {
+            parse("S3_PATH_STYLE", value, &mut self.s3_path_style, &mut problems);
+        }
+        if let Some(value) = get("FONTS_PATH") {
+            self.fonts_path = value;
+        }
+        if let Some(value) = get("DEFAULT_FONT") {
+            self.default_font = value;
+        }
+        if let Some(value) = get("DERIVATIVE_CACHE_MAX_BYTES") {
+            parse("DERIVATIVE_CACHE_MAX_BYTES", value, &mut self.derivative_cache_max_bytes, &mut problems);
+        }
+        if let Some(value) = get("CACHE_CONTROL") {
+            self.cache_control = value;
+        }
+        if let Some(value) = get("PROCESSING_CONCURRENCY") {
+            parse("PROCESSING_CONCURRENCY", value, &mut self.processing_concurrency, &mut problems);
+        }
+        if let Some(value) = get("PROCESSING_QUEUE_DEPTH") {
+            parse("PROCESSING_QUEUE_DEPTH", value, &mut self.processing_queue_depth, &mut problems);
+        }
+        if let Some(value) = get("PROCESSING_TIMEOUT_SECS") {
+            parse("PROCESSING_TIMEOUT_SECS", value, &mut self.processing_timeout_secs, &mut problems);
+        }
+        if let Some(value) = get("METRICS_TOKEN") {
+            self.metrics_token = value;
+        }
+        if let Some(value) = get("PARENT_DELETE_POLICY") {
+            parse("PARENT_DELETE_POLICY", value, &mut self.parent_delete_policy, &mut problems);
+        }
+        if let Some(value) = get("TRASH_RETENTION_SECS") {
+            parse("TRASH_RETENTION_SECS", value, &mut self.trash_retention_secs, &mut problems);
+        }
+        if let Some(value) = get("TRASH_PURGE_INTERVAL_SECS") {
+            parse("TRASH_PURGE_INTERVAL_SECS", value, &mut self.trash_purge_interval_secs, &mut problems);
+        }
+        if let Some(value) = get("BATCH_MAX_ITEMS") {
+            parse("BATCH_MAX_ITEMS", value, &mut self.batch_max_items, &mut problems);
+        }
+        if let Some(value) = get("JOB_WORKERS") {
+            parse("JOB_WORKERS", value, &mut self.job_workers, &mut problems);
+        }
+        if let Some(value) = get("JOB_MAX_ATTEMPTS") {
+            parse("JOB_MAX_ATTEMPTS", value, &mut self.job_max_attempts, &mut problems);
+        }
+        if let Some(value) = get("JOB_POLL_INTERVAL_MS") {
+            parse("JOB_POLL_INTERVAL_MS", value, &mut self.job_poll_interval_ms, &mut problems);
+        }
+        if let Some(value) = get("JOB_RESULT_RETENTION_SECS") {
+            parse("JOB_RESULT_RETENTION_SECS", value, &mut self.job_result_retention_secs, &mut problems);
+        }
+        if let Some(value) = get("WEBHOOK_MAX_ATTEMPTS") {
+            parse("WEBHOOK_MAX_ATTEMPTS", value, &mut self.webhook_max_attempts, &mut problems);
+        }
+        if let Some(value) = get("WEBHOOK_RETRY_BASE_SECS") {
+            parse("WEBHOOK_RETRY_BASE_SECS", value, &mut self.webhook_retry_base_secs, &mut problems);
+        }
+        if let Some(value) = get("WEBHOOK_TIMEOUT_SECS") {
+            parse("WEBHOOK_TIMEOUT_SECS", value, &mut self.webhook_timeout_secs, &mut problems);
+        }
+        if let Some(value) = get("WEBHOOK_ALLOW_PRIVATE_HOSTS") {
+            parse("WEBHOOK_ALLOW_PRIVATE_HOSTS", value, &mut self.webhook_allow_private_hosts, &mut problems);
+        }
+        if let Some(value) = get("SERVER_HOST") {
+            self.server_host = value;
+        }
+        if let Some(value) = get("SERVER_PORT") {
+            parse("SERVER_PORT", value, &mut self.server_port, &mut problems);
+        }
+        if let Some(value) = get("MAX_UPLOAD_SIZE") {
+            parse("MAX_UPLOAD_SIZE", value, &mut self.max_upload_size, &mut problems);
+        }
+        if let Some(value) = get("MAX_IMAGE_WIDTH") {
+            parse("MAX_IMAGE_WIDTH", value, &mut self.max_image_width, &mut problems);
+        }
+        if let Some(value) = get("MAX_IMAGE_HEIGHT") {
+            parse("MAX_IMAGE_HEIGHT", value, &mut self.max_image_height, &mut problems);
+        }
+        if let Some(value) = get("MAX_IMAGE_PIXELS") {
+            parse("MAX_IMAGE_PIXELS", value, &mut self.max_image_pixels, &mut problems);
+        }
+        if let Some(value) = get("MAX_DECODE_ALLOC_BYTES") {
+            parse("MAX_DECODE_ALLOC_BYTES", value, &mut self.max_decode_alloc_bytes, &mut problems);
+        }
+        if let Some(value) = get("MAX_OUTPUT_WIDTH") {
+            parse("MAX_OUTPUT_WIDTH", value, &mut self.max_output_width, &mut problems);
+        }
+        if let Some(value) = get("MAX_OUTPUT_HEIGHT") {
+            parse("MAX_OUTPUT_HEIGHT", value, &mut self.max_output_height, &mut problems);
+        }
+
+        problems
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.jwt_secret.trim().is_empty() {
+            problems.push("JWT_SECRET must not be empty".to_string());
+        } else if !self.is_development() && self.jwt_secret == DEFAULT_JWT_SECRET {
+            problems.push("JWT_SECRET must be changed from the default outside development".to_string());
+        }
+        if self.render_url_max_ttl_secs == 0 {
+            problems.push("RENDER_URL_MAX_TTL_SECS must be greater than zero".to_string());
+        }
+        if self.jwt_expiry_hours <= 0 {
+            problems.push("JWT_EXPIRY_HOURS must be greater than zero".to_string());
+        }
+        if !self.database_url.starts_with("sqlite:") {
+            problems.push(format!("DATABASE_URL must be a sqlite: URL, got '{}'", self.database_url));
+        }
+        match self.storage_backend {
+            StorageKind::Local => {
+                if self.storage_path.trim().is_empty() {
+                    problems.push("STORAGE_PATH must not be empty".to_string());
+                }
+            }
+            StorageKind::S3 => {
+                if self.s3_bucket.trim().is_empty() {
+                    problems.push("S3_BUCKET is required for the s3 storage backend".to_string());
+                }
+                if self.s3_access_key_id.is_empty() || self.s3_secret_access_key.is_empty() {
+                    problems.push("S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY are required for the s3 storage backend".to_string());
+                }
+                if let Some(endpoint) = &self.s3_endpoint {
+                    if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                        problems.push(format!("S3_ENDPOINT must be an http(s) URL, got '{}'", endpoint));
+                    }
+                }
+            }
+        }
+        if self.default_font.is_empty()
+            || !self.default_font.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            problems.push(format!("DEFAULT_FONT must be a font file name without extension, got '{}'", self.default_font));
+        } else if self.default_font != BUNDLED_FONT
+            && !["ttf", "otf"].iter().any(|extension| {
+                Path::new(&self.fonts_path).join(format!("{}.{}", self.default_font, extension)).is_file()
+            })
+        {
+            problems.push(format!(
+                "DEFAULT_FONT '{}' was not found as a .ttf or .otf file in FONTS_PATH '{}'",
+                self.default_font, self.fonts_path
+            ));
+        }
+        if self.processing_timeout_secs == 0 {
+            problems.push("PROCESSING_TIMEOUT_SECS must be greater than zero".to_string());
+        }
+        if self.trash_purge_interval_secs == 0 {
+            problems.push("TRASH_PURGE_INTERVAL_SECS must be greater than zero".to_string());
+        }
+        if self.batch_max_items == 0 {
+            problems.push("BATCH_MAX_ITEMS must be greater than zero".to_string());
+        }
+        if self.job_workers == 0 {
+            problems.push("JOB_WORKERS must be greater than zero".to_string());
+        }
+        if self.job_max_attempts == 0 {
+            problems.push("JOB_MAX_ATTEMPTS must be greater than zero".to_string());
+        }
+        if self.job_poll_interval_ms == 0 {
+            problems.push("JOB_POLL_INTERVAL_MS must be greater than zero".to_string());
+        }
+        if self.webhook_max_attempts == 0 {
+            problems.push("WEBHOOK_MAX_ATTEMPTS must be greater than zero".to_string());
+        }
+        if self.webhook_timeout_secs == 0 {
+            problems.push("WEBHOOK_TIMEOUT_SECS must be greater than zero".to_string());
+        }
+        if self.server_host.parse::<std::net::IpAddr>().is_err() {
+            problems.push(format!("SERVER_HOST must be an IP address, got '{}'", self.server_host));
+        }
+        if self.server_port == 0 {
+            problems.push("SERVER_PORT must not be 0".to_string());
+        }
+        if self.max_upload_size == 0 {
+            problems.push("MAX_UPLOAD_SIZE must be greater than zero".to_string());
+        }
+        let limits = [
+            ("MAX_IMAGE_WIDTH", self.max_image_width as u64),
+            ("MAX_IMAGE_HEIGHT", self.max_image_height as u64),
+            ("MAX_IMAGE_PIXELS", self.max_image_pixels),
+            ("MAX_DECODE_ALLOC_BYTES", self.max_decode_alloc_bytes),
+            ("MAX_OUTPUT_WIDTH", self.max_output_width as u64),
+            ("MAX_OUTPUT_HEIGHT", self.max_output_height as u64),
+        ];
+        for (key, value) in limits {
+            if value == 0 {
+                problems.push(format!("{} must be greater than zero", key));
+            }
+        }
+
+        problems
+    }
+
+    pub fn is_development(&self) -> bool {
+        self.environment == Environment::Development
+    }
+
+    pub fn server_addr(&self) -> std::net::SocketAddr {
+        let host = self.server_host.parse().unwrap_or([0, 0, 0, 0].into());
+        std::net::SocketAddr::new(host, self.server_port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font_problems(settings: &Settings) -> Vec<String> {
+        settings.validate().into_iter().filter(|problem| problem.starts_with("DEFAULT_FONT")).collect()
+    }
+
+    #[test]
+    fn default_font_must_exist() {
+        let mut settings = Settings { fonts_path: "./no-such-fonts-dir".to_string(), ..Settings::default() };
+        assert!(font_problems(&settings).is_empty(), "the bundled font needs no file");
+
+        settings.default_font = "Missing".to_string();
+        assert_eq!(font_problems(&settings).len(), 1);
+
+        settings.fonts_path = std::env::temp_dir().to_string_lossy().into_owned();
+        let path = std::env::temp_dir().join(format!("Font{}.ttf", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        settings.default_font = format!("Font{}", std::process::id());
+        let problems = font_problems(&settings);
+        std::fs::remove_file(&path).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+}
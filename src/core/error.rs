@@ -17,9 +17,35 @@ pub enum ServiceError {
     #[error("Image processing error: {0}")]
     ImageProcessingError(String),
     
-    #[allow(dead_code)]
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+    
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+    
+    #[error("Image too large: {0}")]
+    ImageTooLarge(String),
+    
+    #[error("Storage error: {0}")]
+    StorageError(String),
+    
     #[error("Unauthorized")]
     Unauthorized,
+    
+    #[error("Server is busy, retry in {retry_after_secs}s")]
+    Overloaded { retry_after_secs: u64 },
+    
+    #[error("Timeout: {0}")]
+    Timeout(String),
+    
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    
+    #[error("Not found: {0}")]
+    NotFound(String),
+    
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
 }
 
 impl From<sqlx::Error> for ServiceError {
@@ -49,9 +75,22 @@ impl axum::response::IntoResponse for ServiceError {
             ServiceError::ValidationError(_) => axum::http::StatusCode::BAD_REQUEST,
             ServiceError::AuthenticationError(_) => axum::http::StatusCode::UNAUTHORIZED,
             ServiceError::ImageProcessingError(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ServiceError::UnsupportedMediaType(_) => axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ServiceError::PayloadTooLarge(_) => axum::http::StatusCode::PAYLOAD_TOO_LARGE,
+            ServiceError::ImageTooLarge(_) => axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            ServiceError::StorageError(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
             ServiceError::Unauthorized => axum::http::StatusCode::UNAUTHORIZED,
+            ServiceError::Overloaded { .. } => axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::Timeout(_) => axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::Conflict(_) => axum::http::StatusCode::CONFLICT,
+            ServiceError::NotFound(_) => axum::http::StatusCode::NOT_FOUND,
+            ServiceError::Forbidden(_) => axum::http::StatusCode::FORBIDDEN,
         };
         
-        (status, self.to_string()).into_response()
+        let body = axum::Json(serde_json::json!({ "error": self.to_string() }));
+        if let ServiceError::Overloaded { retry_after_secs } = self {
+            return (status, [(axum::http::header::RETRY_AFTER, retry_after_secs.to_string())], body).into_response();
+        }
+        (status, body).into_response()
     }
 }
\ No newline at end of file
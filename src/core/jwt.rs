@@ -1,6 +1,7 @@
 use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
 use serde::{Deserialize, Serialize};
 use chrono::{Utc, Duration};
+use crate::core::config::Settings;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
@@ -12,16 +13,20 @@ pub struct Claims {
 #[derive(Clone)]
 pub struct JwtService {
     secret: String,
+    expiry_hours: i64,
 }
 
 impl JwtService {
-    pub fn new(secret: String) -> Self {
-        Self { secret }
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            secret: settings.jwt_secret.clone(),
+            expiry_hours: settings.jwt_expiry_hours,
+        }
     }
     
     pub fn generate_token(&self, user_id: &str, username: &str) -> Result<String, jsonwebtoken::errors::Error> {
         let expiration = Utc::now()
-            .checked_add_signed(Duration::hours(24))
+            .checked_add_signed(Duration::hours(self.expiry_hours))
             .expect("Invalid timestamp")
             .timestamp() as usize;
             
@@ -34,7 +39,6 @@ impl JwtService {
         encode(&Header::default(), &claims, &EncodingKey::from_secret(self.secret.as_ref()))
     }
     
-    #[allow(dead_code)]
     pub fn verify_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
         let token_data = decode::<Claims>(
             token,
@@ -1,6 +1,8 @@
 pub mod error;
 pub mod jwt;
 pub mod auth;
+pub mod config;
+pub mod signed_url;
 
 #[allow(unused_imports)]
 pub use error::ServiceError;
\ No newline at end of file
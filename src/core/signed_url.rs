@@ -0,0 +1,211 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::core::config::Settings;
+use crate::core::error::ServiceError;
+
+type HmacSha256 = Hmac<Sha256>;
+pub type QueryParams = Vec<(String, String)>;
+
+pub const EXPIRES_PARAM: &str = "expires";
+pub const SIGNATURE_PARAM: &str = "sig";
+
+// Expiry and signature carried by a signed URL, as unix seconds and hex HMAC-SHA256
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlSignature {
+    pub expires: i64,
+    pub signature: String,
+}
+
+// Signs render URLs so they can be used without a Bearer token, e.g. in <img> tags or by a CDN.
+// A signature covers the image id, the expiry and every render parameter, so none of them can
+// be changed without invalidating it.
+#[derive(Clone)]
+pub struct RenderUrlSigner {
+    secret: String,
+    max_ttl_secs: u64,
+}
+
+impl RenderUrlSigner {
+    pub fn new(settings: &Settings) -> Self {
+        let secret = if settings.render_url_secret.is_empty() {
+            &settings.jwt_secret
+        } else {
+            &settings.render_url_secret
+        };
+        Self {
+            secret: secret.clone(),
+            max_ttl_secs: settings.render_url_max_ttl_secs,
+        }
+    }
+
+    pub fn max_ttl_secs(&self) -> u64 {
+        self.max_ttl_secs
+    }
+
+    fn mac(&self, image_id: &str, params: &[(String, String)], expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(self.secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(format!("render:{}:{}:{}", image_id, expires, canonical_query(params)).as_bytes());
+        mac
+    }
+
+    pub fn sign(&self, image_id: &str, params: &[(String, String)], expires: i64) -> UrlSignature {
+        let signature = format!("{:x}", self.mac(image_id, params, expires).finalize().into_bytes());
+        UrlSignature { expires, signature }
+    }
+
+    pub fn verify(&self, image_id: &str, params: &[(String, String)], signature: &UrlSignature) -> Result<(), ServiceError> {
+        let invalid = || ServiceError::Forbidden("Invalid URL signature".to_string());
+        let bytes = decode_hex(&signature.signature).ok_or_else(invalid)?;
+        self.mac(image_id, params, signature.expires)
+            .verify_slice(&bytes)
+            .map_err(|_| invalid())?;
+
+        if signature.expires <= chrono::Utc::now().timestamp() {
+            return Err(ServiceError::Forbidden("Signed URL has expired".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// Separates the signature parameters from the rest; both or neither must be present
+pub fn split_signature(params: QueryParams) -> Result<(QueryParams, Option<UrlSignature>), ServiceError> {
+    let mut expires = None;
+    let mut signature = None;
+    let mut rest = Vec::with_capacity(params.len());
+    for (key, value) in params {
+        match key.as_str() {
+            EXPIRES_PARAM if expires.is_none() => expires = Some(value),
+            SIGNATURE_PARAM if signature.is_none() => signature = Some(value),
+            EXPIRES_PARAM | SIGNATURE_PARAM => {
+                return Err(ServiceError::ValidationError(format!("Query parameter '{}': given more than once", key)));
+            }
+            _ => rest.push((key, value)),
+        }
+    }
+
+    match (expires, signature) {
+        (None, None) => Ok((rest, None)),
+        (Some(expires), Some(signature)) => {
+            let expires = expires.parse().map_err(|_| {
+                ServiceError::ValidationError(format!("Query parameter '{}': '{}' is not a valid number", EXPIRES_PARAM, expires))
+            })?;
+            Ok((rest, Some(UrlSignature { expires, signature })))
+        }
+        _ => Err(ServiceError::ValidationError(format!(
+            "Signed URLs need both '{}' and '{}'",
+            EXPIRES_PARAM, SIGNATURE_PARAM
+        ))),
+    }
+}
+
+// Query string with every key and value percent-encoded, in the order given
+pub fn encode_query(params: &[(String, String)]) -> String {
+    params
+        .iter()
+        .map(|(key, value)| format!("{}={}", percent_encode(key), percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// Sorted so the signature does not depend on parameter order; encoded so '&' and '=' inside
+// values cannot make two different parameter lists look the same
+fn canonical_query(params: &[(String, String)]) -> String {
+    let mut sorted = params.to_vec();
+    sorted.sort();
+    encode_query(&sorted)
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| value.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> RenderUrlSigner {
+        RenderUrlSigner::new(&Settings::default())
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    fn in_an_hour() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    #[test]
+    fn signatures_verify_regardless_of_parameter_order() {
+        let signature = signer().sign("img", &params(&[("w", "200"), ("fmt", "webp")]), in_an_hour());
+        assert!(signer().verify("img", &params(&[("w", "200"), ("fmt", "webp")]), &signature).is_ok());
+        assert!(signer().verify("img", &params(&[("fmt", "webp"), ("w", "200")]), &signature).is_ok());
+    }
+
+    #[test]
+    fn tampering_invalidates_the_signature() {
+        let original = params(&[("w", "200")]);
+        let signature = signer().sign("img", &original, in_an_hour());
+
+        assert!(signer().verify("other", &original, &signature).is_err());
+        assert!(signer().verify("img", &params(&[("w", "2000")]), &signature).is_err());
+        assert!(signer().verify("img", &params(&[("w", "200"), ("blur", "3")]), &signature).is_err());
+        assert!(signer().verify("img", &[], &signature).is_err());
+
+        let extended = UrlSignature { expires: signature.expires + 1, ..signature.clone() };
+        assert!(signer().verify("img", &original, &extended).is_err());
+
+        let garbage = UrlSignature { signature: "zz".to_string(), ..signature.clone() };
+        assert!(signer().verify("img", &original, &garbage).is_err());
+
+        let other_key = RenderUrlSigner::new(&Settings { render_url_secret: "another-secret".to_string(), ..Settings::default() });
+        assert!(other_key.verify("img", &original, &signature).is_err());
+    }
+
+    #[test]
+    fn expired_signatures_are_rejected() {
+        let signature = signer().sign("img", &[], chrono::Utc::now().timestamp() - 1);
+        assert!(matches!(signer().verify("img", &[], &signature), Err(ServiceError::Forbidden(_))));
+    }
+
+    #[test]
+    fn values_containing_separators_stay_distinct() {
+        let signature = signer().sign("img", &params(&[("w", "1"), ("h", "2")]), in_an_hour());
+        assert!(signer().verify("img", &params(&[("h", "2&w=1")]), &signature).is_err());
+    }
+
+    #[test]
+    fn splits_signature_parameters() {
+        let (rest, signature) = split_signature(params(&[("w", "10"), ("expires", "99"), ("sig", "ab")])).unwrap();
+        assert_eq!(rest, params(&[("w", "10")]));
+        assert_eq!(signature, Some(UrlSignature { expires: 99, signature: "ab".to_string() }));
+
+        assert_eq!(split_signature(params(&[("w", "10")])).unwrap().1, None);
+        assert!(split_signature(params(&[("sig", "ab")])).is_err());
+        assert!(split_signature(params(&[("expires", "soon"), ("sig", "ab")])).is_err());
+        assert!(split_signature(params(&[("expires", "1"), ("sig", "ab"), ("sig", "cd")])).is_err());
+    }
+
+    #[test]
+    fn encodes_reserved_characters() {
+        assert_eq!(encode_query(&params(&[("bg", "#ff00ff"), ("x", "a b&c=d")])), "bg=%23ff00ff&x=a%20b%26c%3Dd");
+    }
+}
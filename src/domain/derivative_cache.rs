@@ -0,0 +1,25 @@
+use sqlx::FromRow;
+use crate::core::error::ServiceError;
+
+// Index entry for one cached transformation result; the bytes live in the storage backend
+#[derive(Debug, Clone, FromRow)]
+pub struct CachedDerivative {
+    pub cache_key: String,
+    pub image_id: String,
+    pub dependencies: String,
+    pub storage_key: String,
+    pub format: String,
+    pub size: i64,
+    pub last_accessed_at: i64,
+}
+
+#[async_trait::async_trait]
+pub trait DerivativeCacheRepository: Send + Sync {
+    async fn find(&self, cache_key: &str) -> Result<Option<CachedDerivative>, ServiceError>;
+    // Replaces any existing entry with the same key
+    async fn insert(&self, entry: &CachedDerivative) -> Result<(), ServiceError>;
+    async fn touch(&self, cache_key: &str, accessed_at: i64) -> Result<(), ServiceError>;
+    async fn remove(&self, cache_key: &str) -> Result<(), ServiceError>;
+    async fn total_size(&self) -> Result<i64, ServiceError>;
+    async fn least_recently_used(&self, limit: i64) -> Result<Vec<CachedDerivative>, ServiceError>;
+}
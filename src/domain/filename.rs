@@ -0,0 +1,150 @@
+// Client-supplied filenames are only kept as display metadata, never used to build storage paths
+
+pub const MAX_FILENAME_LEN: usize = 128;
+const FALLBACK_FILENAME: &str = "unnamed";
+// Device names Windows refuses as file names, with or without an extension
+const WINDOWS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub fn sanitize_filename(raw: &str) -> String {
+    // Drop any directory components, whichever separator the client used
+    let base = raw.rsplit(['/', '\\']).next().unwrap_or("");
+
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '.' | '-' | '_' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    // Leading dots would make "..", "." or hidden files
+    let trimmed = cleaned.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if trimmed.is_empty() {
+        return FALLBACK_FILENAME.to_string();
+    }
+
+    let stem = trimmed.split('.').next().unwrap_or_default().trim_end();
+    if WINDOWS_RESERVED.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) {
+        return truncate_preserving_extension(&format!("_{}", trimmed), MAX_FILENAME_LEN);
+    }
+
+    truncate_preserving_extension(trimmed, MAX_FILENAME_LEN)
+}
+
+fn truncate_preserving_extension(name: &str, max_len: usize) -> String {
+    if name.chars().count() <= max_len {
+        return name.to_string();
+    }
+
+    let (stem, extension) = match name.rfind('.') {
+        Some(idx) if name.len() - idx <= 10 => (&name[..idx], &name[idx..]),
+        _ => (name, ""),
+    };
+
+    let stem_len = max_len - extension.chars().count();
+    let stem: String = stem.chars().take(stem_len).collect();
+    format!("{}{}", stem.trim_end(), extension)
+}
+
+// Object key for a stored image; the extension comes from the detected format, not the client
+pub fn storage_key(image_id: &str, extension: &str) -> String {
+    format!("{}.{}", image_id, extension)
+}
+
+// Display name for an image derived from `original`, with the extension of the format produced
+pub fn derived_filename(original: &str, extension: &str) -> String {
+    let stem = original
+        .rsplit_once('.')
+        .map(|(stem, _)| stem)
+        .unwrap_or(original);
+    format!("{}.{}", stem, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_directory_components() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("..\\..\\windows\\system.ini"), "system.ini");
+        assert_eq!(sanitize_filename("/var/www/photo.png"), "photo.png");
+        assert_eq!(sanitize_filename("C:\\Users\\me\\photo.png"), "photo.png");
+        assert_eq!(sanitize_filename("photos/"), "unnamed");
+        assert_eq!(sanitize_filename(".."), "unnamed");
+        assert_eq!(sanitize_filename("../.."), "unnamed");
+    }
+
+    #[test]
+    fn removes_nul_and_control_characters() {
+        assert_eq!(sanitize_filename("evil\0.png"), "evil.png");
+        assert_eq!(sanitize_filename("photo.png\0.exe"), "photo.png.exe");
+        assert_eq!(sanitize_filename("line\r\nbreak\t.png"), "linebreak.png");
+        assert_eq!(sanitize_filename("\u{7}\u{1b}[31m.png"), "_31m.png");
+    }
+
+    #[test]
+    fn neutralises_bidi_overrides() {
+        // "photo<RLO>gnp.exe" displays as "photoexe.png"
+        assert_eq!(sanitize_filename("photo\u{202e}gnp.exe"), "photo_gnp.exe");
+        assert_eq!(sanitize_filename("\u{200f}\u{2066}name.jpg"), "__name.jpg");
+    }
+
+    #[test]
+    fn restricts_characters_and_hidden_names() {
+        assert_eq!(sanitize_filename("my photo (1).png"), "my photo _1_.png");
+        assert_eq!(sanitize_filename("a<b>c:d\"e|f?g*.png"), "a_b_c_d_e_f_g_.png");
+        assert_eq!(sanitize_filename(".htaccess"), "htaccess");
+        assert_eq!(sanitize_filename("  photo.png. "), "photo.png");
+        assert_eq!(sanitize_filename("фото.png"), "фото.png");
+        assert_eq!(sanitize_filename(""), "unnamed");
+    }
+
+    #[test]
+    fn prefixes_windows_reserved_names() {
+        assert_eq!(sanitize_filename("CON"), "_CON");
+        assert_eq!(sanitize_filename("con.png"), "_con.png");
+        assert_eq!(sanitize_filename("Lpt1.tar.gz"), "_Lpt1.tar.gz");
+        assert_eq!(sanitize_filename("nul .jpg"), "_nul .jpg");
+        assert_eq!(sanitize_filename("console.png"), "console.png");
+        assert_eq!(sanitize_filename("COM10.png"), "COM10.png");
+    }
+
+    #[test]
+    fn truncates_long_names_keeping_the_extension() {
+        let long = format!("{}.png", "a".repeat(500));
+        let sanitized = sanitize_filename(&long);
+        assert_eq!(sanitized.chars().count(), MAX_FILENAME_LEN);
+        assert!(sanitized.ends_with(".png"));
+
+        // Multi-byte characters are counted as characters, never split
+        let sanitized = sanitize_filename(&format!("{}.jpeg", "é".repeat(300)));
+        assert_eq!(sanitized.chars().count(), MAX_FILENAME_LEN);
+        assert!(sanitized.ends_with(".jpeg"));
+
+        // Anything after a far-away dot is not treated as an extension
+        let sanitized = sanitize_filename(&format!("a.{}", "b".repeat(300)));
+        assert_eq!(sanitized.chars().count(), MAX_FILENAME_LEN);
+        assert!(sanitized.starts_with("a.bbb"));
+    }
+
+    #[test]
+    fn truncation_drops_trailing_whitespace_of_the_stem() {
+        assert_eq!(truncate_preserving_extension("abc   def.png", 9), "abc.png");
+        assert_eq!(truncate_preserving_extension("short.png", 9), "short.png");
+    }
+
+    #[test]
+    fn storage_keys_ignore_the_client_name() {
+        assert_eq!(storage_key("1234", "png"), "1234.png");
+        assert_eq!(derived_filename("holiday.jpeg", "webp"), "holiday.webp");
+        assert_eq!(derived_filename("noext", "png"), "noext.png");
+    }
+}
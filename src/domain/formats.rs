@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+// Format name asking the service to choose from the request's Accept header
+pub const AUTO_FORMAT: &str = "auto";
+
+// Formats the service can encode transformation results to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    WebP,
+    Gif,
+    Bmp,
+    Tiff,
+    Ico,
+    Avif,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 8] = [
+        OutputFormat::Jpeg,
+        OutputFormat::Png,
+        OutputFormat::WebP,
+        OutputFormat::Gif,
+        OutputFormat::Bmp,
+        OutputFormat::Tiff,
+        OutputFormat::Ico,
+        OutputFormat::Avif,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(OutputFormat::Jpeg),
+            "png" => Some(OutputFormat::Png),
+            "webp" => Some(OutputFormat::WebP),
+            "gif" => Some(OutputFormat::Gif),
+            "bmp" => Some(OutputFormat::Bmp),
+            "tiff" | "tif" => Some(OutputFormat::Tiff),
+            "ico" => Some(OutputFormat::Ico),
+            "avif" => Some(OutputFormat::Avif),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpeg",
+            OutputFormat::Png => "png",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Gif => "gif",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::Ico => "ico",
+            OutputFormat::Avif => "avif",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Png => "image/png",
+            OutputFormat::WebP => "image/webp",
+            OutputFormat::Gif => "image/gif",
+            OutputFormat::Bmp => "image/bmp",
+            OutputFormat::Tiff => "image/tiff",
+            OutputFormat::Ico => "image/x-icon",
+            OutputFormat::Avif => "image/avif",
+        }
+    }
+
+    // AVIF, then WebP when the client accepts them; otherwise PNG for transparent output, else JPEG
+    pub fn negotiate(accepted: &[OutputFormat], transparent: bool) -> OutputFormat {
+        [OutputFormat::Avif, OutputFormat::WebP]
+            .into_iter()
+            .find(|format| accepted.contains(format))
+            .unwrap_or(if transparent { OutputFormat::Png } else { OutputFormat::Jpeg })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Tiff => "tiff",
+            other => other.name(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JpegOptions {
+    // 1-100, defaults to 80
+    pub quality: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    #[default]
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebpOptions {
+    // The encoder only produces lossless WebP
+    pub lossless: bool,
+}
+
+impl Default for WebpOptions {
+    fn default() -> Self {
+        Self { lossless: true }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AvifOptions {
+    // 1-100, defaults to 80
+    pub quality: Option<u8>,
+    // 1 (slowest, smallest) to 10 (fastest), defaults to 8
+    pub speed: Option<u8>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncoderOptions {
+    pub jpeg: JpegOptions,
+    pub png: PngOptions,
+    pub webp: WebpOptions,
+    pub avif: AvifOptions,
+}
+
+impl EncoderOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(quality) = self.jpeg.quality {
+            if !(1..=100).contains(&quality) {
+                return Err("jpeg.quality must be between 1 and 100".to_string());
+            }
+        }
+        if !self.webp.lossless {
+            return Err("webp.lossless must be true, lossy WebP encoding is not supported".to_string());
+        }
+        if let Some(quality) = self.avif.quality {
+            if !(1..=100).contains(&quality) {
+                return Err("avif.quality must be between 1 and 100".to_string());
+            }
+        }
+        if let Some(speed) = self.avif.speed {
+            if !(1..=10).contains(&speed) {
+                return Err("avif.speed must be between 1 and 10".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::image::ImageTransformation;
+
+    #[test]
+    fn names_round_trip() {
+        for format in OutputFormat::ALL {
+            assert_eq!(OutputFormat::from_name(format.name()), Some(format));
+            assert_eq!(OutputFormat::from_name(&format.name().to_uppercase()), Some(format));
+            // The serde name is the registry name
+            assert_eq!(serde_json::to_value(format).unwrap(), format.name());
+        }
+        assert_eq!(OutputFormat::from_name("jpg"), Some(OutputFormat::Jpeg));
+        assert_eq!(OutputFormat::from_name("tif"), Some(OutputFormat::Tiff));
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        for name in ["", "heic", "jxl", "svg", "image/png", ".png", AUTO_FORMAT] {
+            assert_eq!(OutputFormat::from_name(name), None, "{}", name);
+        }
+
+        let transformation: ImageTransformation =
+            serde_json::from_value(serde_json::json!({ "resize": { "width": 10 }, "format": "heic" })).unwrap();
+        let err = transformation.validate().unwrap_err().to_string();
+        assert!(err.contains("Unsupported format 'heic'") && err.contains("avif, auto"), "{}", err);
+    }
+
+    #[test]
+    fn mime_types_and_extensions() {
+        let expected = [
+            (OutputFormat::Jpeg, "image/jpeg", "jpg"),
+            (OutputFormat::Png, "image/png", "png"),
+            (OutputFormat::WebP, "image/webp", "webp"),
+            (OutputFormat::Gif, "image/gif", "gif"),
+            (OutputFormat::Bmp, "image/bmp", "bmp"),
+            (OutputFormat::Tiff, "image/tiff", "tiff"),
+            (OutputFormat::Ico, "image/x-icon", "ico"),
+            (OutputFormat::Avif, "image/avif", "avif"),
+        ];
+        assert_eq!(expected.len(), OutputFormat::ALL.len());
+        for (format, mime_type, extension) in expected {
+            assert_eq!(format.mime_type(), mime_type);
+            assert_eq!(format.extension(), extension);
+            // Extensions name the format again, so stored keys can be read back
+            assert_eq!(OutputFormat::from_name(extension), Some(format));
+        }
+    }
+
+    fn options(json: serde_json::Value) -> EncoderOptions {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn quality_bounds() {
+        for (json, valid) in [
+            (serde_json::json!({}), true),
+            (serde_json::json!({ "jpeg": { "quality": 1 } }), true),
+            (serde_json::json!({ "jpeg": { "quality": 100 } }), true),
+            (serde_json::json!({ "jpeg": { "quality": 0 } }), false),
+            (serde_json::json!({ "jpeg": { "quality": 101 } }), false),
+            (serde_json::json!({ "avif": { "quality": 1, "speed": 1 } }), true),
+            (serde_json::json!({ "avif": { "quality": 100, "speed": 10 } }), true),
+            (serde_json::json!({ "avif": { "quality": 0 } }), false),
+            (serde_json::json!({ "avif": { "quality": 101 } }), false),
+            (serde_json::json!({ "avif": { "speed": 0 } }), false),
+            (serde_json::json!({ "avif": { "speed": 11 } }), false),
+            (serde_json::json!({ "webp": { "lossless": true } }), true),
+            (serde_json::json!({ "webp": { "lossless": false } }), false),
+        ] {
+            assert_eq!(options(json.clone()).validate().is_ok(), valid, "{}", json);
+        }
+    }
+
+    #[test]
+    fn png_options_are_named() {
+        let options = options(serde_json::json!({ "png": { "compression": "best", "filter": "paeth" } }));
+        assert_eq!(options.png.compression, PngCompression::Best);
+        assert_eq!(options.png.filter, PngFilter::Paeth);
+
+        assert!(serde_json::from_value::<EncoderOptions>(serde_json::json!({ "png": { "compression": "max" } })).is_err());
+        assert!(serde_json::from_value::<EncoderOptions>(serde_json::json!({ "jpeg": { "quality": 300 } })).is_err());
+    }
+}
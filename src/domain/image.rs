@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use crate::domain::formats::EncoderOptions;
+use crate::domain::transformations::{Color, FlipDirection, Gravity, Operation, ResizeFilter, ResizeMode};
 
 #[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct Image {
@@ -9,23 +11,128 @@ pub struct Image {
     pub original_filename: String,
     pub file_size: i64,
     pub mime_type: String,
-    pub storage_path: String,
+    // Object key within the configured storage backend
+    pub storage_key: String,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub color_type: Option<String>,
+    pub bit_depth: Option<i64>,
+    // Hex SHA-256 of the stored bytes
+    pub content_hash: Option<String>,
+    // Set on images saved from a transformation of another image
+    pub parent_id: Option<String>,
+    // Serialized ImageTransformation that produced this image from its parent
+    pub transformation: Option<String>,
     pub created_at: Option<String>,
+    // Set while the image is in the trash
+    pub deleted_at: Option<String>,
+    // Shared by the images one delete moved to the trash
+    pub trash_batch_id: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Image {
+    // Based on the colour type recorded at upload; unknown counts as opaque
+    pub fn has_alpha(&self) -> bool {
+        self.color_type
+            .as_deref()
+            .is_some_and(|color_type| color_type.starts_with("la") || color_type.starts_with("rgba"))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ImageTransformation {
+    // Ordered pipeline; when empty the legacy fields below are used instead
+    #[serde(default)]
+    pub operations: Vec<Operation>,
+    // Apply the EXIF Orientation tag before any other step
+    #[serde(default)]
+    pub auto_orient: bool,
     pub resize: Option<Resize>,
     pub crop: Option<Crop>,
     pub rotate: Option<f32>,
+    pub flip: Option<FlipDirection>,
+    pub watermark: Option<Watermark>,
+    // Output format name, JPEG when omitted
     pub format: Option<String>,
+    // Per-format encoder settings: "jpeg", "png", "webp" and "avif"
+    #[serde(default, flatten)]
+    pub encoder: EncoderOptions,
     pub filters: Option<Filters>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Resize {
-    pub width: u32,
-    pub height: u32,
+    // Either dimension may be omitted to derive it from the aspect ratio
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub mode: ResizeMode,
+    #[serde(default)]
+    pub filter: ResizeFilter,
+    // Which part of the image to keep for `cover`, or where to place it for `pad`
+    #[serde(default)]
+    pub gravity: Gravity,
+    #[serde(default)]
+    pub no_upscale: bool,
+    // Canvas colour for `pad`, transparent by default
+    #[serde(default)]
+    pub background: Option<Color>,
+}
+
+// Either `text` or `image` must be given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Watermark {
+    pub text: Option<TextWatermark>,
+    pub image: Option<ImageWatermark>,
+    #[serde(default = "default_watermark_gravity")]
+    pub gravity: Gravity,
+    // Distance from the anchored edges, or the gap between tiles when `tile` is set
+    #[serde(default)]
+    pub offset_x: i32,
+    #[serde(default)]
+    pub offset_y: i32,
+    #[serde(default)]
+    pub tile: bool,
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f32,
+    // Clockwise, in degrees
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+fn default_watermark_gravity() -> Gravity {
+    Gravity::SouthEast
+}
+
+fn default_watermark_opacity() -> f32 {
+    0.5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextWatermark {
+    pub content: String,
+    // Font file name without extension, looked up in the configured fonts directory or bundled
+    pub font: Option<String>,
+    #[serde(default = "default_font_size")]
+    pub size: f32,
+    #[serde(default = "default_text_color")]
+    pub color: Color,
+}
+
+fn default_font_size() -> f32 {
+    32.0
+}
+
+fn default_text_color() -> Color {
+    Color { r: 255, g: 255, b: 255, a: 255 }
+}
+
+// Another image owned by the same user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageWatermark {
+    pub image_id: String,
+    // Overlay width as a fraction of the base image width; original size when omitted
+    pub scale: Option<f32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,19 +143,117 @@ pub struct Crop {
     pub height: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Filters {
     pub grayscale: bool,
     pub sepia: bool,
+    pub brightness: Option<i32>,
+    pub contrast: Option<f32>,
+    pub saturation: Option<f32>,
+    pub hue_rotate: Option<i32>,
+    pub gamma: Option<f32>,
+    pub tint: Option<Tint>,
+    pub invert: bool,
+    pub posterize: Option<u8>,
+    pub threshold: Option<u8>,
     pub blur: Option<f32>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tint {
+    pub color: Color,
+    pub strength: f32,
+}
+
+impl Filters {
+    // Fixed order used when filters are given as a set rather than as pipeline steps
+    pub fn to_operations(&self) -> Vec<Operation> {
+        let mut operations = Vec::new();
+
+        if self.grayscale {
+            operations.push(Operation::Grayscale);
+        }
+        if self.sepia {
+            operations.push(Operation::Sepia);
+        }
+        if let Some(value) = self.brightness {
+            operations.push(Operation::Brightness { value });
+        }
+        if let Some(value) = self.contrast {
+            operations.push(Operation::Contrast { value });
+        }
+        if let Some(factor) = self.saturation {
+            operations.push(Operation::Saturation { factor });
+        }
+        if let Some(degrees) = self.hue_rotate {
+            operations.push(Operation::HueRotate { degrees });
+        }
+        if let Some(value) = self.gamma {
+            operations.push(Operation::Gamma { value });
+        }
+        if let Some(tint) = &self.tint {
+            operations.push(Operation::Tint { color: tint.color, strength: tint.strength });
+        }
+        if self.invert {
+            operations.push(Operation::Invert);
+        }
+        if let Some(levels) = self.posterize {
+            operations.push(Operation::Posterize { levels });
+        }
+        if let Some(level) = self.threshold {
+            operations.push(Operation::Threshold { level });
+        }
+        if let Some(sigma) = self.blur {
+            operations.push(Operation::Blur { sigma });
+        }
+
+        operations
+    }
+}
+
+// Selects a user's images for batch operations; every given condition must match
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageFilter {
+    pub mime_type: Option<String>,
+    // Compared against created_at, e.g. "2024-01-01" or "2024-01-01 12:00:00"
+    pub created_after: Option<String>,
+    pub created_before: Option<String>,
+    // Case-insensitive substring of the original filename
+    pub filename_contains: Option<String>,
+}
+
 // Repository trait برای تصاویر
 #[async_trait::async_trait]
 pub trait ImageRepository: Send + Sync {
     async fn create_image(&self, image: &Image) -> Result<Image, crate::core::error::ServiceError>;
     async fn find_by_id(&self, id: &str) -> Result<Option<Image>, crate::core::error::ServiceError>;
+    // Listing and matching skip images in the trash; lookups by id include them
     async fn find_by_user_id(&self, user_id: &str, page: i64, limit: i64) -> Result<Vec<Image>, crate::core::error::ServiceError>;
-    #[allow(dead_code)]
-    async fn delete_image(&self, id: &str, user_id: &str) -> Result<bool, crate::core::error::ServiceError>;
+    async fn find_matching(&self, user_id: &str, filter: &ImageFilter, limit: i64) -> Result<Vec<Image>, crate::core::error::ServiceError>;
+    async fn update_content_hash(&self, id: &str, content_hash: &str) -> Result<(), crate::core::error::ServiceError>;
+    // Includes children in the trash
+    async fn find_children(&self, parent_id: &str) -> Result<Vec<Image>, crate::core::error::ServiceError>;
+    // Detaches the derivatives of a deleted parent
+    async fn clear_parent(&self, parent_id: &str) -> Result<u64, crate::core::error::ServiceError>;
+    // Deletes the row, the cached derivatives depending on it and the results of its jobs,
+    // queueing their stored objects for deletion in the same transaction. Returns the queued
+    // keys, or None when no such image exists.
+    async fn delete_image(&self, id: &str, user_id: &str) -> Result<Option<Vec<String>>, crate::core::error::ServiceError>;
+    // Moves the images to the trash in one transaction, tagged with the batch id. The first is
+    // the image being deleted: when it is missing or already in the trash nothing changes.
+    // Returns the ids actually trashed; the others were missing or already there.
+    async fn trash_images(&self, ids: &[String], user_id: &str, deleted_at: &str, batch_id: &str) -> Result<Vec<String>, crate::core::error::ServiceError>;
+    // Takes the images out of the trash in one transaction; the first is the image being
+    // restored, and false means it was no longer in the trash and nothing changed
+    async fn restore_images(&self, ids: &[String], user_id: &str) -> Result<bool, crate::core::error::ServiceError>;
+    // Most recently deleted first
+    async fn find_trashed(&self, user_id: &str, page: i64, limit: i64) -> Result<Vec<Image>, crate::core::error::ServiceError>;
+    // Images of any user deleted at or before `cutoff`
+    async fn find_expired_trash(&self, cutoff: &str, limit: i64) -> Result<Vec<Image>, crate::core::error::ServiceError>;
+    // Queues a stored object that no row refers to for the purger
+    async fn queue_storage_deletion(&self, storage_key: &str) -> Result<(), crate::core::error::ServiceError>;
+    async fn pending_storage_deletions(&self, limit: i64) -> Result<Vec<String>, crate::core::error::ServiceError>;
+    async fn complete_storage_deletion(&self, storage_key: &str) -> Result<(), crate::core::error::ServiceError>;
 }
\ No newline at end of file
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::core::error::ServiceError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Job {
+    pub id: String,
+    pub user_id: String,
+    pub image_id: String,
+    // Serialized ImageTransformation
+    pub transformation: String,
+    pub status: String,
+    pub progress: i64,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub error: Option<String>,
+    // Storage key and format name of the result once the job has succeeded
+    pub result_key: Option<String>,
+    pub result_format: Option<String>,
+    pub available_at: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait JobRepository: Send + Sync {
+    async fn create_job(&self, job: &Job) -> Result<Job, ServiceError>;
+    async fn find_by_id(&self, id: &str) -> Result<Option<Job>, ServiceError>;
+    // Atomically moves the oldest runnable queued job to running and counts the attempt
+    async fn claim_next(&self, now: i64) -> Result<Option<Job>, ServiceError>;
+    async fn update_progress(&self, id: &str, progress: i64) -> Result<(), ServiceError>;
+    async fn mark_succeeded(&self, id: &str, result_key: &str, result_format: &str) -> Result<(), ServiceError>;
+    async fn mark_failed(&self, id: &str, error: &str) -> Result<(), ServiceError>;
+    // Puts the job back in the queue after a failed attempt
+    async fn retry_later(&self, id: &str, error: &str, available_at: i64) -> Result<(), ServiceError>;
+    // Jobs left running by a previous process on their last attempt are failed; returns their ids
+    async fn fail_exhausted_running(&self, error: &str) -> Result<Vec<String>, ServiceError>;
+    // Jobs left running by a previous process with attempts to spare go back to the queue;
+    // returns how many
+    async fn requeue_running(&self) -> Result<u64, ServiceError>;
+    // Queues the stored results of jobs completed at or before `cutoff` for deletion and clears
+    // them from the jobs; returns how many were expired
+    async fn expire_results(&self, cutoff: &str) -> Result<u64, ServiceError>;
+}
@@ -2,6 +2,12 @@ pub mod user;
 pub mod user_repository;
 pub mod image;
 pub mod transformations;
+pub mod filename;
+pub mod formats;
+pub mod render_query;
+pub mod derivative_cache;
+pub mod job;
+pub mod webhook;
 
 #[allow(unused_imports)]
 pub use user_repository::UserRepository;
@@ -0,0 +1,435 @@
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::str::FromStr;
+use crate::core::error::ServiceError;
+use crate::domain::image::{Crop, Filters, ImageTransformation, Resize};
+
+// Query parameters understood by GET /api/images/:id/render, mapped onto the same
+// legacy fields as the JSON body of POST /api/images/:id/transform
+pub const RENDER_PARAMS: &[&str] = &[
+    "w", "h", "fit", "filter", "gravity", "bg", "noup", "crop", "rot", "flip", "orient",
+    "gray", "sepia", "invert", "bri", "con", "sat", "hue", "gamma", "blur", "fmt", "q",
+];
+
+impl ImageTransformation {
+    // Parameters may appear at most once; unknown parameters are rejected so typos are not silently ignored
+    pub fn from_query(params: &[(String, String)]) -> Result<Self, ServiceError> {
+        let mut seen = HashSet::new();
+        let mut transformation = ImageTransformation::default();
+        let mut resize: Option<Resize> = None;
+        let mut filters = Filters::default();
+
+        for (key, value) in params {
+            if !RENDER_PARAMS.contains(&key.as_str()) {
+                return Err(invalid(key, &format!("unknown parameter, expected one of {}", RENDER_PARAMS.join(", "))));
+            }
+            if !seen.insert(key.as_str()) {
+                return Err(invalid(key, "given more than once"));
+            }
+
+            match key.as_str() {
+                "w" => resize_mut(&mut resize).width = Some(number(key, value)?),
+                "h" => resize_mut(&mut resize).height = Some(number(key, value)?),
+                "fit" => resize_mut(&mut resize).mode = named(key, value)?,
+                "filter" => resize_mut(&mut resize).filter = named(key, value)?,
+                "gravity" => resize_mut(&mut resize).gravity = named(key, value)?,
+                "bg" => {
+                    let hex = if value.starts_with('#') { value.clone() } else { format!("#{}", value) };
+                    resize_mut(&mut resize).background = Some(named(key, &hex)?);
+                }
+                "noup" => resize_mut(&mut resize).no_upscale = flag(key, value)?,
+                "crop" => transformation.crop = Some(crop(key, value)?),
+                "rot" => transformation.rotate = Some(number(key, value)?),
+                "flip" => {
+                    let direction = match value.as_str() {
+                        "h" => "horizontal",
+                        "v" => "vertical",
+                        other => other,
+                    };
+                    transformation.flip = Some(named(key, direction)?);
+                }
+                "orient" => transformation.auto_orient = flag(key, value)?,
+                "gray" => filters.grayscale = flag(key, value)?,
+                "sepia" => filters.sepia = flag(key, value)?,
+                "invert" => filters.invert = flag(key, value)?,
+                "bri" => filters.brightness = Some(number(key, value)?),
+                "con" => filters.contrast = Some(number(key, value)?),
+                "sat" => filters.saturation = Some(number(key, value)?),
+                "hue" => filters.hue_rotate = Some(number(key, value)?),
+                "gamma" => filters.gamma = Some(number(key, value)?),
+                "blur" => filters.blur = Some(number(key, value)?),
+                "fmt" => transformation.format = Some(value.to_lowercase()),
+                "q" => {
+                    // Quality applies to whichever lossy encoder ends up being used
+                    let quality = number(key, value)?;
+                    transformation.encoder.jpeg.quality = Some(quality);
+                    transformation.encoder.avif.quality = Some(quality);
+                }
+                _ => return Err(invalid(key, "parameter is not supported")),
+            }
+        }
+
+        if let Some(resize) = &resize {
+            if resize.width.is_none() && resize.height.is_none() {
+                return Err(ServiceError::ValidationError(
+                    "fit, filter, gravity, bg and noup require w or h".to_string(),
+                ));
+            }
+        }
+        transformation.resize = resize;
+        if !filters.to_operations().is_empty() {
+            transformation.filters = Some(filters);
+        }
+
+        Ok(transformation)
+    }
+}
+
+fn invalid(key: &str, reason: &str) -> ServiceError {
+    ServiceError::ValidationError(format!("Query parameter '{}': {}", key, reason))
+}
+
+fn resize_mut(resize: &mut Option<Resize>) -> &mut Resize {
+    resize.get_or_insert_with(|| Resize {
+        width: None,
+        height: None,
+        mode: Default::default(),
+        filter: Default::default(),
+        gravity: Default::default(),
+        no_upscale: false,
+        background: None,
+    })
+}
+
+fn number<T: FromStr>(key: &str, value: &str) -> Result<T, ServiceError> {
+    value.trim().parse().map_err(|_| invalid(key, &format!("'{}' is not a valid number", value)))
+}
+
+// 1/0, true/false, yes/no; an empty value (`?gray`) counts as true
+fn flag(key: &str, value: &str) -> Result<bool, ServiceError> {
+    match value.to_lowercase().as_str() {
+        "" | "1" | "true" | "yes" => Ok(true),
+        "0" | "false" | "no" => Ok(false),
+        _ => Err(invalid(key, &format!("'{}' is not a boolean", value))),
+    }
+}
+
+// Names are deserialized through serde so they stay identical to the JSON route
+fn named<T: DeserializeOwned>(key: &str, value: &str) -> Result<T, ServiceError> {
+    serde_json::from_value(serde_json::Value::String(value.to_lowercase()))
+        .map_err(|_| invalid(key, &format!("'{}' is not a valid value", value)))
+}
+
+// x,y,width,height
+fn crop(key: &str, value: &str) -> Result<Crop, ServiceError> {
+    let parts = value
+        .split(',')
+        .map(|part| number::<u32>(key, part))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match parts.as_slice() {
+        [x, y, width, height] => Ok(Crop { x: *x, y: *y, width: *width, height: *height }),
+        _ => Err(invalid(key, "expected x,y,width,height")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::transformations::{Color, FlipDirection, Gravity, ResizeFilter, ResizeMode};
+
+    // Query strings here are already decoded, so '#' and ',' appear literally
+    fn parse(query: &str) -> Result<ImageTransformation, ServiceError> {
+        let params: Vec<(String, String)> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => (pair.to_string(), String::new()),
+            })
+            .collect();
+        ImageTransformation::from_query(&params)
+    }
+
+    fn parsed(query: &str) -> ImageTransformation {
+        let transformation = parse(query).unwrap_or_else(|e| panic!("{} was rejected: {}", query, e));
+        transformation.validate().unwrap_or_else(|e| panic!("{} failed validation: {}", query, e));
+        transformation
+    }
+
+    // Rejected while parsing, naming the parameter
+    fn malformed(query: &str) {
+        let key = query.split('=').next().unwrap();
+        match parse(query) {
+            Err(ServiceError::ValidationError(message)) => {
+                assert!(message.contains(&format!("'{}'", key)), "{}: {}", query, message)
+            }
+            other => panic!("{} was not rejected: {:?}", query, other.map(|_| ())),
+        }
+    }
+
+    // Well-formed, but refused by the same validation as the JSON route
+    fn out_of_range(query: &str) {
+        let transformation = parse(query).unwrap_or_else(|e| panic!("{} was rejected while parsing: {}", query, e));
+        assert!(
+            matches!(transformation.validate(), Err(ServiceError::ValidationError(_))),
+            "{} passed validation",
+            query
+        );
+    }
+
+    fn resize(query: &str) -> Resize {
+        parsed(query).resize.unwrap()
+    }
+
+    fn filters(query: &str) -> Filters {
+        parsed(query).filters.unwrap()
+    }
+
+    #[test]
+    fn width_and_height() {
+        let both = resize("w=300&h=200");
+        assert_eq!((both.width, both.height), (Some(300), Some(200)));
+        assert_eq!(resize("w=300").height, None);
+        assert_eq!(resize("h=200").width, None);
+        assert_eq!(resize("w= 300 ").width, Some(300));
+
+        out_of_range("w=0");
+        out_of_range("h=0");
+        for query in ["w=abc", "w=-5", "w=1.5", "w=", "h=4294967296"] {
+            malformed(query);
+        }
+    }
+
+    #[test]
+    fn fit() {
+        for (value, mode) in [
+            ("fit", ResizeMode::Fit),
+            ("fill", ResizeMode::Fill),
+            ("cover", ResizeMode::Cover),
+            ("exact", ResizeMode::Exact),
+            ("pad", ResizeMode::Pad),
+            ("COVER", ResizeMode::Cover),
+        ] {
+            assert_eq!(resize(&format!("w=10&fit={}", value)).mode, mode, "fit={}", value);
+        }
+        assert_eq!(resize("w=10").mode, ResizeMode::Fit);
+        malformed("fit=stretch&w=10");
+    }
+
+    #[test]
+    fn filter() {
+        for (value, filter) in [
+            ("nearest", ResizeFilter::Nearest),
+            ("triangle", ResizeFilter::Triangle),
+            ("catmull-rom", ResizeFilter::CatmullRom),
+            ("gaussian", ResizeFilter::Gaussian),
+            ("lanczos3", ResizeFilter::Lanczos3),
+        ] {
+            assert_eq!(resize(&format!("w=10&filter={}", value)).filter, filter, "filter={}", value);
+        }
+        malformed("filter=bicubic&w=10");
+        malformed("filter=catmull_rom&w=10");
+    }
+
+    #[test]
+    fn gravity() {
+        for (value, gravity) in [
+            ("center", Gravity::Center),
+            ("north", Gravity::North),
+            ("south", Gravity::South),
+            ("east", Gravity::East),
+            ("west", Gravity::West),
+            ("north_east", Gravity::NorthEast),
+            ("north_west", Gravity::NorthWest),
+            ("south_east", Gravity::SouthEast),
+            ("south_west", Gravity::SouthWest),
+        ] {
+            assert_eq!(resize(&format!("w=10&h=10&fit=cover&gravity={}", value)).gravity, gravity, "gravity={}", value);
+        }
+        malformed("gravity=top&w=10");
+    }
+
+    #[test]
+    fn background() {
+        assert_eq!(resize("w=10&bg=ff0000").background, Some(Color { r: 255, g: 0, b: 0, a: 255 }));
+        assert_eq!(resize("w=10&bg=#00ff0080").background, Some(Color { r: 0, g: 255, b: 0, a: 128 }));
+        assert_eq!(resize("w=10").background, None);
+        for value in ["red", "fff", "gg0000", "ff00000"] {
+            malformed(&format!("bg={}&w=10", value));
+        }
+    }
+
+    #[test]
+    fn no_upscale() {
+        for value in ["", "1", "true", "yes", "TRUE"] {
+            assert!(resize(&format!("w=10&noup={}", value)).no_upscale, "noup={}", value);
+        }
+        for value in ["0", "false", "no"] {
+            assert!(!resize(&format!("w=10&noup={}", value)).no_upscale, "noup={}", value);
+        }
+        assert!(!resize("w=10").no_upscale);
+        malformed("noup=maybe&w=10");
+    }
+
+    #[test]
+    fn crop_rectangle() {
+        let crop = parsed("crop=10,20,30,40").crop.unwrap();
+        assert_eq!((crop.x, crop.y, crop.width, crop.height), (10, 20, 30, 40));
+
+        out_of_range("crop=0,0,0,10");
+        out_of_range("crop=0,0,10,0");
+        for value in ["1,2,3", "1,2,3,4,5", "a,b,c,d", "-1,0,10,10", ""] {
+            malformed(&format!("crop={}", value));
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        assert_eq!(parsed("rot=90").rotate, Some(90.0));
+        assert_eq!(parsed("rot=-12.5").rotate, Some(-12.5));
+
+        for value in ["361", "-720", "NaN", "inf"] {
+            out_of_range(&format!("rot={}", value));
+        }
+        malformed("rot=right");
+    }
+
+    #[test]
+    fn flip() {
+        for (value, direction) in [
+            ("h", FlipDirection::Horizontal),
+            ("horizontal", FlipDirection::Horizontal),
+            ("v", FlipDirection::Vertical),
+            ("vertical", FlipDirection::Vertical),
+        ] {
+            assert_eq!(parsed(&format!("flip={}", value)).flip, Some(direction), "flip={}", value);
+        }
+        malformed("flip=x");
+        malformed("flip=both");
+    }
+
+    #[test]
+    fn orient() {
+        assert!(parsed("orient").auto_orient);
+        assert!(parsed("orient=1").auto_orient);
+        assert!(!parsed("orient=0&w=10").auto_orient);
+        malformed("orient=2");
+    }
+
+    #[test]
+    fn boolean_filters() {
+        assert!(filters("gray=1").grayscale);
+        assert!(filters("sepia=true").sepia);
+        assert!(filters("invert").invert);
+
+        // Turned off filters leave no filter step behind
+        assert!(parsed("gray=0&sepia=no&invert=false&w=10").filters.is_none());
+
+        malformed("gray=on");
+        malformed("sepia=2");
+        malformed("invert=x");
+    }
+
+    #[test]
+    fn brightness() {
+        assert_eq!(filters("bri=-40").brightness, Some(-40));
+        out_of_range("bri=256");
+        out_of_range("bri=-300");
+        malformed("bri=1.5");
+        malformed("bri=bright");
+    }
+
+    #[test]
+    fn contrast() {
+        assert_eq!(filters("con=12.5").contrast, Some(12.5));
+        out_of_range("con=101");
+        out_of_range("con=NaN");
+        malformed("con=high");
+    }
+
+    #[test]
+    fn saturation() {
+        assert_eq!(filters("sat=1.5").saturation, Some(1.5));
+        out_of_range("sat=-0.5");
+        out_of_range("sat=11");
+        malformed("sat=");
+    }
+
+    #[test]
+    fn hue() {
+        assert_eq!(filters("hue=90").hue_rotate, Some(90));
+        out_of_range("hue=361");
+        malformed("hue=90.5");
+    }
+
+    #[test]
+    fn gamma() {
+        assert_eq!(filters("gamma=2.2").gamma, Some(2.2));
+        out_of_range("gamma=0");
+        out_of_range("gamma=10.5");
+        malformed("gamma=x");
+    }
+
+    #[test]
+    fn blur() {
+        assert_eq!(filters("blur=1.5").blur, Some(1.5));
+        out_of_range("blur=0");
+        out_of_range("blur=-1");
+        malformed("blur=soft");
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(parsed("fmt=webp").format.as_deref(), Some("webp"));
+        assert_eq!(parsed("fmt=PNG").format.as_deref(), Some("png"));
+        assert_eq!(parsed("fmt=jpg").output_format().unwrap().name(), "jpeg");
+        assert!(parsed("fmt=auto").is_auto_format());
+        out_of_range("fmt=xyz");
+        out_of_range("fmt=");
+    }
+
+    #[test]
+    fn quality() {
+        let transformation = parsed("fmt=jpeg&q=75");
+        assert_eq!(transformation.encoder.jpeg.quality, Some(75));
+        assert_eq!(transformation.encoder.avif.quality, Some(75));
+
+        out_of_range("q=0&fmt=jpeg");
+        out_of_range("q=101&fmt=jpeg");
+        malformed("q=256&fmt=jpeg");
+        malformed("q=high&fmt=jpeg");
+    }
+
+    #[test]
+    fn parameters_may_only_appear_once() {
+        malformed("w=10&w=20");
+        malformed("gray=1&gray=0");
+        // Checked before the value, so a second malformed value is still reported as a duplicate
+        match parse("fmt=png&fmt=zzz") {
+            Err(ServiceError::ValidationError(message)) => assert!(message.contains("more than once"), "{}", message),
+            other => panic!("duplicate accepted: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn unknown_parameters_are_rejected() {
+        for query in ["width=10", "W=10", "quality=80", "w=10&rotate=90", ""] {
+            match parse(query) {
+                Err(ServiceError::ValidationError(message)) => assert!(message.contains("unknown parameter"), "{}", message),
+                other => panic!("{} was accepted: {:?}", query, other.map(|_| ())),
+            }
+        }
+        assert_eq!(RENDER_PARAMS.len(), 22);
+    }
+
+    #[test]
+    fn resize_options_require_a_dimension() {
+        for query in ["fit=cover", "gravity=north", "filter=nearest", "bg=ffffff", "noup=1", "fit=pad&gravity=south&fmt=png"] {
+            match parse(query) {
+                Err(ServiceError::ValidationError(message)) => assert!(message.contains("require w or h"), "{}: {}", query, message),
+                other => panic!("{} was accepted: {:?}", query, other.map(|_| ())),
+            }
+        }
+        assert!(parse("fit=cover&h=10").is_ok());
+    }
+}
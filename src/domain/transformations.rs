@@ -1 +1,436 @@
-// Transformations will be implemented later
\ No newline at end of file
+use serde::{Deserialize, Serialize};
+use crate::core::error::ServiceError;
+use crate::domain::formats::{OutputFormat, AUTO_FORMAT};
+use crate::domain::image::{Crop, ImageTransformation, Resize, Watermark};
+
+pub const MAX_PIPELINE_LENGTH: usize = 20;
+
+// RGBA colour written as "#rrggbb" or "#rrggbbaa"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color { r: 0, g: 0, b: 0, a: 0 };
+
+    pub fn to_rgba(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let hex = value.strip_prefix('#').unwrap_or(value);
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid colour '{}', expected #rrggbb or #rrggbbaa", value));
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+        Ok(Color {
+            r: channel(0),
+            g: channel(2),
+            b: channel(4),
+            a: if hex.len() == 8 { channel(6) } else { 255 },
+        })
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        if color.a == 255 {
+            format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", color.r, color.g, color.b, color.a)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlipDirection {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeMode {
+    // Scale to fit inside the box, keeping the aspect ratio
+    #[default]
+    Fit,
+    // Scale to cover the box, keeping the aspect ratio; may exceed the box
+    Fill,
+    // Like fill, then crop to exactly the box around `gravity`
+    Cover,
+    // Stretch to exactly the box, ignoring the aspect ratio
+    Exact,
+    // Like fit, then pad to exactly the box with `background`
+    Pad,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Gravity {
+    #[default]
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Gravity {
+    // Offset of the anchored item given the free space around it on each axis
+    pub fn offset(self, free_x: u32, free_y: u32) -> (u32, u32) {
+        let x = match self {
+            Gravity::West | Gravity::NorthWest | Gravity::SouthWest => 0,
+            Gravity::East | Gravity::NorthEast | Gravity::SouthEast => free_x,
+            Gravity::Center | Gravity::North | Gravity::South => free_x / 2,
+        };
+        let y = match self {
+            Gravity::North | Gravity::NorthEast | Gravity::NorthWest => 0,
+            Gravity::South | Gravity::SouthEast | Gravity::SouthWest => free_y,
+            Gravity::Center | Gravity::East | Gravity::West => free_y / 2,
+        };
+        (x, y)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// One step of a transformation pipeline; steps run in the order given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Resize(Resize),
+    Crop(Crop),
+    // Clockwise; `expand` grows the canvas to fit, `background` fills the uncovered corners
+    Rotate {
+        degrees: f32,
+        #[serde(default = "default_true")]
+        expand: bool,
+        #[serde(default)]
+        background: Option<Color>,
+    },
+    Flip { direction: FlipDirection },
+    Grayscale,
+    Sepia,
+    Brightness { value: i32 },
+    Contrast { value: f32 },
+    Saturation { factor: f32 },
+    HueRotate { degrees: i32 },
+    Gamma { value: f32 },
+    Tint { color: Color, strength: f32 },
+    Invert,
+    Posterize { levels: u8 },
+    Threshold { level: u8 },
+    Blur { sigma: f32 },
+    Watermark(Watermark),
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Resize(_) => "resize",
+            Operation::Crop(_) => "crop",
+            Operation::Rotate { .. } => "rotate",
+            Operation::Flip { .. } => "flip",
+            Operation::Grayscale => "grayscale",
+            Operation::Sepia => "sepia",
+            Operation::Brightness { .. } => "brightness",
+            Operation::Contrast { .. } => "contrast",
+            Operation::Saturation { .. } => "saturation",
+            Operation::HueRotate { .. } => "hue_rotate",
+            Operation::Gamma { .. } => "gamma",
+            Operation::Tint { .. } => "tint",
+            Operation::Invert => "invert",
+            Operation::Posterize { .. } => "posterize",
+            Operation::Threshold { .. } => "threshold",
+            Operation::Blur { .. } => "blur",
+            Operation::Watermark(_) => "watermark",
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Operation::Resize(resize) => {
+                if resize.width.is_none() && resize.height.is_none() {
+                    return Err("width or height is required".to_string());
+                }
+                if resize.width == Some(0) || resize.height == Some(0) {
+                    return Err("width and height must be greater than zero".to_string());
+                }
+            }
+            Operation::Crop(crop) => {
+                if crop.width == 0 || crop.height == 0 {
+                    return Err("width and height must be greater than zero".to_string());
+                }
+            }
+            Operation::Rotate { degrees, .. } => {
+                if !degrees.is_finite() {
+                    return Err("degrees must be a finite number".to_string());
+                }
+                if !(-360.0..=360.0).contains(degrees) {
+                    return Err("degrees must be between -360 and 360".to_string());
+                }
+            }
+            Operation::Flip { .. } | Operation::Grayscale | Operation::Sepia | Operation::Invert | Operation::Threshold { .. } => {}
+            Operation::Brightness { value } => {
+                if !(-255..=255).contains(value) {
+                    return Err("value must be between -255 and 255".to_string());
+                }
+            }
+            Operation::Contrast { value } => {
+                if !value.is_finite() || !(-100.0..=100.0).contains(value) {
+                    return Err("value must be between -100 and 100".to_string());
+                }
+            }
+            Operation::Saturation { factor } => {
+                if !factor.is_finite() || !(0.0..=10.0).contains(factor) {
+                    return Err("factor must be between 0 and 10".to_string());
+                }
+            }
+            Operation::HueRotate { degrees } => {
+                if !(-360..=360).contains(degrees) {
+                    return Err("degrees must be between -360 and 360".to_string());
+                }
+            }
+            Operation::Gamma { value } => {
+                if !value.is_finite() || *value < 0.1 || *value > 10.0 {
+                    return Err("value must be between 0.1 and 10".to_string());
+                }
+            }
+            Operation::Tint { strength, .. } => {
+                if !strength.is_finite() || !(0.0..=1.0).contains(strength) {
+                    return Err("strength must be between 0 and 1".to_string());
+                }
+            }
+            Operation::Posterize { levels } => {
+                if *levels < 2 {
+                    return Err("levels must be between 2 and 255".to_string());
+                }
+            }
+            Operation::Blur { sigma } => {
+                if !sigma.is_finite() || *sigma <= 0.0 {
+                    return Err("sigma must be a positive number".to_string());
+                }
+            }
+            Operation::Watermark(watermark) => watermark.validate()?,
+        }
+        Ok(())
+    }
+}
+
+pub const MAX_WATERMARK_TEXT_LEN: usize = 256;
+
+impl Watermark {
+    pub fn validate(&self) -> Result<(), String> {
+        match (&self.text, &self.image) {
+            (Some(text), None) => {
+                if text.content.trim().is_empty() {
+                    return Err("text.content must not be empty".to_string());
+                }
+                if text.content.chars().count() > MAX_WATERMARK_TEXT_LEN {
+                    return Err(format!("text.content must be at most {} characters", MAX_WATERMARK_TEXT_LEN));
+                }
+                if !text.size.is_finite() || !(1.0..=1000.0).contains(&text.size) {
+                    return Err("text.size must be between 1 and 1000".to_string());
+                }
+                if let Some(font) = &text.font {
+                    let valid = !font.is_empty()
+                        && font.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                    if !valid {
+                        return Err("text.font may only contain letters, digits, '-' and '_'".to_string());
+                    }
+                }
+            }
+            (None, Some(image)) => {
+                if image.image_id.trim().is_empty() {
+                    return Err("image.image_id must not be empty".to_string());
+                }
+                if let Some(scale) = image.scale {
+                    if !scale.is_finite() || scale <= 0.0 || scale > 1.0 {
+                        return Err("image.scale must be greater than 0 and at most 1".to_string());
+                    }
+                }
+            }
+            _ => return Err("exactly one of text or image is required".to_string()),
+        }
+
+        if !self.opacity.is_finite() || !(0.0..=1.0).contains(&self.opacity) {
+            return Err("opacity must be between 0 and 1".to_string());
+        }
+        if !self.rotation.is_finite() || !(-360.0..=360.0).contains(&self.rotation) {
+            return Err("rotation must be between -360 and 360".to_string());
+        }
+        if self.tile && (self.offset_x < 0 || self.offset_y < 0) {
+            return Err("offset_x and offset_y must not be negative when tiling".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl ImageTransformation {
+    fn has_legacy_fields(&self) -> bool {
+        self.resize.is_some()
+            || self.crop.is_some()
+            || self.rotate.is_some()
+            || self.flip.is_some()
+            || self.filters.is_some()
+            || self.watermark.is_some()
+    }
+
+    // The legacy optional fields always run as resize, crop, rotate, flip, filters, then watermark
+    fn legacy_pipeline(&self) -> Vec<Operation> {
+        let mut operations = Vec::new();
+
+        if let Some(resize) = &self.resize {
+            operations.push(Operation::Resize(resize.clone()));
+        }
+        if let Some(crop) = &self.crop {
+            operations.push(Operation::Crop(crop.clone()));
+        }
+        if let Some(degrees) = self.rotate {
+            operations.push(Operation::Rotate { degrees, expand: true, background: None });
+        }
+        if let Some(direction) = self.flip {
+            operations.push(Operation::Flip { direction });
+        }
+        if let Some(filters) = &self.filters {
+            operations.extend(filters.to_operations());
+        }
+        if let Some(watermark) = &self.watermark {
+            operations.push(Operation::Watermark(watermark.clone()));
+        }
+
+        operations
+    }
+
+    // Ids of other images the pipeline overlays, in order of first use
+    pub fn watermark_image_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = Vec::new();
+        for operation in self.pipeline() {
+            if let Operation::Watermark(Watermark { image: Some(image), .. }) = operation {
+                if !ids.contains(&image.image_id) {
+                    ids.push(image.image_id);
+                }
+            }
+        }
+        ids
+    }
+
+    pub fn pipeline(&self) -> Vec<Operation> {
+        if self.operations.is_empty() {
+            self.legacy_pipeline()
+        } else {
+            self.operations.clone()
+        }
+    }
+
+    pub fn is_auto_format(&self) -> bool {
+        self.format.as_deref().is_some_and(|name| name.eq_ignore_ascii_case(AUTO_FORMAT))
+    }
+
+    // Replaces "auto" with the negotiated format; returns whether negotiation took place
+    pub fn resolve_auto_format(&mut self, accepted: &[OutputFormat], source_has_alpha: bool) -> bool {
+        if !self.is_auto_format() {
+            return false;
+        }
+        let transparent = source_has_alpha || self.introduces_transparency();
+        self.format = Some(OutputFormat::negotiate(accepted, transparent).name().to_string());
+        true
+    }
+
+    // Steps that can leave transparent pixels in an otherwise opaque image
+    fn introduces_transparency(&self) -> bool {
+        let see_through = |background: Option<Color>| background.is_none_or(|color| color.a < 255);
+        self.pipeline().iter().any(|operation| match operation {
+            Operation::Rotate { degrees, background, .. } => degrees % 90.0 != 0.0 && see_through(*background),
+            Operation::Resize(resize) => resize.mode == ResizeMode::Pad && see_through(resize.background),
+            _ => false,
+        })
+    }
+
+    // "auto" must have been resolved before this is called
+    pub fn output_format(&self) -> Result<OutputFormat, ServiceError> {
+        match &self.format {
+            None => Ok(OutputFormat::Jpeg),
+            Some(name) => OutputFormat::from_name(name).ok_or_else(|| {
+                let mut supported: Vec<&str> = OutputFormat::ALL.iter().map(|f| f.name()).collect();
+                supported.push(AUTO_FORMAT);
+                ServiceError::ValidationError(format!(
+                    "Unsupported format '{}', expected one of: {}",
+                    name,
+                    supported.join(", ")
+                ))
+            }),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if !self.is_auto_format() {
+            self.output_format()?;
+        }
+        self.encoder.validate().map_err(ServiceError::ValidationError)?;
+
+        if !self.operations.is_empty() && self.has_legacy_fields() {
+            return Err(ServiceError::ValidationError(
+                "operations cannot be combined with resize, crop, rotate or filters".to_string(),
+            ));
+        }
+
+        let pipeline = self.pipeline();
+
+        // A request with no steps is only meaningful as a format conversion or re-orientation
+        if pipeline.is_empty() && self.format.is_none() && !self.auto_orient {
+            return Err(ServiceError::ValidationError("transformation pipeline is empty".to_string()));
+        }
+        if pipeline.len() > MAX_PIPELINE_LENGTH {
+            return Err(ServiceError::ValidationError(format!(
+                "transformation pipeline has {} steps, the maximum is {}",
+                pipeline.len(),
+                MAX_PIPELINE_LENGTH
+            )));
+        }
+
+        for (index, operation) in pipeline.iter().enumerate() {
+            operation.validate().map_err(|reason| {
+                ServiceError::ValidationError(format!("operations[{}] ({}): {}", index, operation.name(), reason))
+            })?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use crate::core::error::ServiceError;
+
+// Subscribes a webhook to every event
+pub const ALL_EVENTS: &str = "*";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    #[serde(rename = "image.uploaded")]
+    ImageUploaded,
+    #[serde(rename = "image.transformed")]
+    ImageTransformed,
+    #[serde(rename = "image.deleted")]
+    ImageDeleted,
+    #[serde(rename = "job.completed")]
+    JobCompleted,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 4] = [
+        WebhookEvent::ImageUploaded,
+        WebhookEvent::ImageTransformed,
+        WebhookEvent::ImageDeleted,
+        WebhookEvent::JobCompleted,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::ImageUploaded => "image.uploaded",
+            WebhookEvent::ImageTransformed => "image.transformed",
+            WebhookEvent::ImageDeleted => "image.deleted",
+            WebhookEvent::JobCompleted => "job.completed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Webhook {
+    pub id: String,
+    pub user_id: String,
+    pub url: String,
+    pub secret: String,
+    // Comma-separated event names, or ALL_EVENTS
+    pub events: String,
+    pub created_at: Option<String>,
+}
+
+impl Webhook {
+    pub fn event_names(&self) -> Vec<String> {
+        self.events.split(',').map(str::to_string).collect()
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    // Shared by the deliveries of one event to different webhooks
+    pub event_id: String,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    pub response_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub delivered_at: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait WebhookRepository: Send + Sync {
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, ServiceError>;
+    async fn find_by_id(&self, id: &str) -> Result<Option<Webhook>, ServiceError>;
+    async fn find_by_user_id(&self, user_id: &str) -> Result<Vec<Webhook>, ServiceError>;
+    async fn delete_webhook(&self, id: &str, user_id: &str) -> Result<bool, ServiceError>;
+    // Webhooks of `user_id` subscribed to `event`, directly or through ALL_EVENTS
+    async fn find_subscribed(&self, user_id: &str, event: &str) -> Result<Vec<Webhook>, ServiceError>;
+
+    async fn create_delivery(&self, delivery: &WebhookDelivery) -> Result<(), ServiceError>;
+    // Atomically marks up to `limit` due deliveries as delivering and counts the attempt
+    async fn claim_due_deliveries(&self, now: i64, limit: i64) -> Result<Vec<WebhookDelivery>, ServiceError>;
+    async fn mark_delivered(&self, id: &str, response_status: i64) -> Result<(), ServiceError>;
+    async fn schedule_retry(&self, id: &str, response_status: Option<i64>, error: &str, next_attempt_at: i64) -> Result<(), ServiceError>;
+    async fn mark_failed(&self, id: &str, response_status: Option<i64>, error: &str) -> Result<(), ServiceError>;
+    // Deliveries interrupted by a shutdown go back to pending; returns how many
+    async fn requeue_delivering(&self) -> Result<u64, ServiceError>;
+    // Most recent first
+    async fn find_deliveries(&self, webhook_id: &str, limit: i64) -> Result<Vec<WebhookDelivery>, ServiceError>;
+}
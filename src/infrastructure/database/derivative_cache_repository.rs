@@ -0,0 +1,94 @@
+use sqlx::SqlitePool;
+use crate::domain::derivative_cache::{CachedDerivative, DerivativeCacheRepository};
+use crate::core::error::ServiceError;
+
+#[derive(Clone)]
+pub struct SqliteDerivativeCacheRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteDerivativeCacheRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl DerivativeCacheRepository for SqliteDerivativeCacheRepository {
+    async fn find(&self, cache_key: &str) -> Result<Option<CachedDerivative>, ServiceError> {
+        sqlx::query_as::<_, CachedDerivative>(
+            r#"
+            SELECT cache_key, image_id, dependencies, storage_key, format, size, last_accessed_at
+            FROM derivative_cache WHERE cache_key = ?
+            "#,
+        )
+        .bind(cache_key)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to find cached derivative: {}", e)))
+    }
+
+    async fn insert(&self, entry: &CachedDerivative) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO derivative_cache (cache_key, image_id, dependencies, storage_key, format, size, last_accessed_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&entry.cache_key)
+        .bind(&entry.image_id)
+        .bind(&entry.dependencies)
+        .bind(&entry.storage_key)
+        .bind(&entry.format)
+        .bind(entry.size)
+        .bind(entry.last_accessed_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to insert cached derivative: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn touch(&self, cache_key: &str, accessed_at: i64) -> Result<(), ServiceError> {
+        sqlx::query("UPDATE derivative_cache SET last_accessed_at = ? WHERE cache_key = ?")
+            .bind(accessed_at)
+            .bind(cache_key)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to touch cached derivative: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, cache_key: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM derivative_cache WHERE cache_key = ?")
+            .bind(cache_key)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to remove cached derivative: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn total_size(&self) -> Result<i64, ServiceError> {
+        sqlx::query_scalar::<_, i64>("SELECT COALESCE(SUM(size), 0) FROM derivative_cache")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to sum derivative cache size: {}", e)))
+    }
+
+    async fn least_recently_used(&self, limit: i64) -> Result<Vec<CachedDerivative>, ServiceError> {
+        sqlx::query_as::<_, CachedDerivative>(
+            r#"
+            SELECT cache_key, image_id, dependencies, storage_key, format, size, last_accessed_at
+            FROM derivative_cache
+            ORDER BY last_accessed_at ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to list cached derivatives: {}", e)))
+    }
+}
@@ -1,5 +1,5 @@
 use sqlx::SqlitePool;
-use crate::domain::image::{Image, ImageRepository};
+use crate::domain::image::{Image, ImageFilter, ImageRepository};
 use crate::core::error::ServiceError;
 
 #[derive(Clone)]  // اضافه کردن این خط
@@ -11,32 +11,6 @@ impl SqliteImageRepository {
     pub fn new(pool: SqlitePool) -> Self {
         Self { pool }
     }
-
-    pub async fn create_table(&self) -> Result<(), ServiceError> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS images (
-                id TEXT PRIMARY KEY NOT NULL,
-                user_id TEXT NOT NULL,
-                filename TEXT NOT NULL,
-                original_filename TEXT NOT NULL,
-                file_size INTEGER NOT NULL,
-                mime_type TEXT NOT NULL,
-                storage_path TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (user_id) REFERENCES users (id)
-            )
-            "#
-        )
-        .execute(&self.pool)
-        .await
-        .map_err(|e| {
-            ServiceError::DatabaseError(format!("Failed to create images table: {}", e))
-        })?;
-
-        println!("✅ Images table created or already exists");
-        Ok(())
-    }
 }
 
 #[async_trait::async_trait]
@@ -44,8 +18,8 @@ impl ImageRepository for SqliteImageRepository {
     async fn create_image(&self, image: &Image) -> Result<Image, ServiceError> {
         let _result = sqlx::query(
             r#"
-            INSERT INTO images (id, user_id, filename, original_filename, file_size, mime_type, storage_path)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO images (id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&image.id)
@@ -54,7 +28,14 @@ impl ImageRepository for SqliteImageRepository {
         .bind(&image.original_filename)
         .bind(image.file_size)
         .bind(&image.mime_type)
-        .bind(&image.storage_path)
+        .bind(&image.storage_key)
+        .bind(image.width)
+        .bind(image.height)
+        .bind(&image.color_type)
+        .bind(image.bit_depth)
+        .bind(&image.content_hash)
+        .bind(&image.parent_id)
+        .bind(&image.transformation)
         .execute(&self.pool)
         .await
         .map_err(|e| {
@@ -63,7 +44,7 @@ impl ImageRepository for SqliteImageRepository {
 
         let created_image = sqlx::query_as::<_, Image>(
             r#"
-            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_path, created_at
+            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation, created_at, deleted_at, trash_batch_id
             FROM images WHERE id = ?
             "#,
         )
@@ -80,7 +61,7 @@ impl ImageRepository for SqliteImageRepository {
     async fn find_by_id(&self, id: &str) -> Result<Option<Image>, ServiceError> {
         let image = sqlx::query_as::<_, Image>(
             r#"
-            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_path, created_at
+            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation, created_at, deleted_at, trash_batch_id
             FROM images WHERE id = ?
             "#,
         )
@@ -98,8 +79,8 @@ impl ImageRepository for SqliteImageRepository {
         let offset = (page - 1) * limit;
         let images = sqlx::query_as::<_, Image>(
             r#"
-            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_path, created_at
-            FROM images WHERE user_id = ?
+            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation, created_at, deleted_at, trash_batch_id
+            FROM images WHERE user_id = ? AND deleted_at IS NULL
             ORDER BY created_at DESC
             LIMIT ? OFFSET ?
             "#,
@@ -116,18 +97,267 @@ impl ImageRepository for SqliteImageRepository {
         Ok(images)
     }
 
-    async fn delete_image(&self, id: &str, user_id: &str) -> Result<bool, ServiceError> {
-        let result = sqlx::query(
-            "DELETE FROM images WHERE id = ? AND user_id = ?",
+    async fn find_matching(&self, user_id: &str, filter: &ImageFilter, limit: i64) -> Result<Vec<Image>, ServiceError> {
+        let images = sqlx::query_as::<_, Image>(
+            r#"
+            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation, created_at, deleted_at, trash_batch_id
+            FROM images
+            WHERE user_id = ?1
+              AND deleted_at IS NULL
+              AND (?2 IS NULL OR mime_type = ?2)
+              AND (?3 IS NULL OR created_at >= ?3)
+              AND (?4 IS NULL OR created_at < ?4)
+              AND (?5 IS NULL OR instr(lower(original_filename), lower(?5)) > 0)
+            ORDER BY created_at DESC
+            LIMIT ?6
+            "#,
+        )
+        .bind(user_id)
+        .bind(&filter.mime_type)
+        .bind(&filter.created_after)
+        .bind(&filter.created_before)
+        .bind(&filter.filename_contains)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            ServiceError::DatabaseError(format!("Failed to find matching images: {}", e))
+        })?;
+
+        Ok(images)
+    }
+
+    async fn update_content_hash(&self, id: &str, content_hash: &str) -> Result<(), ServiceError> {
+        sqlx::query("UPDATE images SET content_hash = ? WHERE id = ?")
+            .bind(content_hash)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                ServiceError::DatabaseError(format!("Failed to update content hash: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    async fn find_children(&self, parent_id: &str) -> Result<Vec<Image>, ServiceError> {
+        let images = sqlx::query_as::<_, Image>(
+            r#"
+            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation, created_at, deleted_at, trash_batch_id
+            FROM images WHERE parent_id = ?
+            ORDER BY created_at, rowid
+            "#,
+        )
+        .bind(parent_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            ServiceError::DatabaseError(format!("Failed to find derived images: {}", e))
+        })?;
+
+        Ok(images)
+    }
+
+    async fn clear_parent(&self, parent_id: &str) -> Result<u64, ServiceError> {
+        let result = sqlx::query("UPDATE images SET parent_id = NULL WHERE parent_id = ?")
+            .bind(parent_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                ServiceError::DatabaseError(format!("Failed to detach derived images: {}", e))
+            })?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_image(&self, id: &str, user_id: &str) -> Result<Option<Vec<String>>, ServiceError> {
+        let db_error = |e: sqlx::Error| ServiceError::DatabaseError(format!("Failed to delete image: {}", e));
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let storage_key = sqlx::query_scalar::<_, String>("SELECT storage_key FROM images WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        let Some(storage_key) = storage_key else {
+            return Ok(None);
+        };
+
+        // Cached derivatives of the image, or using it as a watermark overlay, and the results of
+        // jobs run on it
+        let mut keys = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT storage_key FROM derivative_cache
+            WHERE image_id = ?1 OR instr(dependencies, ',' || ?1 || ',') > 0
+            UNION
+            SELECT result_key FROM jobs WHERE image_id = ?1 AND result_key IS NOT NULL
+            "#,
         )
         .bind(id)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        keys.push(storage_key);
+
+        for key in &keys {
+            sqlx::query("INSERT OR IGNORE INTO storage_deletions (storage_key) VALUES (?)")
+                .bind(key)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+        }
+        sqlx::query("DELETE FROM derivative_cache WHERE image_id = ?1 OR instr(dependencies, ',' || ?1 || ',') > 0")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        sqlx::query(
+            r#"
+            UPDATE jobs SET result_key = NULL, result_format = NULL, updated_at = CURRENT_TIMESTAMP
+            WHERE image_id = ? AND result_key IS NOT NULL
+            "#,
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        sqlx::query("DELETE FROM images WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+        Ok(Some(keys))
+    }
+
+    async fn trash_images(&self, ids: &[String], user_id: &str, deleted_at: &str, batch_id: &str) -> Result<Vec<String>, ServiceError> {
+        let db_error = |e: sqlx::Error| ServiceError::DatabaseError(format!("Failed to move images to trash: {}", e));
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        let mut trashed = Vec::with_capacity(ids.len());
+        for (index, id) in ids.iter().enumerate() {
+            let result = sqlx::query(
+                "UPDATE images SET deleted_at = ?, trash_batch_id = ? WHERE id = ? AND user_id = ? AND deleted_at IS NULL",
+            )
+            .bind(deleted_at)
+            .bind(batch_id)
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+            if result.rows_affected() > 0 {
+                trashed.push(id.clone());
+            } else if index == 0 {
+                // Dropping the transaction rolls it back
+                return Ok(Vec::new());
+            }
+        }
+
+        tx.commit().await.map_err(db_error)?;
+        Ok(trashed)
+    }
+
+    async fn restore_images(&self, ids: &[String], user_id: &str) -> Result<bool, ServiceError> {
+        let db_error = |e: sqlx::Error| ServiceError::DatabaseError(format!("Failed to restore images: {}", e));
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        for (index, id) in ids.iter().enumerate() {
+            let result = sqlx::query(
+                "UPDATE images SET deleted_at = NULL, trash_batch_id = NULL WHERE id = ? AND user_id = ? AND deleted_at IS NOT NULL",
+            )
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+
+            if index == 0 && result.rows_affected() == 0 {
+                return Ok(false);
+            }
+        }
+
+        tx.commit().await.map_err(db_error)?;
+        Ok(true)
+    }
+
+    async fn find_trashed(&self, user_id: &str, page: i64, limit: i64) -> Result<Vec<Image>, ServiceError> {
+        let offset = (page - 1) * limit;
+        let images = sqlx::query_as::<_, Image>(
+            r#"
+            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation, created_at, deleted_at, trash_batch_id
+            FROM images WHERE user_id = ? AND deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC, rowid DESC
+            LIMIT ? OFFSET ?
+            "#,
+        )
         .bind(user_id)
-        .execute(&self.pool)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            ServiceError::DatabaseError(format!("Failed to find trashed images: {}", e))
+        })?;
+
+        Ok(images)
+    }
+
+    async fn find_expired_trash(&self, cutoff: &str, limit: i64) -> Result<Vec<Image>, ServiceError> {
+        let images = sqlx::query_as::<_, Image>(
+            r#"
+            SELECT id, user_id, filename, original_filename, file_size, mime_type, storage_key, width, height, color_type, bit_depth, content_hash, parent_id, transformation, created_at, deleted_at, trash_batch_id
+            FROM images WHERE deleted_at IS NOT NULL AND deleted_at <= ?
+            ORDER BY deleted_at
+            LIMIT ?
+            "#,
+        )
+        .bind(cutoff)
+        .bind(limit)
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| {
-            ServiceError::DatabaseError(format!("Failed to delete image: {}", e))
+            ServiceError::DatabaseError(format!("Failed to find expired trash: {}", e))
         })?;
 
-        Ok(result.rows_affected() > 0)
+        Ok(images)
+    }
+
+    async fn queue_storage_deletion(&self, storage_key: &str) -> Result<(), ServiceError> {
+        sqlx::query("INSERT OR IGNORE INTO storage_deletions (storage_key) VALUES (?)")
+            .bind(storage_key)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                ServiceError::DatabaseError(format!("Failed to queue storage deletion: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    async fn pending_storage_deletions(&self, limit: i64) -> Result<Vec<String>, ServiceError> {
+        sqlx::query_scalar::<_, String>("SELECT storage_key FROM storage_deletions ORDER BY created_at LIMIT ?")
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                ServiceError::DatabaseError(format!("Failed to find pending storage deletions: {}", e))
+            })
+    }
+
+    async fn complete_storage_deletion(&self, storage_key: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM storage_deletions WHERE storage_key = ?")
+            .bind(storage_key)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                ServiceError::DatabaseError(format!("Failed to complete storage deletion: {}", e))
+            })?;
+
+        Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,296 @@
+use sqlx::SqlitePool;
+use crate::domain::job::{Job, JobRepository};
+use crate::core::error::ServiceError;
+
+const JOB_COLUMNS: &str = "id, user_id, image_id, transformation, status, progress, attempts, max_attempts, \
+    error, result_key, result_format, available_at, created_at, updated_at, completed_at";
+
+#[derive(Clone)]
+pub struct SqliteJobRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteJobRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl JobRepository for SqliteJobRepository {
+    async fn create_job(&self, job: &Job) -> Result<Job, ServiceError> {
+        sqlx::query(
+            r#"
+            INSERT INTO jobs (id, user_id, image_id, transformation, status, progress, attempts, max_attempts, available_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&job.id)
+        .bind(&job.user_id)
+        .bind(&job.image_id)
+        .bind(&job.transformation)
+        .bind(&job.status)
+        .bind(job.progress)
+        .bind(job.attempts)
+        .bind(job.max_attempts)
+        .bind(job.available_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to create job: {}", e)))?;
+
+        self.find_by_id(&job.id)
+            .await?
+            .ok_or_else(|| ServiceError::DatabaseError("Failed to fetch created job".to_string()))
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Job>, ServiceError> {
+        sqlx::query_as::<_, Job>(&format!("SELECT {} FROM jobs WHERE id = ?", JOB_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to find job by id: {}", e)))
+    }
+
+    async fn claim_next(&self, now: i64) -> Result<Option<Job>, ServiceError> {
+        sqlx::query_as::<_, Job>(&format!(
+            r#"
+            UPDATE jobs
+            SET status = 'running', attempts = attempts + 1, progress = 0, updated_at = CURRENT_TIMESTAMP
+            WHERE id = (
+                SELECT id FROM jobs
+                WHERE status = 'queued' AND available_at <= ? AND attempts < max_attempts
+                ORDER BY available_at, created_at
+                LIMIT 1
+            )
+            RETURNING {}
+            "#,
+            JOB_COLUMNS
+        ))
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to claim job: {}", e)))
+    }
+
+    async fn update_progress(&self, id: &str, progress: i64) -> Result<(), ServiceError> {
+        sqlx::query("UPDATE jobs SET progress = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(progress)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to update job progress: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn mark_succeeded(&self, id: &str, result_key: &str, result_format: &str) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = 'succeeded', progress = 100, error = NULL, result_key = ?, result_format = ?,
+                updated_at = CURRENT_TIMESTAMP, completed_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(result_key)
+        .bind(result_format)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to complete job: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: &str, error: &str) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = 'failed', error = ?, updated_at = CURRENT_TIMESTAMP, completed_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(error)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to fail job: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn retry_later(&self, id: &str, error: &str, available_at: i64) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = 'queued', progress = 0, error = ?, available_at = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(error)
+        .bind(available_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to requeue job: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn fail_exhausted_running(&self, error: &str) -> Result<Vec<String>, ServiceError> {
+        sqlx::query_scalar::<_, String>(
+            r#"
+            UPDATE jobs
+            SET status = 'failed', error = ?, updated_at = CURRENT_TIMESTAMP, completed_at = CURRENT_TIMESTAMP
+            WHERE status = 'running' AND attempts >= max_attempts
+            RETURNING id
+            "#,
+        )
+        .bind(error)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to fail exhausted jobs: {}", e)))
+    }
+
+    async fn requeue_running(&self) -> Result<u64, ServiceError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs SET status = 'queued', progress = 0, updated_at = CURRENT_TIMESTAMP
+            WHERE status = 'running' AND attempts < max_attempts
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to requeue running jobs: {}", e)))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn expire_results(&self, cutoff: &str) -> Result<u64, ServiceError> {
+        let db_error = |e: sqlx::Error| ServiceError::DatabaseError(format!("Failed to expire job results: {}", e));
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO storage_deletions (storage_key)
+            SELECT result_key FROM jobs WHERE result_key IS NOT NULL AND completed_at <= ?
+            "#,
+        )
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+        let result = sqlx::query(
+            r#"
+            UPDATE jobs SET result_key = NULL, result_format = NULL, updated_at = CURRENT_TIMESTAMP
+            WHERE result_key IS NOT NULL AND completed_at <= ?
+            "#,
+        )
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?;
+
+        tx.commit().await.map_err(db_error)?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use crate::infrastructure::database::migrations;
+
+    async fn repository() -> SqliteJobRepository {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        migrations::run_migrations(&pool).await.unwrap();
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ('u1', 'alice', 'x')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        SqliteJobRepository::new(pool)
+    }
+
+    async fn create(repository: &SqliteJobRepository, id: &str, max_attempts: i64) -> Job {
+        repository
+            .create_job(&Job {
+                id: id.to_string(),
+                user_id: "u1".to_string(),
+                image_id: "img".to_string(),
+                transformation: "{}".to_string(),
+                status: "queued".to_string(),
+                progress: 0,
+                attempts: 0,
+                max_attempts,
+                error: None,
+                result_key: None,
+                result_format: None,
+                available_at: 0,
+                created_at: None,
+                updated_at: None,
+                completed_at: None,
+            })
+            .await
+            .unwrap()
+    }
+
+    async fn status(repository: &SqliteJobRepository, id: &str) -> String {
+        repository.find_by_id(id).await.unwrap().unwrap().status
+    }
+
+    #[tokio::test]
+    async fn exhausted_jobs_are_not_claimed() {
+        let repository = repository().await;
+        create(&repository, "job", 1).await;
+
+        let claimed = repository.claim_next(1).await.unwrap().unwrap();
+        assert_eq!(claimed.attempts, 1);
+
+        // Put back in the queue without going through the retry check
+        repository.retry_later("job", "crashed", 0).await.unwrap();
+        assert!(repository.claim_next(1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn interrupted_jobs_on_their_last_attempt_fail() {
+        let repository = repository().await;
+        create(&repository, "last", 1).await;
+        create(&repository, "spare", 2).await;
+        repository.claim_next(1).await.unwrap().unwrap();
+        repository.claim_next(1).await.unwrap().unwrap();
+
+        let failed = repository.fail_exhausted_running("interrupted").await.unwrap();
+        assert_eq!(failed, vec!["last".to_string()]);
+        assert_eq!(repository.requeue_running().await.unwrap(), 1);
+
+        assert_eq!(status(&repository, "last").await, "failed");
+        assert_eq!(status(&repository, "spare").await, "queued");
+        assert_eq!(repository.claim_next(1).await.unwrap().unwrap().id, "spare");
+    }
+
+    #[tokio::test]
+    async fn expired_results_are_queued_for_deletion() {
+        let repository = repository().await;
+        for id in ["old", "new"] {
+            create(&repository, id, 1).await;
+            repository.claim_next(1).await.unwrap().unwrap();
+            repository.mark_succeeded(id, &format!("jobs/{}.png", id), "png").await.unwrap();
+        }
+        sqlx::query("UPDATE jobs SET completed_at = '2020-01-01 00:00:00' WHERE id = 'old'")
+            .execute(&repository.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(repository.expire_results("2021-01-01 00:00:00").await.unwrap(), 1);
+
+        let old = repository.find_by_id("old").await.unwrap().unwrap();
+        assert_eq!((old.status.as_str(), old.result_key), ("succeeded", None));
+        assert!(repository.find_by_id("new").await.unwrap().unwrap().result_key.is_some());
+        let queued: Vec<String> = sqlx::query_scalar("SELECT storage_key FROM storage_deletions")
+            .fetch_all(&repository.pool)
+            .await
+            .unwrap();
+        assert_eq!(queued, vec!["jobs/old.png".to_string()]);
+    }
+}
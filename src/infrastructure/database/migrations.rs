@@ -0,0 +1,167 @@
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::SqlitePool;
+use crate::core::error::ServiceError;
+
+// Migrations from ./migrations, embedded at compile time; applied versions are tracked in _sqlx_migrations
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+pub async fn run_migrations(pool: &SqlitePool) -> Result<(), ServiceError> {
+    MIGRATOR.run(pool).await.map_err(|e| {
+        ServiceError::DatabaseError(format!("Failed to run migrations: {}", e))
+    })
+}
+
+pub async fn migration_status(pool: &SqlitePool) -> Result<Vec<MigrationStatus>, ServiceError> {
+    let mut conn = pool.acquire().await?;
+
+    conn.ensure_migrations_table().await.map_err(|e| {
+        ServiceError::DatabaseError(format!("Failed to create migrations table: {}", e))
+    })?;
+
+    let applied = conn.list_applied_migrations().await.map_err(|e| {
+        ServiceError::DatabaseError(format!("Failed to list applied migrations: {}", e))
+    })?;
+
+    let status = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.iter().any(|a| a.version == migration.version),
+        })
+        .collect();
+
+    Ok(status)
+}
+
+pub async fn pending_migrations(pool: &SqlitePool) -> Result<Vec<MigrationStatus>, ServiceError> {
+    let status = migration_status(pool).await?;
+    Ok(status.into_iter().filter(|m| !m.applied).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // Every connection to sqlite::memory: is its own database, so the pool keeps exactly one
+    async fn fresh_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn columns(pool: &SqlitePool, table: &str) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM pragma_table_info(?) ORDER BY cid")
+            .bind(table)
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn migrations_apply_to_a_fresh_database() {
+        let pool = fresh_pool().await;
+        run_migrations(&pool).await.unwrap();
+
+        let applied: Vec<(i64, bool)> =
+            sqlx::query_as("SELECT version, success FROM _sqlx_migrations ORDER BY version")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let expected: Vec<(i64, bool)> = MIGRATOR.iter().map(|m| (m.version, true)).collect();
+        assert_eq!(applied, expected);
+        assert!(pending_migrations(&pool).await.unwrap().is_empty());
+
+        assert_eq!(
+            columns(&pool, "images").await,
+            [
+                "id", "user_id", "filename", "original_filename", "storage_key", "file_size",
+                "mime_type", "width", "height", "created_at", "color_type", "bit_depth",
+                "content_hash", "parent_id", "transformation", "deleted_at", "trash_batch_id",
+            ]
+        );
+        assert_eq!(columns(&pool, "users").await, ["id", "username", "password_hash", "created_at"]);
+        for table in ["derivative_cache", "jobs", "webhooks", "webhook_deliveries", "storage_deletions"] {
+            assert!(!columns(&pool, table).await.is_empty(), "missing table {}", table);
+        }
+    }
+
+    #[tokio::test]
+    async fn storage_keys_are_backfilled_from_stored_paths() {
+        let pool = fresh_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        conn.ensure_migrations_table().await.unwrap();
+        for migration in MIGRATOR.iter().filter(|m| m.version <= 2) {
+            conn.apply(migration).await.unwrap();
+        }
+        drop(conn);
+
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ('u', 'user', 'x')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO images (id, user_id, filename, original_filename, storage_path, file_size, mime_type)
+             VALUES ('i', 'u', 'cat.png', 'cat.png', './uploads/i_cat.png', 1, 'image/png')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        run_migrations(&pool).await.unwrap();
+
+        let (filename, storage_key): (String, String) =
+            sqlx::query_as("SELECT filename, storage_key FROM images WHERE id = 'i'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(storage_key, "i_cat.png");
+        assert_eq!(filename, "i_cat.png");
+    }
+
+    #[tokio::test]
+    async fn trashed_images_are_batched_by_deletion_time() {
+        let pool = fresh_pool().await;
+        let mut conn = pool.acquire().await.unwrap();
+        conn.ensure_migrations_table().await.unwrap();
+        for migration in MIGRATOR.iter().filter(|m| m.version <= 11) {
+            conn.apply(migration).await.unwrap();
+        }
+        drop(conn);
+
+        sqlx::query("INSERT INTO users (id, username, password_hash) VALUES ('u', 'user', 'x')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO images (id, user_id, filename, original_filename, storage_key, file_size, mime_type, width, height, deleted_at)
+             VALUES ('trashed', 'u', 'a.png', 'a.png', 'a.png', 1, 'image/png', 1, 1, '2024-01-01 00:00:00'),
+                    ('live', 'u', 'b.png', 'b.png', 'b.png', 1, 'image/png', 1, 1, NULL)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        run_migrations(&pool).await.unwrap();
+
+        let batches: Vec<(String, Option<String>)> = sqlx::query_as("SELECT id, trash_batch_id FROM images ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            batches,
+            vec![("live".to_string(), None), ("trashed".to_string(), Some("2024-01-01 00:00:00".to_string()))]
+        );
+    }
+}
@@ -1,5 +1,12 @@
 pub mod sqlite;
 pub mod image_repository;
+pub mod derivative_cache_repository;
+pub mod job_repository;
+pub mod webhook_repository;
+pub mod migrations;
 
 pub use sqlite::SqliteUserRepository;
-pub use image_repository::SqliteImageRepository;
\ No newline at end of file
+pub use image_repository::SqliteImageRepository;
+pub use derivative_cache_repository::SqliteDerivativeCacheRepository;
+pub use job_repository::SqliteJobRepository;
+pub use webhook_repository::SqliteWebhookRepository;
\ No newline at end of file
@@ -13,27 +13,6 @@ impl SqliteUserRepository {
     pub fn new(pool: SqlitePool) -> Self {
         Self { pool }
     }
-
-    pub async fn create_table(&self) -> Result<(), ServiceError> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS users (
-                id TEXT PRIMARY KEY NOT NULL,
-                username TEXT UNIQUE NOT NULL,
-                password_hash TEXT NOT NULL,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )
-            "#
-        )
-        .execute(&self.pool)
-        .await
-        .map_err(|e| {
-            ServiceError::DatabaseError(format!("Failed to create users table: {}", e))
-        })?;
-
-        println!("✅ Users table created or already exists");
-        Ok(())
-    }
 }
 
 #[async_trait::async_trait]
@@ -0,0 +1,203 @@
+use sqlx::SqlitePool;
+use crate::domain::webhook::{Webhook, WebhookDelivery, WebhookRepository, ALL_EVENTS};
+use crate::core::error::ServiceError;
+
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event_id, event, payload, status, attempts, max_attempts, \
+    response_status, last_error, next_attempt_at, created_at, updated_at, delivered_at";
+
+#[derive(Clone)]
+pub struct SqliteWebhookRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteWebhookRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebhookRepository for SqliteWebhookRepository {
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, ServiceError> {
+        sqlx::query("INSERT INTO webhooks (id, user_id, url, secret, events) VALUES (?, ?, ?, ?, ?)")
+            .bind(&webhook.id)
+            .bind(&webhook.user_id)
+            .bind(&webhook.url)
+            .bind(&webhook.secret)
+            .bind(&webhook.events)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to create webhook: {}", e)))?;
+
+        self.find_by_id(&webhook.id)
+            .await?
+            .ok_or_else(|| ServiceError::DatabaseError("Failed to fetch created webhook".to_string()))
+    }
+
+    async fn find_by_id(&self, id: &str) -> Result<Option<Webhook>, ServiceError> {
+        sqlx::query_as::<_, Webhook>("SELECT id, user_id, url, secret, events, created_at FROM webhooks WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to find webhook by id: {}", e)))
+    }
+
+    async fn find_by_user_id(&self, user_id: &str) -> Result<Vec<Webhook>, ServiceError> {
+        sqlx::query_as::<_, Webhook>(
+            "SELECT id, user_id, url, secret, events, created_at FROM webhooks WHERE user_id = ? ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to find webhooks by user: {}", e)))
+    }
+
+    async fn delete_webhook(&self, id: &str, user_id: &str) -> Result<bool, ServiceError> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = ? AND user_id = ?")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| ServiceError::DatabaseError(format!("Failed to delete webhook: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn find_subscribed(&self, user_id: &str, event: &str) -> Result<Vec<Webhook>, ServiceError> {
+        sqlx::query_as::<_, Webhook>(
+            r#"
+            SELECT id, user_id, url, secret, events, created_at FROM webhooks
+            WHERE user_id = ? AND (events = ? OR ',' || events || ',' LIKE '%,' || ? || ',%')
+            "#,
+        )
+        .bind(user_id)
+        .bind(ALL_EVENTS)
+        .bind(event)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to find subscribed webhooks: {}", e)))
+    }
+
+    async fn create_delivery(&self, delivery: &WebhookDelivery) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (id, webhook_id, event_id, event, payload, status, attempts, max_attempts, next_attempt_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.webhook_id)
+        .bind(&delivery.event_id)
+        .bind(&delivery.event)
+        .bind(&delivery.payload)
+        .bind(&delivery.status)
+        .bind(delivery.attempts)
+        .bind(delivery.max_attempts)
+        .bind(delivery.next_attempt_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to create webhook delivery: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(&self, now: i64, limit: i64) -> Result<Vec<WebhookDelivery>, ServiceError> {
+        sqlx::query_as::<_, WebhookDelivery>(&format!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'delivering', attempts = attempts + 1, updated_at = CURRENT_TIMESTAMP
+            WHERE id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'pending' AND next_attempt_at <= ?
+                ORDER BY next_attempt_at
+                LIMIT ?
+            )
+            RETURNING {}
+            "#,
+            DELIVERY_COLUMNS
+        ))
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to claim webhook deliveries: {}", e)))
+    }
+
+    async fn mark_delivered(&self, id: &str, response_status: i64) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'succeeded', response_status = ?, last_error = NULL,
+                updated_at = CURRENT_TIMESTAMP, delivered_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(response_status)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to record webhook delivery: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn schedule_retry(&self, id: &str, response_status: Option<i64>, error: &str, next_attempt_at: i64) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'pending', response_status = ?, last_error = ?, next_attempt_at = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(response_status)
+        .bind(error)
+        .bind(next_attempt_at)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to reschedule webhook delivery: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(&self, id: &str, response_status: Option<i64>, error: &str) -> Result<(), ServiceError> {
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'failed', response_status = ?, last_error = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(response_status)
+        .bind(error)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to record webhook failure: {}", e)))?;
+
+        Ok(())
+    }
+
+    async fn requeue_delivering(&self) -> Result<u64, ServiceError> {
+        let result = sqlx::query(
+            "UPDATE webhook_deliveries SET status = 'pending', updated_at = CURRENT_TIMESTAMP WHERE status = 'delivering'",
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to requeue webhook deliveries: {}", e)))?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn find_deliveries(&self, webhook_id: &str, limit: i64) -> Result<Vec<WebhookDelivery>, ServiceError> {
+        sqlx::query_as::<_, WebhookDelivery>(&format!(
+            "SELECT {} FROM webhook_deliveries WHERE webhook_id = ? ORDER BY created_at DESC, rowid DESC LIMIT ?",
+            DELIVERY_COLUMNS
+        ))
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| ServiceError::DatabaseError(format!("Failed to find webhook deliveries: {}", e)))
+    }
+}
@@ -1,3 +1,4 @@
 pub mod database;
+pub mod storage;
 
-pub use database::{SqliteUserRepository, SqliteImageRepository};
\ No newline at end of file
+pub use database::{SqliteUserRepository, SqliteImageRepository, SqliteDerivativeCacheRepository, SqliteJobRepository, SqliteWebhookRepository};
\ No newline at end of file
@@ -1 +1,86 @@
-$null 
+use std::io::{ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use url::Url;
+use crate::core::error::ServiceError;
+use crate::infrastructure::storage::{validate_key, ByteStream, StorageBackend};
+
+#[derive(Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: &str) -> Result<Self, ServiceError> {
+        std::fs::create_dir_all(root).map_err(|e| {
+            ServiceError::StorageError(format!("Failed to create storage directory {}: {}", root, e))
+        })?;
+        Ok(Self { root: PathBuf::from(root) })
+    }
+
+    fn path_for(&self, key: &str) -> Result<PathBuf, ServiceError> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), ServiceError> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&path, data).await
+            .map_err(|e| ServiceError::StorageError(format!("Failed to save {}: {}", key, e)))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, ServiceError> {
+        let path = self.path_for(key)?;
+        fs::read(&path).await
+            .map_err(|e| ServiceError::StorageError(format!("Failed to read {}: {}", key, e)))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ServiceError> {
+        let path = self.path_for(key)?;
+        match fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(ServiceError::StorageError(format!("Failed to delete {}: {}", key, e))),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, ServiceError> {
+        let path = self.path_for(key)?;
+        fs::try_exists(&path).await
+            .map_err(|e| ServiceError::StorageError(format!("Failed to check {}: {}", key, e)))
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream, ServiceError> {
+        let path = self.path_for(key)?;
+        let file = fs::File::open(&path).await
+            .map_err(|e| ServiceError::StorageError(format!("Failed to open {}: {}", key, e)))?;
+        Ok(ReaderStream::new(file).map(|chunk| chunk.map_err(ServiceError::from)).boxed())
+    }
+
+    async fn stream_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream, ServiceError> {
+        let path = self.path_for(key)?;
+        let mut file = fs::File::open(&path).await
+            .map_err(|e| ServiceError::StorageError(format!("Failed to open {}: {}", key, e)))?;
+        file.seek(SeekFrom::Start(range.start)).await?;
+        let reader = file.take(range.end - range.start);
+        Ok(ReaderStream::new(reader).map(|chunk| chunk.map_err(ServiceError::from)).boxed())
+    }
+
+    // Files are only served through the API, which checks ownership
+    async fn presign(&self, key: &str, _expires_in: Duration) -> Result<Option<Url>, ServiceError> {
+        validate_key(key)?;
+        Ok(None)
+    }
+}
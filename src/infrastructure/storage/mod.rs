@@ -0,0 +1,144 @@
+pub mod local;
+pub mod s3;
+
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::Stream;
+use url::Url;
+use crate::core::config::{Settings, StorageKind};
+use crate::core::error::ServiceError;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, ServiceError>> + Send>>;
+
+// Object storage addressed by backend-agnostic keys such as "<uuid>.png"
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), ServiceError>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>, ServiceError>;
+    async fn delete(&self, key: &str) -> Result<(), ServiceError>;
+    #[allow(dead_code)]
+    async fn exists(&self, key: &str) -> Result<bool, ServiceError>;
+    async fn stream(&self, key: &str) -> Result<ByteStream, ServiceError>;
+    // Bytes in `range` only; the range must lie within the object
+    async fn stream_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream, ServiceError>;
+    // A time-limited GET URL for the object, or None when the backend cannot hand out direct URLs
+    #[allow(dead_code)]
+    async fn presign(&self, key: &str, expires_in: Duration) -> Result<Option<Url>, ServiceError>;
+}
+
+pub fn from_settings(settings: &Settings) -> Result<Arc<dyn StorageBackend>, ServiceError> {
+    match settings.storage_backend {
+        StorageKind::Local => Ok(Arc::new(LocalStorage::new(&settings.storage_path)?)),
+        StorageKind::S3 => Ok(Arc::new(S3Storage::new(settings)?)),
+    }
+}
+
+// Keys are generated by the service, but never let one address anything outside the store
+pub fn validate_key(key: &str) -> Result<(), ServiceError> {
+    let invalid = key.is_empty()
+        || key.starts_with('/')
+        || key.contains('\\')
+        || key.contains('\0')
+        || key.split('/').any(|part| part.is_empty() || part == "." || part == "..");
+
+    if invalid {
+        return Err(ServiceError::StorageError(format!("Invalid storage key: {:?}", key)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::TryStreamExt;
+    use object_store::memory::InMemory;
+
+    async fn collect(stream: ByteStream) -> Vec<u8> {
+        let chunks: Vec<Bytes> = stream.try_collect().await.unwrap();
+        chunks.concat()
+    }
+
+    // What the service relies on from every backend
+    async fn check_contract(storage: &dyn StorageBackend) {
+        let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+
+        assert!(!storage.exists("a_photo.png").await.unwrap());
+        storage.put("a_photo.png", &data).await.unwrap();
+        assert!(storage.exists("a_photo.png").await.unwrap());
+        assert_eq!(storage.get("a_photo.png").await.unwrap(), data);
+        assert_eq!(collect(storage.stream("a_photo.png").await.unwrap()).await, data);
+        assert_eq!(
+            collect(storage.stream_range("a_photo.png", 100..4200).await.unwrap()).await,
+            &data[100..4200]
+        );
+
+        // Overwrites replace the whole object
+        storage.put("a_photo.png", b"short").await.unwrap();
+        assert_eq!(storage.get("a_photo.png").await.unwrap(), b"short");
+
+        // Nested keys, as used for derivatives and job results
+        storage.put("jobs/abc.webp", b"result").await.unwrap();
+        assert_eq!(storage.get("jobs/abc.webp").await.unwrap(), b"result");
+
+        // Backends that can sign hand out a URL for the object itself
+        if let Some(url) = storage.presign("jobs/abc.webp", Duration::from_secs(60)).await.unwrap() {
+            assert!(url.path().ends_with("/jobs/abc.webp"), "{}", url);
+        }
+
+        // Deleting is idempotent and the object is gone afterwards
+        storage.delete("a_photo.png").await.unwrap();
+        storage.delete("a_photo.png").await.unwrap();
+        assert!(!storage.exists("a_photo.png").await.unwrap());
+        assert!(storage.get("a_photo.png").await.is_err());
+        assert!(storage.stream("a_photo.png").await.is_err());
+
+        for key in ["", "/etc/passwd", "../escape", "a/../b", "a//b", "a\\b", "nul\0"] {
+            assert!(storage.put(key, b"x").await.is_err(), "accepted key {:?}", key);
+            assert!(storage.get(key).await.is_err(), "accepted key {:?}", key);
+            assert!(storage.exists(key).await.is_err(), "accepted key {:?}", key);
+            assert!(storage.presign(key, Duration::from_secs(60)).await.is_err(), "accepted key {:?}", key);
+        }
+    }
+
+    #[tokio::test]
+    async fn local_storage_meets_contract() {
+        let root = std::env::temp_dir().join(format!("storage-test-{}", uuid::Uuid::new_v4()));
+        let storage = LocalStorage::new(root.to_str().unwrap()).unwrap();
+        check_contract(&storage).await;
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn s3_storage_meets_contract() {
+        let storage = S3Storage::with_store(Arc::new(InMemory::new()));
+        check_contract(&storage).await;
+    }
+
+    #[tokio::test]
+    async fn s3_storage_presigns_without_a_request() {
+        let settings = Settings {
+            s3_bucket: "images".to_string(),
+            s3_region: "us-east-1".to_string(),
+            s3_access_key_id: "access".to_string(),
+            s3_secret_access_key: "secret".to_string(),
+            s3_endpoint: Some("http://localhost:9000".to_string()),
+            s3_path_style: true,
+            ..Settings::default()
+        };
+        let storage = S3Storage::new(&settings).unwrap();
+
+        let url = storage.presign("jobs/abc.webp", Duration::from_secs(300)).await.unwrap().unwrap();
+        assert_eq!(url.host_str(), Some("localhost"));
+        assert_eq!(url.path(), "/images/jobs/abc.webp");
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(query.contains(&("X-Amz-Expires".to_string(), "300".to_string())), "{}", url);
+        assert!(query.iter().any(|(name, _)| name == "X-Amz-Signature"), "{}", url);
+    }
+}
@@ -0,0 +1,121 @@
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use axum::http::Method;
+use futures_util::StreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use object_store::signer::Signer;
+use object_store::{GetOptions, GetRange, ObjectStore, PutPayload};
+use url::Url;
+use crate::core::config::Settings;
+use crate::core::error::ServiceError;
+use crate::infrastructure::storage::{validate_key, ByteStream, StorageBackend};
+
+// Any S3-compatible endpoint (AWS, MinIO, R2, ...)
+#[derive(Clone)]
+pub struct S3Storage {
+    store: Arc<dyn ObjectStore>,
+    // Present for real S3 stores, which can sign URLs
+    signer: Option<Arc<dyn Signer>>,
+}
+
+impl S3Storage {
+    pub fn new(settings: &Settings) -> Result<Self, ServiceError> {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(&settings.s3_bucket)
+            .with_region(&settings.s3_region)
+            .with_access_key_id(&settings.s3_access_key_id)
+            .with_secret_access_key(&settings.s3_secret_access_key)
+            .with_virtual_hosted_style_request(!settings.s3_path_style);
+
+        if let Some(endpoint) = &settings.s3_endpoint {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+
+        let store = builder.build().map_err(storage_error)?;
+        let store = Arc::new(store);
+        Ok(Self { signer: Some(store.clone()), ..Self::with_store(store) })
+    }
+
+    // Any object_store implementation; tests use the in-memory one as an S3 stand-in
+    pub fn with_store(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store, signer: None }
+    }
+
+    fn path_for(key: &str) -> Result<Path, ServiceError> {
+        validate_key(key)?;
+        Path::parse(key).map_err(|e| ServiceError::StorageError(format!("Invalid storage key: {}", e)))
+    }
+}
+
+fn storage_error(err: object_store::Error) -> ServiceError {
+    ServiceError::StorageError(err.to_string())
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), ServiceError> {
+        let path = Self::path_for(key)?;
+        self.store
+            .put(&path, PutPayload::from(data.to_vec()))
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, ServiceError> {
+        let path = Self::path_for(key)?;
+        let result = self.store.get(&path).await.map_err(storage_error)?;
+        let bytes = result.bytes().await.map_err(storage_error)?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ServiceError> {
+        let path = Self::path_for(key)?;
+        match self.store.delete(&path).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, ServiceError> {
+        let path = Self::path_for(key)?;
+        match self.store.head(&path).await {
+            Ok(_) => Ok(true),
+            Err(object_store::Error::NotFound { .. }) => Ok(false),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn stream(&self, key: &str) -> Result<ByteStream, ServiceError> {
+        let path = Self::path_for(key)?;
+        let result = self.store.get(&path).await.map_err(storage_error)?;
+        Ok(result.into_stream().map(|chunk| chunk.map_err(storage_error)).boxed())
+    }
+
+    async fn stream_range(&self, key: &str, range: Range<u64>) -> Result<ByteStream, ServiceError> {
+        let path = Self::path_for(key)?;
+        let options = GetOptions {
+            range: Some(GetRange::Bounded(range)),
+            ..Default::default()
+        };
+        let result = self.store.get_opts(&path, options).await.map_err(storage_error)?;
+        Ok(result.into_stream().map(|chunk| chunk.map_err(storage_error)).boxed())
+    }
+
+    async fn presign(&self, key: &str, expires_in: Duration) -> Result<Option<Url>, ServiceError> {
+        let path = Self::path_for(key)?;
+        let Some(signer) = &self.signer else {
+            return Ok(None);
+        };
+        let url = signer
+            .signed_url(Method::GET, &path, expires_in)
+            .await
+            .map_err(storage_error)?;
+        Ok(Some(url))
+    }
+}
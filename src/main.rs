@@ -4,45 +4,102 @@ mod infrastructure;
 mod application;
 mod api;
 
-use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
 use sqlx::SqlitePool;
+use sqlx::sqlite::SqliteConnectOptions;
+use crate::core::config::Settings;
 use crate::core::jwt::JwtService;
 use crate::application::{user_service::UserService, image_service::ImageService};
-use crate::infrastructure::{SqliteUserRepository, SqliteImageRepository};
+use crate::application::derivative_cache::DerivativeCache;
+use crate::application::job_service::JobService;
+use crate::application::webhook_service::WebhookService;
+use crate::infrastructure::{SqliteUserRepository, SqliteImageRepository, SqliteDerivativeCacheRepository, SqliteJobRepository, SqliteWebhookRepository};
+use crate::infrastructure::database::migrations;
+use crate::infrastructure::storage;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🚀 Starting Image Processing Service...");
 
+    // Load configuration
+    let settings = Settings::load().unwrap_or_else(|e| {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    });
+    println!("⚙️  Loaded configuration ({:?})", settings.environment);
+
     // Connect to database
-    let database_url = "sqlite::memory:"; // استفاده از memory برای تست
-    let pool = SqlitePool::connect(database_url).await?;
+    let connect_options = SqliteConnectOptions::from_str(&settings.database_url)?
+        .create_if_missing(true);
+    let pool = SqlitePool::connect_with(connect_options).await?;
     println!("✅ Connected to database successfully");
 
+    // `migrate [status|run]` manages the schema without starting the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        return migrate_command(&pool, args.get(1).map(String::as_str)).await;
+    }
+
+    // Apply pending migrations
+    let pending = migrations::pending_migrations(&pool).await?;
+    migrations::run_migrations(&pool).await?;
+    println!("📋 Database schema up to date ({} migrations applied)", pending.len());
+
     // Create repositories
     let user_repository = SqliteUserRepository::new(pool.clone());
     let image_repository = SqliteImageRepository::new(pool.clone());
+    let derivative_cache_repository = SqliteDerivativeCacheRepository::new(pool.clone());
+    let job_repository = SqliteJobRepository::new(pool.clone());
+    let webhook_repository = SqliteWebhookRepository::new(pool.clone());
 
-    // Create tables
-    user_repository.create_table().await?;
-    image_repository.create_table().await?;
-    println!("📋 Database tables created");
-
-    // Create upload directory
-    let storage_path = "./uploads".to_string();
-    tokio::fs::create_dir_all(&storage_path).await?;
-    println!("📁 Created uploads directory");
+    // Create storage backend
+    let storage = storage::from_settings(&settings)?;
+    println!("📁 Using {:?} storage backend", settings.storage_backend);
 
     // Create services
     let user_service = UserService::new(user_repository);
-    let image_service = ImageService::new(image_repository, storage_path);
-    let jwt_service = JwtService::new("your-super-secret-jwt-key".to_string());
+    let derivative_cache = DerivativeCache::new(
+        Arc::new(derivative_cache_repository),
+        storage.clone(),
+        settings.derivative_cache_max_bytes,
+    );
+    let webhook_service = WebhookService::new(Arc::new(webhook_repository), &settings)?;
+    let image_service = ImageService::new(
+        image_repository,
+        storage.clone(),
+        derivative_cache,
+        webhook_service.clone(),
+        &settings,
+    );
+    let job_service = JobService::new(
+        image_service.clone(),
+        Arc::new(job_repository),
+        storage,
+        webhook_service.clone(),
+        &settings,
+    );
+    let jwt_service = JwtService::new(&settings);
+
+    // Start background job workers
+    job_service.start_workers().await?;
+    println!("🛠️  Started {} job workers", settings.job_workers);
+    webhook_service.start_dispatcher().await?;
+    // Also finishes file deletions interrupted by a previous shutdown, in the background
+    image_service.start_trash_purger();
 
     // Create router
-    let app = api::routes::create_router(user_service, image_service, jwt_service);
+    let app = api::routes::create_router(
+        &settings,
+        user_service,
+        image_service,
+        job_service,
+        webhook_service,
+        jwt_service,
+    );
 
     // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+    let addr = settings.server_addr();
     println!("🌐 Server listening on {}", addr);
     
     axum::serve(
@@ -51,4 +108,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+async fn migrate_command(pool: &SqlitePool, action: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    match action.unwrap_or("status") {
+        "status" => {
+            for migration in migrations::migration_status(pool).await? {
+                let marker = if migration.applied { "applied" } else { "pending" };
+                println!("  {:>4}  {:<8} {}", migration.version, marker, migration.description);
+            }
+        }
+        "run" => {
+            let pending = migrations::pending_migrations(pool).await?;
+            migrations::run_migrations(pool).await?;
+            for migration in &pending {
+                println!("✅ Applied {} {}", migration.version, migration.description);
+            }
+            println!("📋 {} migrations applied", pending.len());
+        }
+        other => {
+            return Err(format!("Unknown migrate action '{}', expected 'status' or 'run'", other).into());
+        }
+    }
+
+    Ok(())
+}
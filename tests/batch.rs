@@ -0,0 +1,28 @@
+mod common;
+
+use common::{png, read_json, spawn_app, JsonBody};
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn batch_limit_counts_ids_before_deduplication() {
+    let app = spawn_app(|settings| settings.batch_max_items = 3).await;
+    let id = app.upload(&png(8, 8)).await;
+    let batch = |image_ids: Vec<&str>| {
+        app.client
+            .post(app.url("/api/images/batch/transform"))
+            .bearer_auth(&app.token)
+            .json_body(serde_json::json!({
+                "image_ids": image_ids,
+                "transformations": { "resize": { "width": 4 }, "format": "png" },
+            }))
+            .send()
+    };
+
+    let response = batch(vec![&id, &id, &id]).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let manifest = read_json(response).await;
+    assert_eq!(manifest["items"].as_array().unwrap().len(), 1, "repeated ids are rendered once");
+
+    let response = batch(vec![&id, &id, &id, &id]).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,185 @@
+// Runs the full service on an ephemeral port, with its database and storage in a temp directory
+#![allow(dead_code)]
+
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use image_processing_service::api::routes::create_router;
+use image_processing_service::application::derivative_cache::DerivativeCache;
+use image_processing_service::application::image_service::ImageService;
+use image_processing_service::application::job_service::JobService;
+use image_processing_service::application::user_service::UserService;
+use image_processing_service::application::webhook_service::WebhookService;
+use image_processing_service::core::config::Settings;
+use image_processing_service::core::jwt::JwtService;
+use image_processing_service::infrastructure::database::migrations;
+use image_processing_service::infrastructure::storage;
+use image_processing_service::infrastructure::{
+    SqliteDerivativeCacheRepository, SqliteImageRepository, SqliteJobRepository, SqliteUserRepository,
+    SqliteWebhookRepository,
+};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::SqlitePool;
+
+pub struct TestApp {
+    pub base_url: String,
+    pub client: reqwest::Client,
+    pub token: String,
+    pub pool: SqlitePool,
+    pub dir: PathBuf,
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub async fn spawn_app(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    let dir = std::env::temp_dir().join(format!("image-service-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut settings = Settings {
+        database_url: format!("sqlite:{}", dir.join("test.db").display()),
+        storage_path: dir.join("uploads").to_string_lossy().into_owned(),
+        ..Settings::default()
+    };
+    configure(&mut settings);
+
+    let options = SqliteConnectOptions::from_str(&settings.database_url).unwrap().create_if_missing(true);
+    let pool = SqlitePool::connect_with(options).await.unwrap();
+    migrations::run_migrations(&pool).await.unwrap();
+
+    let storage = storage::from_settings(&settings).unwrap();
+    let webhook_service = WebhookService::new(Arc::new(SqliteWebhookRepository::new(pool.clone())), &settings).unwrap();
+    let derivative_cache = DerivativeCache::new(
+        Arc::new(SqliteDerivativeCacheRepository::new(pool.clone())),
+        storage.clone(),
+        settings.derivative_cache_max_bytes,
+    );
+    let image_service = ImageService::new(
+        SqliteImageRepository::new(pool.clone()),
+        storage.clone(),
+        derivative_cache,
+        webhook_service.clone(),
+        &settings,
+    );
+    let job_service = JobService::new(
+        image_service.clone(),
+        Arc::new(SqliteJobRepository::new(pool.clone())),
+        storage,
+        webhook_service.clone(),
+        &settings,
+    );
+    job_service.start_workers().await.unwrap();
+    webhook_service.start_dispatcher().await.unwrap();
+
+    let app = create_router(
+        &settings,
+        UserService::new(SqliteUserRepository::new(pool.clone())),
+        image_service,
+        job_service,
+        webhook_service,
+        JwtService::new(&settings),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = reqwest::Client::new();
+    let token = register(&client, &base_url, "alice").await;
+    TestApp { base_url, client, token, pool, dir }
+}
+
+async fn register(client: &reqwest::Client, base_url: &str, username: &str) -> String {
+    let response = client
+        .post(format!("{}/auth/register", base_url))
+        .header("content-type", "application/json")
+        .body(serde_json::json!({ "username": username, "password": "correct horse battery" }).to_string())
+        .send()
+        .await
+        .unwrap();
+    read_json(response).await["token"].as_str().unwrap().to_string()
+}
+
+impl TestApp {
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub async fn register(&self, username: &str) -> String {
+        register(&self.client, &self.base_url, username).await
+    }
+
+    // Uploads a PNG and returns the new image's id
+    pub async fn upload(&self, png: &[u8]) -> String {
+        let response = self.send_upload(png).await;
+        assert!(response.status().is_success(), "upload failed: {}", response.status());
+        read_json(response).await["id"].as_str().unwrap().to_string()
+    }
+
+    pub async fn send_upload(&self, png: &[u8]) -> reqwest::Response {
+        let boundary = "test-boundary";
+        let mut body = format!(
+            "--{}\r\ncontent-disposition: form-data; name=\"image\"; filename=\"test.png\"\r\ncontent-type: image/png\r\n\r\n",
+            boundary
+        )
+        .into_bytes();
+        body.extend_from_slice(png);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        self.client
+            .post(self.url("/api/images"))
+            .bearer_auth(&self.token)
+            .header("content-type", format!("multipart/form-data; boundary={}", boundary))
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    // Keys of the objects in local storage, derivative cache entries excluded
+    pub fn stored_objects(&self) -> Vec<String> {
+        fn walk(root: &std::path::Path, dir: &std::path::Path, keys: &mut Vec<String>) {
+            for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    walk(root, &path, keys);
+                } else {
+                    keys.push(path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"));
+                }
+            }
+        }
+        let root = self.dir.join("uploads");
+        let mut keys = Vec::new();
+        walk(&root, &root, &mut keys);
+        keys.retain(|key| !key.starts_with("derivatives/"));
+        keys.sort();
+        keys
+    }
+}
+
+pub trait JsonBody {
+    fn json_body(self, value: serde_json::Value) -> Self;
+}
+
+impl JsonBody for reqwest::RequestBuilder {
+    fn json_body(self, value: serde_json::Value) -> Self {
+        self.header("content-type", "application/json").body(value.to_string())
+    }
+}
+
+pub async fn read_json(response: reqwest::Response) -> serde_json::Value {
+    serde_json::from_slice(&response.bytes().await.unwrap()).unwrap()
+}
+
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([(x * 255 / width.max(1)) as u8, (y * 255 / height.max(1)) as u8, 128, 255])
+    }));
+    let mut buffer = Cursor::new(Vec::new());
+    img.write_to(&mut buffer, ImageFormat::Png).unwrap();
+    buffer.into_inner()
+}
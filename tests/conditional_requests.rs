@@ -0,0 +1,64 @@
+mod common;
+
+use common::{png, spawn_app, JsonBody};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn downloads_revalidate_with_etag_and_last_modified() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(16, 16)).await;
+    let url = app.url(&format!("/api/images/{}", id));
+
+    let response = app.client.get(&url).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].clone();
+    let last_modified = response.headers()[LAST_MODIFIED].clone();
+
+    let response = app.client.get(&url).bearer_auth(&app.token).header(IF_NONE_MATCH, etag.clone()).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag);
+    assert!(response.bytes().await.unwrap().is_empty());
+
+    let response = app.client.get(&url).bearer_auth(&app.token).header(IF_MODIFIED_SINCE, last_modified).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = app.client.get(&url).bearer_auth(&app.token).header(IF_NONE_MATCH, "\"other\"").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn renders_revalidate_with_etag() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(16, 16)).await;
+    let url = app.url(&format!("/api/images/{}/render?w=8&fmt=png", id));
+
+    let response = app.client.get(&url).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].clone();
+
+    let response = app.client.get(&url).bearer_auth(&app.token).header(IF_NONE_MATCH, etag.clone()).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag);
+
+    // A different transformation has a different ETag
+    let other = app.url(&format!("/api/images/{}/render?w=4&fmt=png", id));
+    let response = app.client.get(&other).bearer_auth(&app.token).header(IF_NONE_MATCH, etag).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn post_transforms_ignore_conditional_headers() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(16, 16)).await;
+    let url = app.url(&format!("/api/images/{}/transform", id));
+    let body = serde_json::json!({ "resize": { "width": 8 }, "format": "png" });
+
+    let response = app.client.post(&url).bearer_auth(&app.token).json_body(body.clone()).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].clone();
+
+    let response = app.client.post(&url).bearer_auth(&app.token).header(IF_NONE_MATCH, etag).json_body(body).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.bytes().await.unwrap().is_empty());
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{png, spawn_app, JsonBody, TestApp};
+use reqwest::StatusCode;
+
+async fn render(app: &TestApp, path: &str) -> (String, Vec<u8>) {
+    let response = app.client.get(app.url(path)).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cache = response.headers()["x-cache"].to_str().unwrap().to_string();
+    (cache, response.bytes().await.unwrap().to_vec())
+}
+
+async fn cached_entries(app: &TestApp) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM derivative_cache").fetch_one(&app.pool).await.unwrap()
+}
+
+fn cached_files(app: &TestApp) -> usize {
+    let root = app.dir.join("uploads").join("derivatives");
+    std::fs::read_dir(root).into_iter().flatten().flatten().flat_map(|dir| std::fs::read_dir(dir.path()).into_iter().flatten()).count()
+}
+
+async fn purge(app: &TestApp, id: &str) {
+    app.client.delete(app.url(&format!("/api/images/{}", id))).bearer_auth(&app.token).send().await.unwrap();
+    app.client.delete(app.url("/api/trash")).bearer_auth(&app.token).send().await.unwrap();
+}
+
+#[tokio::test]
+async fn repeated_renders_are_served_from_the_cache() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(32, 32)).await;
+    let path = format!("/api/images/{}/render?w=16&fmt=png", id);
+
+    let (first, rendered) = render(&app, &path).await;
+    assert_eq!(first, "MISS");
+    let (second, cached) = render(&app, &path).await;
+    assert_eq!(second, "HIT");
+    assert_eq!(cached, rendered);
+
+    // The equivalent JSON transform shares the entry
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/transform", id)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "resize": { "width": 16 }, "format": "png" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-cache"], "HIT");
+    assert_eq!(cached_entries(&app).await, 1);
+}
+
+#[tokio::test]
+async fn entries_go_when_their_source_is_purged() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(32, 32)).await;
+    render(&app, &format!("/api/images/{}/render?w=16&fmt=png", id)).await;
+    render(&app, &format!("/api/images/{}/render?w=8&fmt=png", id)).await;
+    assert_eq!(cached_entries(&app).await, 2);
+    assert_eq!(cached_files(&app), 2);
+
+    // Moving the image to the trash keeps its entries for a restore
+    app.client.delete(app.url(&format!("/api/images/{}", id))).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(cached_entries(&app).await, 2);
+
+    app.client.delete(app.url("/api/trash")).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(cached_entries(&app).await, 0);
+    assert_eq!(cached_files(&app), 0);
+}
+
+#[tokio::test]
+async fn entries_go_when_their_watermark_is_purged() {
+    let app = spawn_app(|_| {}).await;
+    let base = app.upload(&png(32, 32)).await;
+    let overlay = app.upload(&png(8, 8)).await;
+
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/transform", base)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "watermark": { "image": { "image_id": overlay } }, "format": "png" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-cache"], "MISS");
+    assert_eq!(cached_entries(&app).await, 1);
+
+    purge(&app, &overlay).await;
+    assert_eq!(cached_entries(&app).await, 0);
+    assert_eq!(cached_files(&app), 0);
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::spawn_app;
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn metrics_require_the_scrape_token() {
+    let app = spawn_app(|settings| settings.metrics_token = "scrape-secret".to_string()).await;
+    let url = app.url("/metrics");
+
+    let response = app.client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.client.get(&url).bearer_auth("wrong-secret").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // A user's JWT is not a scrape token
+    let response = app.client.get(&url).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.client.get(&url).bearer_auth("scrape-secret").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains("# TYPE"));
+}
+
+#[tokio::test]
+async fn metrics_are_not_served_without_a_token() {
+    let app = spawn_app(|_| {}).await;
+
+    let response = app.client.get(app.url("/metrics")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
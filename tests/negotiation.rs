@@ -0,0 +1,60 @@
+mod common;
+
+use std::io::Cursor;
+use common::{png, spawn_app, TestApp};
+use image::{DynamicImage, ImageFormat, RgbImage};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, VARY};
+use reqwest::StatusCode;
+
+fn opaque_png(width: u32, height: u32) -> Vec<u8> {
+    let mut buffer = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(RgbImage::new(width, height)).write_to(&mut buffer, ImageFormat::Png).unwrap();
+    buffer.into_inner()
+}
+
+async fn render(app: &TestApp, id: &str, query: &str, accept: &str) -> reqwest::Response {
+    let response = app
+        .client
+        .get(app.url(&format!("/api/images/{}/render?{}", id, query)))
+        .bearer_auth(&app.token)
+        .header(ACCEPT, accept)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+}
+
+#[tokio::test]
+async fn auto_format_follows_the_accept_header() {
+    let app = spawn_app(|_| {}).await;
+    let opaque = app.upload(&opaque_png(16, 16)).await;
+    let transparent = app.upload(&png(16, 16)).await;
+
+    for (id, accept, expected) in [
+        (&opaque, "image/webp,image/*;q=0.8", "image/webp"),
+        (&opaque, "image/webp;q=0,*/*", "image/jpeg"),
+        (&opaque, "*/*", "image/jpeg"),
+        (&transparent, "image/jpeg,*/*", "image/png"),
+        (&transparent, "image/webp,*/*", "image/webp"),
+    ] {
+        // The second request is a cache hit and must carry the same headers
+        for _ in 0..2 {
+            let response = render(&app, id, "w=8&fmt=auto", accept).await;
+            assert_eq!(response.headers()[CONTENT_TYPE], expected, "{}", accept);
+            assert_eq!(response.headers()[VARY], "accept", "{}", accept);
+            let data = response.bytes().await.unwrap();
+            assert_eq!(image::guess_format(&data).unwrap().to_mime_type(), expected);
+        }
+    }
+}
+
+#[tokio::test]
+async fn explicit_formats_do_not_vary() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&opaque_png(16, 16)).await;
+
+    let response = render(&app, &id, "w=8&fmt=png", "image/webp").await;
+    assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+    assert!(response.headers().get(VARY).is_none());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::{png, spawn_app, TestApp};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, RANGE};
+use reqwest::StatusCode;
+
+async fn download(app: &TestApp, id: &str, headers: &[(reqwest::header::HeaderName, &str)]) -> reqwest::Response {
+    let mut request = app.client.get(app.url(&format!("/api/images/{}", id))).bearer_auth(&app.token);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    request.send().await.unwrap()
+}
+
+// The stored original and its ETag
+async fn original(app: &TestApp, id: &str) -> (Vec<u8>, String) {
+    let response = download(app, id, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+    let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+    (response.bytes().await.unwrap().to_vec(), etag)
+}
+
+#[tokio::test]
+async fn single_ranges_are_served_as_partial_content() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(32, 32)).await;
+    let (data, _) = original(&app, &id).await;
+    let len = data.len();
+
+    for (range, expected) in [
+        ("bytes=0-99".to_string(), 0..100),
+        (format!("bytes={}-", len - 10), len - 10..len),
+        ("bytes=-20".to_string(), len - 20..len),
+        (format!("bytes=100-{}", len * 2), 100..len),
+    ] {
+        let response = download(&app, &id, &[(RANGE, &range)]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{}", range);
+        assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+        assert_eq!(
+            response.headers()[CONTENT_RANGE].to_str().unwrap(),
+            format!("bytes {}-{}/{}", expected.start, expected.end - 1, len)
+        );
+        assert_eq!(response.headers()[CONTENT_LENGTH].to_str().unwrap(), (expected.end - expected.start).to_string());
+        assert_eq!(response.bytes().await.unwrap(), &data[expected], "{}", range);
+    }
+}
+
+#[tokio::test]
+async fn multiple_ranges_are_served_as_multipart_byteranges() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(32, 32)).await;
+    let (data, _) = original(&app, &id).await;
+    let len = data.len();
+
+    let response = download(&app, &id, &[(RANGE, "bytes=0-9,-5")]).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let content_type = response.headers()[CONTENT_TYPE].to_str().unwrap().to_string();
+    let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").expect(&content_type).to_string();
+    let declared: usize = response.headers()[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+    let body = response.bytes().await.unwrap().to_vec();
+    assert_eq!(body.len(), declared);
+
+    let mut expected = Vec::new();
+    for range in [0..10, len - 5..len] {
+        expected.extend_from_slice(
+            format!(
+                "\r\n--{}\r\ncontent-type: image/png\r\ncontent-range: bytes {}-{}/{}\r\n\r\n",
+                boundary, range.start, range.end - 1, len
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&data[range]);
+    }
+    expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    assert_eq!(body, expected);
+}
+
+#[tokio::test]
+async fn unsatisfiable_ranges_get_416_with_the_length() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(16, 16)).await;
+    let (data, _) = original(&app, &id).await;
+
+    let range = format!("bytes={}-", data.len());
+    let response = download(&app, &id, &[(RANGE, &range)]).await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[CONTENT_RANGE].to_str().unwrap(), format!("bytes */{}", data.len()));
+    assert!(response.bytes().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn invalid_ranges_get_the_full_response() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(16, 16)).await;
+    let (data, _) = original(&app, &id).await;
+
+    for range in ["bytes=", "bytes=abc", "items=0-9", "bytes=9-0"] {
+        let response = download(&app, &id, &[(RANGE, range)]).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", range);
+        assert_eq!(response.bytes().await.unwrap(), data, "{}", range);
+    }
+}
+
+#[tokio::test]
+async fn if_range_falls_back_to_the_full_response_when_stale() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(16, 16)).await;
+    let (data, etag) = original(&app, &id).await;
+
+    let response = download(&app, &id, &[(RANGE, "bytes=0-9"), (IF_RANGE, &etag)]).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.bytes().await.unwrap(), &data[..10]);
+
+    let response = download(&app, &id, &[(RANGE, "bytes=0-9"), (IF_RANGE, "\"stale\"")]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(CONTENT_RANGE).is_none());
+    assert_eq!(response.bytes().await.unwrap(), data);
+}
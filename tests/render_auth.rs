@@ -0,0 +1,74 @@
+mod common;
+
+use common::{png, read_json, spawn_app, JsonBody};
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn signed_render_urls_work_without_a_bearer_token() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(64, 32)).await;
+
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/render-url", id)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "query": "w=16&fmt=png", "expires_in": 600 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = read_json(response).await;
+    let url = body["url"].as_str().unwrap().to_string();
+
+    let response = app.client.get(app.url(&url)).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let rendered = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+    assert_eq!(rendered.width(), 16);
+
+    let tampered = url.replace("w=16", "w=32");
+    assert_ne!(tampered, url);
+    let response = app.client.get(app.url(&tampered)).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let unsigned = format!("/api/images/{}/render?w=16&fmt=png", id);
+    let response = app.client.get(app.url(&unsigned)).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = app.client.get(app.url(&unsigned)).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn render_urls_are_only_issued_to_the_owner() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(8, 8)).await;
+    let other = app.register("mallory").await;
+
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/render-url", id)))
+        .bearer_auth(&other)
+        .json_body(serde_json::json!({ "query": "w=4" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app
+        .client
+        .get(app.url(&format!("/api/images/{}/render?w=4", id)))
+        .bearer_auth(&other)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn other_routes_still_require_a_bearer_token() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(8, 8)).await;
+
+    let response = app.client.get(app.url(&format!("/api/images/{}", id))).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::{png, read_json, spawn_app, JsonBody};
+use reqwest::StatusCode;
+
+// Makes inserts into images fail after the object has been stored
+async fn fail_image_inserts(pool: &sqlx::SqlitePool, condition: &str) {
+    sqlx::query(&format!(
+        "CREATE TRIGGER fail_image_insert BEFORE INSERT ON images WHEN {} BEGIN SELECT RAISE(ABORT, 'insert refused'); END",
+        condition
+    ))
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn failed_derivative_inserts_leave_no_object_behind() {
+    let app = spawn_app(|_| {}).await;
+    let id = app.upload(&png(8, 8)).await;
+    let before = app.stored_objects();
+    assert_eq!(before.len(), 1);
+
+    fail_image_inserts(&app.pool, "NEW.parent_id IS NOT NULL").await;
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/transform", id)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "resize": { "width": 4 }, "format": "png", "save": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(app.stored_objects(), before);
+}
+
+#[tokio::test]
+async fn failed_upload_inserts_leave_no_object_behind() {
+    let app = spawn_app(|_| {}).await;
+    fail_image_inserts(&app.pool, "1").await;
+
+    let response = app.send_upload(&png(8, 8)).await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(app.stored_objects().is_empty());
+}
+
+async fn completed_job(app: &common::TestApp, image_id: &str) -> String {
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/jobs", image_id)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "resize": { "width": 4 }, "format": "png" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job_id = read_json(response).await["id"].as_str().unwrap().to_string();
+
+    for _ in 0..100 {
+        let response = app.client.get(app.url(&format!("/api/jobs/{}", job_id))).bearer_auth(&app.token).send().await.unwrap();
+        if read_json(response).await["status"] == "succeeded" {
+            return job_id;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("job {} did not succeed", job_id);
+}
+
+#[tokio::test]
+async fn job_results_go_with_their_image() {
+    let app = spawn_app(|_| {}).await;
+    let image_id = app.upload(&png(8, 8)).await;
+    let job_id = completed_job(&app, &image_id).await;
+    let result = |app: &common::TestApp| {
+        app.client.get(app.url(&format!("/api/jobs/{}/result", job_id))).bearer_auth(&app.token).send()
+    };
+    assert_eq!(result(&app).await.unwrap().status(), StatusCode::OK);
+    assert!(app.stored_objects().iter().any(|key| key.starts_with("jobs/")));
+
+    let request = |method: reqwest::Method, path: String| app.client.request(method, app.url(&path)).bearer_auth(&app.token).send();
+    request(reqwest::Method::DELETE, format!("/api/images/{}", image_id)).await.unwrap();
+    assert_eq!(result(&app).await.unwrap().status(), StatusCode::NOT_FOUND);
+
+    request(reqwest::Method::POST, format!("/api/trash/{}/restore", image_id)).await.unwrap();
+    assert_eq!(result(&app).await.unwrap().status(), StatusCode::OK);
+
+    request(reqwest::Method::DELETE, format!("/api/images/{}", image_id)).await.unwrap();
+    let response = request(reqwest::Method::DELETE, "/api/trash".to_string()).await.unwrap();
+    assert_eq!(read_json(response).await["purged"], 1);
+
+    assert_eq!(result(&app).await.unwrap().status(), StatusCode::NOT_FOUND);
+    assert!(app.stored_objects().is_empty(), "left behind: {:?}", app.stored_objects());
+}
+
+#[tokio::test]
+async fn deletes_leave_other_queued_files_to_the_purger() {
+    let app = spawn_app(|_| {}).await;
+    let image_id = app.upload(&png(8, 8)).await;
+
+    // Queued by an earlier deletion whose file could not be removed yet
+    std::fs::write(app.dir.join("uploads").join("stray.png"), b"stray").unwrap();
+    sqlx::query("INSERT INTO storage_deletions (storage_key) VALUES ('stray.png')")
+        .execute(&app.pool)
+        .await
+        .unwrap();
+
+    app.client.delete(app.url(&format!("/api/images/{}", image_id))).bearer_auth(&app.token).send().await.unwrap();
+    app.client.delete(app.url("/api/trash")).bearer_auth(&app.token).send().await.unwrap();
+
+    assert_eq!(app.stored_objects(), vec!["stray.png".to_string()]);
+    let queued: Vec<String> = sqlx::query_scalar("SELECT storage_key FROM storage_deletions")
+        .fetch_all(&app.pool)
+        .await
+        .unwrap();
+    assert_eq!(queued, vec!["stray.png".to_string()]);
+}
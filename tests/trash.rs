@@ -0,0 +1,73 @@
+mod common;
+
+use common::{png, spawn_app, JsonBody, TestApp};
+use image_processing_service::core::config::ParentDeletePolicy;
+use reqwest::header::LOCATION;
+use reqwest::StatusCode;
+
+// Saves a resized copy of the image and returns the derivative's id
+async fn derive(app: &TestApp, id: &str) -> String {
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/transform", id)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "resize": { "width": 4 }, "format": "png", "save": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let location = response.headers()[LOCATION].to_str().unwrap();
+    location.trim_start_matches("/api/images/").to_string()
+}
+
+async fn delete(app: &TestApp, id: &str) {
+    let response = app.client.delete(app.url(&format!("/api/images/{}", id))).bearer_auth(&app.token).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+async fn restore(app: &TestApp, id: &str) -> StatusCode {
+    app.client.post(app.url(&format!("/api/trash/{}/restore", id))).bearer_auth(&app.token).send().await.unwrap().status()
+}
+
+async fn is_live(app: &TestApp, id: &str) -> bool {
+    let response = app.client.get(app.url(&format!("/api/images/{}", id))).bearer_auth(&app.token).send().await.unwrap();
+    response.status() == StatusCode::OK
+}
+
+#[tokio::test]
+async fn restoring_a_cascade_brings_back_its_children() {
+    let app = spawn_app(|settings| settings.parent_delete_policy = ParentDeletePolicy::Cascade).await;
+    let parent = app.upload(&png(8, 8)).await;
+    let child = derive(&app, &parent).await;
+    let grandchild = derive(&app, &child).await;
+
+    delete(&app, &parent).await;
+    assert!(!is_live(&app, &child).await);
+    assert!(!is_live(&app, &grandchild).await);
+
+    assert!(restore(&app, &parent).await.is_success());
+    assert!(is_live(&app, &parent).await);
+    assert!(is_live(&app, &child).await);
+    assert!(is_live(&app, &grandchild).await);
+}
+
+#[tokio::test]
+async fn separately_deleted_children_stay_in_the_trash() {
+    let app = spawn_app(|settings| settings.parent_delete_policy = ParentDeletePolicy::Cascade).await;
+    let parent = app.upload(&png(8, 8)).await;
+    let child = derive(&app, &parent).await;
+    let sibling = derive(&app, &parent).await;
+
+    // Within the same second, so only the recorded batch tells the two deletes apart
+    delete(&app, &child).await;
+    delete(&app, &parent).await;
+
+    assert!(restore(&app, &parent).await.is_success());
+    assert!(is_live(&app, &parent).await);
+    assert!(is_live(&app, &sibling).await);
+    assert!(!is_live(&app, &child).await);
+
+    assert!(restore(&app, &child).await.is_success());
+    assert!(is_live(&app, &child).await);
+    assert_eq!(restore(&app, &child).await, StatusCode::CONFLICT);
+}
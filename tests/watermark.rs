@@ -0,0 +1,49 @@
+mod common;
+
+use common::{png, read_json, spawn_app, JsonBody};
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn scaled_overlays_are_held_to_the_output_limits() {
+    let app = spawn_app(|_| {}).await;
+    let base = app.upload(&png(8000, 1)).await;
+    // Scaled to the base width, this overlay would be 8000x131072000
+    let overlay = app.upload(&png(1, 16384)).await;
+
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/transform", base)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({
+            "watermark": { "image": { "image_id": overlay, "scale": 1.0 } },
+            "format": "png"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = read_json(response).await;
+    assert!(body.to_string().contains("8000x131072000"), "{}", body);
+}
+
+#[tokio::test]
+async fn overlay_scales_must_be_a_positive_fraction() {
+    let app = spawn_app(|_| {}).await;
+    let base = app.upload(&png(16, 16)).await;
+    let overlay = app.upload(&png(4, 4)).await;
+
+    for scale in [0.0, -0.5, 1.5, 1e39] {
+        let response = app
+            .client
+            .post(app.url(&format!("/api/images/{}/transform", base)))
+            .bearer_auth(&app.token)
+            .json_body(serde_json::json!({
+                "watermark": { "image": { "image_id": overlay, "scale": scale } },
+                "format": "png"
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "scale {} should be refused", scale);
+    }
+}
@@ -0,0 +1,171 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode as AxumStatus};
+use axum::routing::post;
+use axum::Router;
+use common::{png, read_json, spawn_app, JsonBody};
+use image_processing_service::application::webhook_service::signature;
+use reqwest::StatusCode;
+
+const SECRET: &str = "a-test-secret-of-enough-length";
+
+type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+// Answers 500 to the first delivery and 200 to the rest, recording every request
+async fn spawn_receiver() -> (String, Received) {
+    let received: Received = Arc::default();
+    let app = Router::new()
+        .route(
+            "/hook",
+            post(|State(received): State<Received>, headers: HeaderMap, body: String| async move {
+                let mut received = received.lock().unwrap();
+                received.push((headers, body));
+                if received.len() == 1 { AxumStatus::INTERNAL_SERVER_ERROR } else { AxumStatus::OK }
+            }),
+        )
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, received)
+}
+
+#[tokio::test]
+async fn internal_hosts_are_refused_by_default() {
+    let app = spawn_app(|_| {}).await;
+
+    for url in ["http://127.0.0.1:9/hook", "http://localhost/hook", "http://169.254.169.254/latest", "http://[::1]/", "http://10.0.0.1/"] {
+        let response = app
+            .client
+            .post(app.url("/api/webhooks"))
+            .bearer_auth(&app.token)
+            .json_body(serde_json::json!({ "url": url, "events": ["*"] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{} should be refused", url);
+    }
+}
+
+#[tokio::test]
+async fn deliveries_are_signed_retried_and_logged() {
+    let app = spawn_app(|settings| {
+        settings.webhook_allow_private_hosts = true;
+        settings.webhook_retry_base_secs = 0;
+    })
+    .await;
+    let (url, received) = spawn_receiver().await;
+
+    let response = app
+        .client
+        .post(app.url("/api/webhooks"))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "url": url, "secret": SECRET, "events": ["image.uploaded"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let webhook_id = read_json(response).await["id"].as_str().unwrap().to_string();
+
+    let image_id = app.upload(&png(8, 8)).await;
+
+    for _ in 0..100 {
+        if received.lock().unwrap().len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 2, "expected the failed delivery and its retry");
+
+    for (headers, body) in &received {
+        let header = |name: &str| headers[name].to_str().unwrap().to_string();
+        let timestamp: i64 = header("x-webhook-timestamp").parse().unwrap();
+        assert_eq!(header("x-webhook-signature"), format!("sha256={}", signature(SECRET, timestamp, body)));
+        assert_eq!(header("x-webhook-event"), "image.uploaded");
+        assert_eq!(header("x-webhook-id"), webhook_id);
+
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["data"]["image_id"], image_id.as_str());
+    }
+    // The retry is the same delivery of the same event
+    assert_eq!(received[0].0["x-webhook-delivery"], received[1].0["x-webhook-delivery"]);
+    assert_eq!(received[0].1, received[1].1);
+
+    // The success is recorded just after the receiver answers
+    let mut deliveries = serde_json::Value::Null;
+    for _ in 0..50 {
+        let response = app
+            .client
+            .get(app.url(&format!("/api/webhooks/{}/deliveries", webhook_id)))
+            .bearer_auth(&app.token)
+            .send()
+            .await
+            .unwrap();
+        deliveries = read_json(response).await;
+        if deliveries[0]["status"] == "succeeded" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let deliveries = deliveries.as_array().unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0]["status"], "succeeded");
+    assert_eq!(deliveries[0]["attempts"], 2);
+    assert_eq!(deliveries[0]["response_status"], 200);
+    assert_eq!(deliveries[0]["event"], "image.uploaded");
+    assert_eq!(deliveries[0]["payload"]["data"]["image_id"], image_id.as_str());
+}
+
+#[tokio::test]
+async fn only_explicit_transforms_are_announced() {
+    let app = spawn_app(|settings| settings.webhook_allow_private_hosts = true).await;
+    let image_id = app.upload(&png(8, 8)).await;
+
+    // Nothing listens on the discard port; only the queued deliveries matter here
+    let response = app
+        .client
+        .post(app.url("/api/webhooks"))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "url": "http://127.0.0.1:9/hook", "events": ["image.transformed"] }))
+        .send()
+        .await
+        .unwrap();
+    let webhook_id = read_json(response).await["id"].as_str().unwrap().to_string();
+    let deliveries = || async {
+        let response = app
+            .client
+            .get(app.url(&format!("/api/webhooks/{}/deliveries", webhook_id)))
+            .bearer_auth(&app.token)
+            .send()
+            .await
+            .unwrap();
+        read_json(response).await.as_array().unwrap().len()
+    };
+
+    for _ in 0..2 {
+        let response = app
+            .client
+            .get(app.url(&format!("/api/images/{}/render?w=4&fmt=png", image_id)))
+            .bearer_auth(&app.token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    assert_eq!(deliveries().await, 0);
+
+    let response = app
+        .client
+        .post(app.url(&format!("/api/images/{}/transform", image_id)))
+        .bearer_auth(&app.token)
+        .json_body(serde_json::json!({ "resize": { "width": 4 }, "format": "png" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(deliveries().await, 1);
+}